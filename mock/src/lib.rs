@@ -0,0 +1,320 @@
+//! A stand-in for the Xserver panel that serves the recorded pages in
+//! `fixtures/`, so the login and extend flows can be exercised without an
+//! account or network access.
+//!
+//! Which page is answered is decided by the data sent, see the constants
+//! below. Everything lives under `/xapanel/`, like on the real panel.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// Logs in directly.
+pub const EMAIL: &str = "user@example.com";
+/// Logs in after two-way authentication with [`AUTH_CODE`].
+pub const TWO_FACTOR_EMAIL: &str = "2fa@example.com";
+/// Gets an unknown page after logging in.
+pub const MAINTENANCE_EMAIL: &str = "maintenance@example.com";
+pub const PASSWORD: &str = "password";
+/// Code accepted by the two-way authentication form.
+pub const AUTH_CODE: &str = "123456";
+
+/// Extended right away.
+pub const VPS_SUCCESS: &str = "1001";
+/// Renewal window not open yet, told after submitting the extend form.
+pub const VPS_UNAVAILABLE: &str = "1002";
+/// Asks for an image captcha and a Turnstile challenge.
+pub const VPS_CAPTCHA: &str = "1003";
+/// Asks for an image captcha only.
+pub const VPS_IMAGE_CAPTCHA: &str = "1004";
+/// Asks for a captcha but has no form to answer it.
+pub const VPS_CAPTCHA_NO_FORM: &str = "1005";
+/// Answers the extend form with an unknown page.
+pub const VPS_UNKNOWN: &str = "1006";
+/// Extend page without a form.
+pub const VPS_NO_FORM: &str = "1007";
+/// Renewal window not open yet, told on the extend page.
+pub const VPS_CLOSED: &str = "1008";
+/// VPS ids listed under the free VPS heading, in page order.
+pub const FREE_VPS: [&str; 8] = [
+    VPS_SUCCESS,
+    VPS_UNAVAILABLE,
+    VPS_CAPTCHA,
+    VPS_IMAGE_CAPTCHA,
+    VPS_CAPTCHA_NO_FORM,
+    VPS_UNKNOWN,
+    VPS_NO_FORM,
+    VPS_CLOSED,
+];
+
+/// Captcha answer completing the renewal.
+pub const CAPTCHA_SUCCESS: &str = "1234";
+/// Captcha answer completing it with the generic message.
+pub const CAPTCHA_SUCCESS_GENERIC: &str = "2222";
+/// Captcha answer after which the panel says the window is not open.
+pub const CAPTCHA_UNAVAILABLE: &str = "3333";
+/// Captcha answer answered with an unknown page.
+pub const CAPTCHA_UNKNOWN: &str = "4444";
+// Any other answer is rejected.
+
+const SESSION_COOKIE: &str = "XSESSION";
+const SESSION: &str = "mock-session";
+const PREFIX: &str = "/xapanel/";
+
+mod fixtures {
+    pub const LOGIN: &str = include_str!("../fixtures/login.html");
+    pub const TWO_FACTOR: &str = include_str!("../fixtures/two_factor.html");
+    pub const TWO_FACTOR_CODE: &str = include_str!("../fixtures/two_factor_code.html");
+    pub const INDEX: &str = include_str!("../fixtures/index.html");
+    pub const EXTEND: &str = include_str!("../fixtures/extend.html");
+    pub const NO_FORM: &str = include_str!("../fixtures/no_form.html");
+    pub const EXTEND_UNAVAILABLE: &str = include_str!("../fixtures/extend_unavailable.html");
+    pub const EXTEND_DONE: &str = include_str!("../fixtures/extend_done.html");
+    pub const CAPTCHA: &str = include_str!("../fixtures/captcha.html");
+    pub const CAPTCHA_IMAGE: &str = include_str!("../fixtures/captcha_image.html");
+    pub const CAPTCHA_NO_FORM: &str = include_str!("../fixtures/captcha_no_form.html");
+    pub const CAPTCHA_WRONG: &str = include_str!("../fixtures/captcha_wrong.html");
+    pub const CAPTCHA_DONE: &str = include_str!("../fixtures/captcha_done.html");
+    pub const UNKNOWN: &str = include_str!("../fixtures/unknown.html");
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    form: HashMap<String, String>,
+    logged_in: bool,
+}
+
+enum Response {
+    Page(String),
+    Redirect { path: &'static str, login: bool },
+    NotFound,
+}
+
+impl Response {
+    fn page(html: &str) -> Self {
+        Response::Page(html.to_string())
+    }
+
+    fn redirect(path: &'static str) -> Self {
+        Response::Redirect { path, login: false }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let (status, headers, body) = match self {
+            Response::Page(body) => (
+                "200 OK",
+                "Content-Type: text/html; charset=UTF-8\r\n".to_string(),
+                body,
+            ),
+            Response::Redirect { path, login } => {
+                let mut headers = format!("Location: {}{}\r\n", PREFIX, path);
+                if login {
+                    headers.push_str(&format!(
+                        "Set-Cookie: {}={}; Path=/\r\n",
+                        SESSION_COOKIE, SESSION
+                    ));
+                }
+                ("302 Found", headers, String::new())
+            }
+            Response::NotFound => ("404 Not Found", String::new(), "Not Found".to_string()),
+        };
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+}
+
+fn parse_pairs(s: &str) -> HashMap<String, String> {
+    url::form_urlencoded::parse(s.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn route(req: &Request) -> Response {
+    let field = |name: &str| req.form.get(name).map(String::as_str).unwrap_or_default();
+    let id = || {
+        req.query
+            .get("id_vps")
+            .or_else(|| req.form.get("id_vps"))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let session = |res: Response| {
+        if req.logged_in {
+            res
+        } else {
+            Response::redirect("login/xvps/")
+        }
+    };
+
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "login/xvps/") => Response::page(fixtures::LOGIN),
+        ("POST", "login/xvps/login") => {
+            let email = field("memberid");
+            if email == MAINTENANCE_EMAIL {
+                Response::redirect("maintenance")
+            } else if field("user_password") != PASSWORD {
+                Response::redirect("login/xvps/?error=1")
+            } else if email == EMAIL {
+                Response::Redirect {
+                    path: "xvps/index",
+                    login: true,
+                }
+            } else if email == TWO_FACTOR_EMAIL {
+                Response::redirect("myaccount/loginauth/index")
+            } else {
+                Response::redirect("login/xvps/?error=1")
+            }
+        }
+        ("GET", "maintenance") => Response::page(fixtures::UNKNOWN),
+        ("GET", "myaccount/loginauth/index") => {
+            Response::Page(fixtures::TWO_FACTOR.replace("{{EMAIL}}", TWO_FACTOR_EMAIL))
+        }
+        ("POST", "myaccount/loginauth/select") if field("auth_type") == "auth_mail" => {
+            Response::page(fixtures::TWO_FACTOR_CODE)
+        }
+        ("POST", "myaccount/loginauth/do") => {
+            if field("auth_code") == AUTH_CODE {
+                Response::Redirect {
+                    path: "xvps/index",
+                    login: true,
+                }
+            } else {
+                Response::redirect("myaccount/loginauth/index")
+            }
+        }
+        ("GET", "xvps/index") => session(Response::page(fixtures::INDEX)),
+        ("GET", "xvps/server/freevps/extend/index") => session(match id().as_str() {
+            VPS_NO_FORM => Response::page(fixtures::NO_FORM),
+            VPS_CLOSED => Response::page(fixtures::EXTEND_UNAVAILABLE),
+            id if FREE_VPS.contains(&id) => Response::Page(fixtures::EXTEND.replace("{{ID}}", id)),
+            _ => Response::NotFound,
+        }),
+        ("POST", "xvps/server/freevps/extend/conf") => {
+            let id = id();
+            session(match id.as_str() {
+                VPS_SUCCESS => Response::page(fixtures::EXTEND_DONE),
+                VPS_UNAVAILABLE => Response::page(fixtures::EXTEND_UNAVAILABLE),
+                VPS_CAPTCHA => Response::Page(fixtures::CAPTCHA.replace("{{ID}}", &id)),
+                VPS_IMAGE_CAPTCHA => Response::Page(fixtures::CAPTCHA_IMAGE.replace("{{ID}}", &id)),
+                VPS_CAPTCHA_NO_FORM => Response::page(fixtures::CAPTCHA_NO_FORM),
+                _ => Response::page(fixtures::UNKNOWN),
+            })
+        }
+        ("POST", "xvps/server/freevps/extend/do") => session(match field("auth_code") {
+            CAPTCHA_SUCCESS => Response::page(fixtures::CAPTCHA_DONE),
+            CAPTCHA_SUCCESS_GENERIC => Response::page(fixtures::EXTEND_DONE),
+            CAPTCHA_UNAVAILABLE => Response::page(fixtures::EXTEND_UNAVAILABLE),
+            CAPTCHA_UNKNOWN => Response::page(fixtures::UNKNOWN),
+            _ => Response::page(fixtures::CAPTCHA_WRONG),
+        }),
+        _ => Response::NotFound,
+    }
+}
+
+/// A running mock panel. It stops with the runtime it was started on.
+pub struct MockPanel {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockPanel {
+    /// Starts the mock on a free local port.
+    pub async fn start() -> std::io::Result<Self> {
+        Self::bind("127.0.0.1:0").await
+    }
+
+    pub async fn bind(addr: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let panel = MockPanel {
+            addr: listener.local_addr()?,
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let requests = panel.requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(socket, requests).await {
+                        eprintln!("Mock panel: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(panel)
+    }
+
+    /// Base URL to use in place of the panel, e.g. as `XRENEW_PANEL_URL`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}{}", self.addr, PREFIX)
+    }
+
+    /// Requests served so far, as `METHOD path?query` with the path relative
+    /// to the base URL.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(socket: TcpStream, requests: Arc<Mutex<Vec<String>>>) -> std::io::Result<()> {
+    let mut socket = BufReader::new(socket);
+    let mut line = String::new();
+    socket.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut length = 0;
+    let mut logged_in = false;
+    loop {
+        line.clear();
+        if socket.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.parse().unwrap_or(0),
+            "cookie" => {
+                logged_in |= value
+                    .split(';')
+                    .any(|c| c.trim() == format!("{}={}", SESSION_COOKIE, SESSION));
+            }
+            _ => {}
+        }
+    }
+    let mut body = vec![0; length];
+    socket.read_exact(&mut body).await?;
+
+    let target = target.strip_prefix(PREFIX).unwrap_or(&target).to_string();
+    requests
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", method, target));
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let req = Request {
+        method,
+        path: path.to_string(),
+        query: parse_pairs(query),
+        form: parse_pairs(&String::from_utf8_lossy(&body)),
+        logged_in,
+    };
+    socket.write_all(&route(&req).into_bytes()).await?;
+    socket.shutdown().await
+}
@@ -0,0 +1,18 @@
+use xrenew_mock::MockPanel;
+
+#[tokio::main]
+async fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let panel = match MockPanel::bind(&addr).await {
+        Ok(panel) => panel,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    println!("Mock panel listening on {}", panel.base_url());
+    println!("Run xrenew with XRENEW_PANEL_URL={}", panel.base_url());
+    tokio::signal::ctrl_c().await.ok();
+}
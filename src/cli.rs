@@ -1,40 +1,302 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    config::parse_attempts,
+    data::value::parse_profile,
+    external::{NotifierConfig, SolverKind},
+    history::Outcome,
+    retry::RetryClass,
+    schedule::{Cron, Plan, parse_lead, parse_times},
+    task::Backend,
+};
 
 #[derive(Parser)]
 #[command(author, version, about)]
 pub struct Cli {
+    /// Profile to operate on (defaults to "default")
+    #[arg(long, global = true, value_parser = parse_profile)]
+    pub profile: Option<String>,
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Interactive login and extend VPS
-    Login,
+    /// Save the account, log in and extend VPS
+    Login(LoginArgs),
     /// Extend VPS without interaction
     Extend {
         /// Run from systemd timer
         #[arg(long)]
         auto: bool,
+        /// Extend every configured profile
+        #[arg(long, conflicts_with = "profile")]
+        all: bool,
+        /// Only extend the VPS with this id (repeatable)
+        #[arg(long = "vps", value_name = "ID")]
+        vps: Vec<String>,
+        /// Log in and inspect the extend form without submitting it
+        #[arg(long, conflicts_with = "auto")]
+        dry_run: bool,
     },
     /// Show stored account and run logs
     Status,
-    /// Enable daily automatic extension
-    Enable,
+    /// Enable automatic extension (twice a day unless a schedule is given)
+    Enable(EnableArgs),
+    /// Run automatic extension on a schedule in the foreground (for containers)
+    Daemon,
     /// Disable automatic extension
     Disable,
-    /// Delete saved data
+    /// List configured profiles
+    Profiles,
+    /// Delete saved data (only the given profile with --profile)
     Clear,
-    /// Set TwoCaptcha API key
-    Captcha { key: String },
-    /// Set Discord webhook URL
+    /// Set captcha solver API keys and priority (shows the setting without arguments)
+    Captcha {
+        /// API key for the selected service
+        key: Option<String>,
+        /// Service the key belongs to
+        #[arg(long, value_enum, default_value_t = SolverKind::TwoCaptcha)]
+        service: SolverKind,
+        /// Solvers to try in priority order, comma separated
+        #[arg(long, value_enum, value_delimiter = ',')]
+        order: Vec<SolverKind>,
+    },
+    /// Train or evaluate the offline captcha OCR
+    Ocr {
+        #[command(subcommand)]
+        action: OcrAction,
+    },
+    /// Manage notification channels
+    Notify {
+        #[command(subcommand)]
+        action: NotifyAction,
+    },
+    /// Add a Discord webhook (same as `notify add discord <URL>`)
+    #[command(hide = true)]
     Webhook { url: String },
+    /// Read two-factor codes from an IMAP mailbox (shows the setting without a host)
+    Imap {
+        /// IMAP server host
+        host: Option<String>,
+        /// IMAP server port
+        #[arg(long, default_value_t = 993)]
+        port: u16,
+        /// Login user (defaults to the account email)
+        #[arg(long)]
+        user: Option<String>,
+        /// Mailbox to watch
+        #[arg(long, default_value = "INBOX")]
+        mailbox: String,
+        /// Only consider mails whose sender contains this text
+        #[arg(long, default_value = "xserver")]
+        from: String,
+        /// Connect without TLS (local test servers only)
+        #[arg(long)]
+        plain: bool,
+        /// Remove the IMAP setting
+        #[arg(long, conflicts_with = "host")]
+        remove: bool,
+    },
+    /// Configure retries of failed steps (shows the setting without arguments)
+    Retry {
+        /// Tries per step, including the first one
+        #[arg(long, value_parser = parse_attempts)]
+        attempts: Option<u32>,
+        /// Seconds to wait before the first retry (doubled each time)
+        #[arg(long, value_name = "SECS")]
+        backoff: Option<u64>,
+        /// Upper limit of the wait in seconds
+        #[arg(long, value_name = "SECS")]
+        max_backoff: Option<u64>,
+        /// Failure kinds to retry, comma separated (empty to disable)
+        #[arg(long, value_enum, value_delimiter = ',', num_args = 0..)]
+        on: Option<Vec<RetryClass>>,
+        /// Go back to the default settings
+        #[arg(long, conflicts_with_all = ["attempts", "backoff", "max_backoff", "on"])]
+        reset: bool,
+    },
+    /// Configure remote log shipping (shows the setting without arguments)
+    Telemetry {
+        /// Turn log shipping on or off
+        #[arg(value_enum)]
+        state: Option<Switch>,
+        /// Collector URL that receives the log lines
+        #[arg(long, conflicts_with = "reset_endpoint")]
+        endpoint: Option<String>,
+        /// Go back to the default collector
+        #[arg(long)]
+        reset_endpoint: bool,
+    },
+    /// Show, check or edit the configuration file (config.toml)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
     /// Update xrenew to the latest version
     Update {
         /// Run from systemd timer
         #[arg(long)]
         auto: bool,
     },
+    /// Show past runs, oldest first
+    History {
+        /// Only runs with this outcome
+        #[arg(long, value_enum)]
+        outcome: Option<Outcome>,
+        /// Only runs of this VPS ID
+        #[arg(long, value_name = "ID")]
+        vps: Option<String>,
+        /// Only runs within the last N days
+        #[arg(long, value_name = "N")]
+        days: Option<i64>,
+        /// Show at most this many of the newest runs
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Include every profile
+        #[arg(long, conflicts_with = "profile")]
+        all: bool,
+        /// Print JSON Lines instead of a table
+        #[arg(long)]
+        json: bool,
+    },
     /// Reload automatic extension timer
     Refresh,
+    /// Check that automatic extension works, with a fix for each problem
+    Doctor,
+    /// Re-encrypt stored credentials with a new key (a fresh key file by default)
+    Rekey {
+        /// Derive the key from a passphrase (XRENEW_NEW_PASSPHRASE or prompt)
+        #[arg(long, conflicts_with = "key_file")]
+        passphrase: bool,
+        /// Use this key file, creating it with a random key if missing
+        #[arg(long, value_name = "PATH")]
+        key_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+pub struct LoginArgs {
+    /// Account email (or XRENEW_EMAIL); asked for when missing
+    #[arg(long)]
+    pub email: Option<String>,
+    /// Read the password from standard input
+    #[arg(long, conflicts_with = "password_file")]
+    pub password_stdin: bool,
+    /// Read the password from the first line of this file
+    #[arg(long, value_name = "PATH")]
+    pub password_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct EnableArgs {
+    #[command(flatten)]
+    pub plan: PlanArgs,
+    /// Where to register the runs (detected when omitted)
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+}
+
+/// A new schedule, stored and used from then on.
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct PlanArgs {
+    /// Cron expression: minute hour day month weekday, e.g. "0 9,21 * * *"
+    #[arg(long, value_name = "EXPR", value_parser = |s: &str| Cron::parse(s).map(Plan::Cron))]
+    pub schedule: Option<Plan>,
+    /// Local times of day, e.g. 09:00,21:00
+    #[arg(long, value_name = "HH:MM,...", value_parser = |s: &str| parse_times(s).map(Plan::Times))]
+    pub times: Option<Plan>,
+    /// Run this long before the earliest VPS expiry, e.g. 20h (at most 48h)
+    #[arg(long, value_name = "DURATION", value_parser = |s: &str| parse_lead(s).map(Plan::BeforeExpiry))]
+    pub before_expiry: Option<Plan>,
+}
+
+impl PlanArgs {
+    pub fn plan(self) -> Option<Plan> {
+        self.schedule.or(self.times).or(self.before_expiry)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum OcrAction {
+    /// Learn digit templates from labelled images (`<answer>_*.png`)
+    Train {
+        /// Corpus directory (defaults to images collected from solved captchas)
+        dir: Option<PathBuf>,
+    },
+    /// Measure recognition accuracy against labelled images
+    Eval {
+        /// Corpus directory (defaults to images collected from solved captchas)
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NotifyAction {
+    /// Add a notification channel
+    Add {
+        #[command(subcommand)]
+        channel: NotifierConfig,
+    },
+    /// List notification channels
+    List,
+    /// Remove a notification channel by its number in `notify list`
+    Remove { index: usize },
+    /// Send a test message (to every channel without a number)
+    Test { index: Option<usize> },
+    /// Choose which runs send a notification (shows the policy without options)
+    Policy(PolicyArgs),
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Show the effective settings and where each value comes from
+    Show,
+    /// Check config.toml and the XRENEW_* environment variables
+    Validate,
+    /// Open config.toml in $VISUAL or $EDITOR, creating it from a template
+    Edit,
+}
+
+#[derive(Args)]
+pub struct PolicyArgs {
+    /// Notify on successful runs
+    #[arg(long, value_enum)]
+    pub success: Option<Switch>,
+    /// Notify on failed runs
+    #[arg(long, value_enum)]
+    pub failure: Option<Switch>,
+    /// Notify failures only once this many runs in a row have failed
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub failures_after: Option<u32>,
+    /// Notify on skipped runs
+    #[arg(long, value_enum)]
+    pub skip: Option<Switch>,
+    /// Notify on the first success after failures
+    #[arg(long, value_enum)]
+    pub recovery: Option<Switch>,
+    /// Warn on every run while a VPS expires within this many hours
+    #[arg(long, value_name = "HOURS", conflicts_with = "no_expiry")]
+    pub expiry_within: Option<u32>,
+    /// Turn off expiry warnings
+    #[arg(long)]
+    pub no_expiry: bool,
+    /// Attach the page saved on a layout error to the notification
+    #[arg(long, value_enum)]
+    pub attach_snapshot: Option<Switch>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+impl Switch {
+    pub fn enabled(self) -> bool {
+        matches!(self, Switch::On)
+    }
 }
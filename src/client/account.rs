@@ -1,7 +1,63 @@
+use std::sync::OnceLock;
+
 use bincode::{Decode, Encode};
 
+use crate::{
+    config::{self, Source},
+    data::value::DEFAULT_PROFILE,
+};
+
+pub const EMAIL_VAR: &str = "XRENEW_EMAIL";
+pub const PASSWORD_VAR: &str = "XRENEW_PASSWORD";
+
 #[derive(Clone, Debug, Encode, Decode)]
 pub struct Account {
     pub email: String,
     pub password: String,
 }
+
+impl Account {
+    /// Credentials from `XRENEW_EMAIL` and `XRENEW_PASSWORD`, which are
+    /// never stored.
+    pub fn from_env() -> Option<Account> {
+        let email = std::env::var(EMAIL_VAR).ok().filter(|e| !e.is_empty())?;
+        let password = std::env::var(PASSWORD_VAR).ok()?;
+        Some(Account { email, password })
+    }
+}
+
+static SELECTED: OnceLock<String> = OnceLock::new();
+
+/// Sets the profile chosen on the command line, the one the environment
+/// credentials belong to.
+pub fn select_profile(profile: &str) {
+    SELECTED.set(profile.to_string()).ok();
+}
+
+pub fn is_selected(profile: &str) -> bool {
+    SELECTED.get().map_or(DEFAULT_PROFILE, String::as_str) == profile
+}
+
+/// Account to log in with: the environment credentials for the selected
+/// profile, otherwise the ones from config.toml or the stored ones.
+pub fn account(profile: &str) -> Option<Account> {
+    config::account(profile).0
+}
+
+/// Whether `account(profile)` comes from the environment.
+pub fn account_from_env(profile: &str) -> bool {
+    matches!(config::account(profile).1, Source::Env(_))
+}
+
+/// Profiles that have an account, including the selected one when it only
+/// has environment credentials.
+pub fn account_profiles() -> Vec<String> {
+    let mut profiles = config::profiles();
+    if let Some(selected) = SELECTED.get()
+        && !profiles.contains(selected)
+    {
+        profiles.push(selected.clone());
+    }
+    profiles.retain(|p| account(p).is_some());
+    profiles
+}
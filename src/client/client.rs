@@ -1,28 +1,37 @@
-use std::sync::{Arc, LazyLock};
+use std::sync::Arc;
 
 use cookie_store::CookieStore;
 use reqwest_cookie_store::CookieStoreMutex;
 use ua_generator::ua::spoof_ua;
+use url::Url;
 
-use crate::data::value::{get_cookie, get_ua, set_cookie, set_ua};
+use super::account;
+use crate::data::value::{
+    get_account, get_cookie, get_cookie_email, get_ua, set_cookie, set_cookie_email, set_ua,
+};
+use crate::endpoint::ENDPOINTS;
 
 pub struct Client {
     cookie_store: Arc<CookieStoreMutex>,
     pub client: reqwest::Client,
+    /// Base URL of the panel, ending with a slash.
+    pub(super) panel: Url,
 }
 
 pub fn create_client(ua: String, cookie: Option<String>) -> Client {
     let cookie_store = match cookie {
         Some(cookie) => {
-            let store = CookieStore::default();
-            let mut reader = std::io::Cursor::new(cookie);
-            cookie_store::serde::json::load(&mut reader).expect("Failed to load cookie store");
+            let reader = std::io::Cursor::new(cookie);
+            let store = cookie_store::serde::json::load_all(reader).unwrap_or_else(|e| {
+                eprintln!("Failed to load saved cookies: {}", e);
+                CookieStore::default()
+            });
             Arc::new(CookieStoreMutex::new(store))
         }
         None => Arc::new(CookieStoreMutex::new(CookieStore::default())),
     };
     let ua = ua.clone();
-    let client = reqwest::Client::builder()
+    let client = crate::config::http_builder()
         .cookie_provider(cookie_store.clone())
         .default_headers({
             let mut headers = reqwest::header::HeaderMap::new();
@@ -39,36 +48,62 @@ pub fn create_client(ua: String, cookie: Option<String>) -> Client {
     Client {
         cookie_store,
         client,
+        panel: ENDPOINTS.panel.clone(),
     }
 }
 
 impl Client {
+    /// URL of a panel page, `path` being relative to the panel base URL.
+    pub(super) fn page(&self, path: &str) -> String {
+        self.panel
+            .join(path)
+            .expect("Panel paths are relative")
+            .to_string()
+    }
+
     pub fn get_cookie(&self) -> String {
         let store = self
             .cookie_store
             .lock()
             .expect("Failed to lock cookie store");
         let mut writer = Vec::new();
-        cookie_store::serde::json::save(&*store, &mut writer)
+        // The panel session cookie has no expiry, so keep non-persistent
+        // cookies too or the session can never be resumed.
+        cookie_store::serde::json::save_incl_expired_and_nonpersistent(&store, &mut writer)
             .expect("Failed to save cookie store to JSON");
         String::from_utf8(writer).expect("Failed to convert cookie store to string")
     }
 }
 
-pub static DEFAULT_CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    let ua = match get_ua() {
+pub fn load_client(profile: &str) -> Client {
+    let ua = match get_ua(profile) {
         Some(ua) => ua,
         None => {
             let ua = spoof_ua().to_string();
-            set_ua(&ua);
+            set_ua(profile, &ua);
             ua
         }
     };
-    let cookie = get_cookie();
-    create_client(ua, cookie)
-});
+    create_client(ua, session_cookie(profile))
+}
 
-pub fn save_default_client() {
-    let cookie = DEFAULT_CLIENT.get_cookie();
-    set_cookie(&cookie);
+/// The saved session, unless it was issued to another account than the one
+/// that logs in now, e.g. one given with XRENEW_EMAIL. Sessions saved
+/// without an email belong to the stored account.
+pub(super) fn session_cookie(profile: &str) -> Option<String> {
+    let email = account(profile)?.email;
+    let owner = get_cookie_email(profile).or_else(|| get_account(profile).map(|a| a.email))?;
+    if owner == email {
+        get_cookie(profile)
+    } else {
+        None
+    }
+}
+
+pub fn save_client(profile: &str, client: &Client) {
+    let cookie = client.get_cookie();
+    set_cookie(profile, &cookie);
+    if let Some(account) = account(profile) {
+        set_cookie_email(profile, &account.email);
+    }
 }
@@ -3,17 +3,14 @@ use std::collections::HashMap;
 use super::Account;
 use super::Client;
 use super::form::{FieldType, Form, classify_field, extract_forms, get_mailaddress};
-
-const LOGIN_URL: &str = "https://secure.xserver.ne.jp/xapanel/login/xvps/";
-const TOP_PAGE: &str = "https://secure.xserver.ne.jp/xapanel/xvps/index";
-const AUTH_URL: &str = "https://secure.xserver.ne.jp/xapanel/myaccount/loginauth/index";
+use super::snapshot::{Page, ParseFailure, form_failure};
 
 #[derive(Debug, thiserror::Error)]
 pub enum LoginError {
     #[error("Failed to send login request: {0}")]
     RequestError(#[from] reqwest::Error),
     #[error("Failed to parse login response: {0}")]
-    ParseError(&'static str),
+    ParseError(#[from] ParseFailure),
 }
 
 #[derive(Debug)]
@@ -26,17 +23,47 @@ pub enum LoginStatus {
 pub type LoginResult<T> = Result<T, LoginError>;
 
 impl Client {
+    fn login_url(&self) -> String {
+        self.page("login/xvps/")
+    }
+
+    pub(super) fn top_page(&self) -> String {
+        self.page("xvps/index")
+    }
+
+    fn auth_url(&self) -> String {
+        self.page("myaccount/loginauth/index")
+    }
+
+    /// Checks whether the restored session is still logged in. Returns the
+    /// VPS list page when it is, or `None` when the panel redirects to the
+    /// login page.
+    pub async fn check_session(&self) -> LoginResult<Option<String>> {
+        let res = self.client.get(self.top_page()).send().await?;
+        let page = Page::read(res.error_for_status()?).await?;
+
+        if page.url.as_str().starts_with(&self.login_url()) {
+            return Ok(None);
+        }
+
+        if page.url.as_str().starts_with(&self.top_page()) {
+            return Ok(Some(page.text));
+        }
+
+        Err(page.fail("Unknown session status").into())
+    }
+
     pub async fn login_page(&self) -> LoginResult<Form> {
-        let res = self.client.get(LOGIN_URL).send().await?;
-        let res = res.error_for_status()?;
-        let text = res.text().await?;
-        let url = LOGIN_URL.parse::<url::Url>().expect("Invalid login URL");
-        for form in extract_forms(&text, Some(&url)).into_iter() {
-            if form.action.as_ref().map_or(false, |a| a.contains("login")) {
+        let login_url = self.login_url();
+        let res = self.client.get(&login_url).send().await?;
+        let page = Page::read(res.error_for_status()?).await?;
+        let url = login_url.parse::<url::Url>().expect("Invalid login URL");
+        for form in extract_forms(&page.text, Some(&url)).into_iter() {
+            if form.action.as_ref().is_some_and(|a| a.contains("login")) {
                 return Ok(form);
             }
         }
-        Err(LoginError::ParseError("No login form found"))
+        Err(page.fail("No login form found").into())
     }
 
     pub async fn try_login(&self, form: &Form, account: &Account) -> LoginResult<LoginStatus> {
@@ -61,34 +88,31 @@ impl Client {
             .send()
             .await?;
 
-        let res = res.error_for_status()?;
+        let page = Page::read(res.error_for_status()?).await?;
+        let url = &page.url;
 
-        let url = res.url().clone();
-
-        if url.as_str().starts_with(LOGIN_URL) {
+        if url.as_str().starts_with(&self.login_url()) {
             return Ok(LoginStatus::Failure(
                 "Login failed(アカウントが間違っている可能性があります)".to_string(),
             ));
         }
 
-        if url.as_str().starts_with(AUTH_URL) {
-            let text = res.text().await?;
-            let forms = extract_forms(&text, Some(&url));
-            if forms.is_empty() {
-                return Err(LoginError::ParseError("No two-way auth form found"));
-            }
+        if url.as_str().starts_with(&self.auth_url()) {
+            let forms = extract_forms(&page.text, Some(url));
+            let Some(form) = forms.into_iter().next() else {
+                return Err(page.fail("No two-way auth form found").into());
+            };
             return Ok(LoginStatus::TowWayAuthRequired(
-                forms.into_iter().next().unwrap(),
-                get_mailaddress(&text),
+                form,
+                get_mailaddress(&page.text),
             ));
         }
 
-        if url.as_str().starts_with(TOP_PAGE) {
-            let text = res.text().await?;
-            return Ok(LoginStatus::Success(text));
+        if url.as_str().starts_with(&self.top_page()) {
+            return Ok(LoginStatus::Success(page.text));
         }
 
-        return Err(LoginError::ParseError("Unknown login status"));
+        Err(page.fail("Unknown login status").into())
     }
 
     pub async fn two_way_select_email(&self, form: &Form) -> LoginResult<Form> {
@@ -100,7 +124,7 @@ impl Client {
         if let Some(auth_type) = params.get_mut("auth_type") {
             *auth_type = "auth_mail".into();
         } else {
-            return Err(LoginError::ParseError("auth_type not found in form"));
+            return Err(form_failure(form, "auth_type not found in form").into());
         }
 
         let res = self
@@ -110,26 +134,22 @@ impl Client {
             .send()
             .await?;
 
-        let res = res.error_for_status()?;
-        let url = res.url().clone();
-        let text = res.text().await?;
+        let page = Page::read(res.error_for_status()?).await?;
 
-        let forms = extract_forms(&text, Some(&url));
+        let forms = extract_forms(&page.text, Some(&page.url));
         if forms.is_empty() {
-            return Err(LoginError::ParseError(
-                "No forms found in two-way auth response",
-            ));
+            return Err(page.fail("No forms found in two-way auth response").into());
         }
 
         for form in forms {
-            if form.action.as_ref().map_or(false, |a| a.contains("/do")) {
+            if form.action.as_ref().is_some_and(|a| a.contains("/do")) {
                 return Ok(form);
             }
         }
 
-        Err(LoginError::ParseError(
-            "No valid form found in two-way auth response",
-        ))
+        Err(page
+            .fail("No valid form found in two-way auth response")
+            .into())
     }
 
     pub async fn two_way_auth(&self, form: &Form, code: &str) -> LoginResult<LoginStatus> {
@@ -141,7 +161,7 @@ impl Client {
         if let Some(auth_code) = params.get_mut("auth_code") {
             *auth_code = code.to_string();
         } else {
-            return Err(LoginError::ParseError("auth_code not found in form"));
+            return Err(form_failure(form, "auth_code not found in form").into());
         }
 
         let res = self
@@ -151,14 +171,18 @@ impl Client {
             .send()
             .await?;
 
-        let res = res.error_for_status()?;
-        let url = res.url().clone();
+        let page = Page::read(res.error_for_status()?).await?;
+
+        if page.url.as_str().starts_with(&self.top_page()) {
+            return Ok(LoginStatus::Success(page.text));
+        }
 
-        if url.as_str().starts_with(TOP_PAGE) {
-            let text = res.text().await?;
-            return Ok(LoginStatus::Success(text));
+        if page.url.as_str().starts_with(&self.auth_url()) {
+            return Ok(LoginStatus::Failure(
+                "Authentication code was rejected".to_string(),
+            ));
         }
 
-        Err(LoginError::ParseError("Two-way auth failed"))
+        Err(page.fail("Unknown two-way auth status").into())
     }
 }
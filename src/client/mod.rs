@@ -1,10 +1,20 @@
 mod account;
+#[allow(clippy::module_inception)]
 mod client;
 mod form;
 mod login;
 mod server;
+mod snapshot;
 
-pub use account::Account;
-pub use client::{Client, DEFAULT_CLIENT, save_default_client};
-pub use login::LoginStatus;
-pub use server::{Captcha, CaptchaResponse, ExtendResponse, get_server_id};
+pub use account::{
+    Account, EMAIL_VAR, PASSWORD_VAR, account, account_from_env, account_profiles, is_selected,
+    select_profile,
+};
+pub use client::{Client, load_client, save_client};
+pub use login::{LoginError, LoginStatus};
+pub use server::{
+    Captcha, CaptchaResponse, ExtendError, ExtendResponse, JST, Server, get_server_ids,
+};
+
+#[cfg(test)]
+mod tests;
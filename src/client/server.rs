@@ -1,15 +1,152 @@
+use bincode::{Decode, Encode};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, TimeZone};
 use scraper::{ElementRef, Html, Selector};
 use url::Url;
 
 use super::Client;
 use super::form::{Form, extract_forms};
+use super::snapshot::{Page, ParseFailure, form_failure};
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct Server {
+    pub id: String,
+    pub name: String,
+    #[bincode(with_serde)]
+    pub expires: Option<NaiveDate>,
+}
+
+/// Xserver dates are shown in JST.
+pub const JST: FixedOffset = FixedOffset::east_opt(9 * 3600).unwrap();
 
-pub fn get_server_id(html: &str) -> Option<String> {
+impl Server {
+    /// End of the last day of the contract.
+    pub fn expires_at(&self) -> Option<DateTime<FixedOffset>> {
+        let day_after = self.expires? + Duration::days(1);
+        JST.from_local_datetime(&day_after.and_hms_opt(0, 0, 0)?)
+            .single()
+    }
+
+    /// Extension is accepted from the day before the expiry date.
+    pub fn renewal_opens_at(&self) -> Option<DateTime<FixedOffset>> {
+        let day_before = self.expires? - Duration::days(1);
+        JST.from_local_datetime(&day_before.and_hms_opt(0, 0, 0)?)
+            .single()
+    }
+}
+
+/// Finds the first date written as `2025年7月25日`, `2025/07/25` or
+/// `2025-07-25` in `text`.
+pub fn parse_expiry(text: &str) -> Option<NaiveDate> {
+    let chars: Vec<char> = text.chars().collect();
+    for start in 0..chars.len() {
+        if start > 0 && chars[start - 1].is_ascii_digit() {
+            continue;
+        }
+        let mut nums = [0u32; 3];
+        let mut pos = start;
+        let mut ok = true;
+        for (i, num) in nums.iter_mut().enumerate() {
+            let digits = chars[pos..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            let valid_len = if i == 0 {
+                digits == 4
+            } else {
+                (1..=2).contains(&digits)
+            };
+            if !valid_len {
+                ok = false;
+                break;
+            }
+            *num = chars[pos..pos + digits]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .ok()?;
+            pos += digits;
+            let sep = chars.get(pos).copied();
+            let sep_ok = match i {
+                0 => matches!(sep, Some('年' | '/' | '-')),
+                1 => matches!(sep, Some('月' | '/' | '-')),
+                _ => true,
+            };
+            if !sep_ok {
+                ok = false;
+                break;
+            }
+            if i < 2 {
+                pos += 1;
+            }
+        }
+        if ok && let Some(date) = NaiveDate::from_ymd_opt(nums[0] as i32, nums[1], nums[2]) {
+            return Some(date);
+        }
+    }
+    None
+}
+
+impl std::fmt::Display for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() || self.name == self.id {
+            write!(f, "{}", self.id)
+        } else {
+            write!(f, "{} ({})", self.name, self.id)
+        }
+    }
+}
+
+fn link_id(href: &str) -> Option<String> {
+    let url = Url::parse(&format!("http://dummy.local{}", href))
+        .or_else(|_| Url::parse(href))
+        .ok()?;
+    url.query_pairs()
+        .find(|(k, _)| k == "id")
+        .map(|(_, v)| v.into_owned())
+}
+
+/// The expiry date on the extend page: in the text of a 利用期限 label, or in
+/// the element that follows a label standing on its own (`th`/`td`,
+/// `dt`/`dd`). Dates elsewhere, e.g. when to try again, are ignored.
+pub fn parse_extend_expiry(html: &str) -> Option<NaiveDate> {
+    const LABEL: &str = "利用期限";
+    let doc = Html::parse_document(html);
+    doc.root_element().descendants().find_map(|node| {
+        let text = node.value().as_text()?;
+        let pos = text.find(LABEL)?;
+        if let Some(date) = parse_expiry(&text[pos + LABEL.len()..]) {
+            return Some(date);
+        }
+        let label = text.trim().trim_end_matches([':', '：']);
+        if !label.ends_with(LABEL) || label.chars().count() > 8 {
+            return None;
+        }
+        let element = node.parent()?;
+        element
+            .next_siblings()
+            .find_map(ElementRef::wrap)
+            .and_then(|next| parse_expiry(&element_text(next)))
+    })
+}
+
+fn element_text(el: ElementRef) -> String {
+    el.text()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns every VPS listed under a "無料" heading, in page order.
+pub fn get_server_ids(html: &str) -> Vec<Server> {
     let doc = Html::parse_document(html);
 
     let h3_sel = Selector::parse("h3").unwrap();
+    let row_sel = Selector::parse("tr").unwrap();
+    let cell_sel = Selector::parse("td").unwrap();
     let link_sel = Selector::parse("a[href]").unwrap();
 
+    let mut servers: Vec<Server> = Vec::new();
     for h3 in doc.select(&h3_sel) {
         if !h3.text().any(|t| t.contains("無料")) {
             continue;
@@ -20,31 +157,32 @@ pub fn get_server_id(html: &str) -> Option<String> {
                 if el.name() == "table" {
                     let table = ElementRef::wrap(node).unwrap();
 
-                    for a in table.select(&link_sel) {
-                        if let Some(href) = a.value().attr("href") {
-                            let full = Url::parse(&format!("http://dummy.local{}", href))
-                                .or_else(|_| Url::parse(href));
-
-                            if let Ok(url) = full {
-                                if let Some(id_val) = url
-                                    .query_pairs()
-                                    .find(|(k, _)| k == "id")
-                                    .map(|(_, v)| v.into_owned())
-                                {
-                                    return Some(id_val);
-                                }
-                            }
+                    for row in table.select(&row_sel) {
+                        let Some((id, link)) = row.select(&link_sel).find_map(|a| {
+                            a.value().attr("href").and_then(link_id).map(|id| (id, a))
+                        }) else {
+                            continue;
+                        };
+                        if servers.iter().any(|s| s.id == id) {
+                            continue;
                         }
+                        let name = row
+                            .select(&cell_sel)
+                            .map(element_text)
+                            .find(|t| !t.is_empty())
+                            .unwrap_or_else(|| element_text(link));
+                        let expires = row
+                            .select(&cell_sel)
+                            .find_map(|cell| parse_expiry(&element_text(cell)));
+                        servers.push(Server { id, name, expires });
                     }
-                    break;
-                } else {
-                    break;
                 }
+                break;
             }
             next = node.next_sibling();
         }
     }
-    None
+    servers
 }
 
 pub fn get_message(html: &str) -> Option<String> {
@@ -69,10 +207,10 @@ pub fn get_captcha_image(doc: &Html) -> Option<String> {
     let img_sel = Selector::parse("img").unwrap();
 
     for img in doc.select(&img_sel) {
-        if let Some(src) = img.value().attr("src") {
-            if src.contains("base64") {
-                return Some(src.to_string());
-            }
+        if let Some(src) = img.value().attr("src")
+            && src.contains("base64")
+        {
+            return Some(src.to_string());
         }
     }
     None
@@ -90,7 +228,9 @@ pub enum ExtendError {
     #[error("Failed to send extend request: {0}")]
     RequestError(#[from] reqwest::Error),
     #[error("Failed to parse extend response: {0}")]
-    ParseError(&'static str),
+    ParseError(#[from] ParseFailure),
+    #[error("Captcha answer was rejected")]
+    CaptchaRejected,
 }
 
 #[derive(Debug)]
@@ -109,20 +249,14 @@ impl Captcha {
     pub fn base64_image(&self) -> Option<String> {
         let image = self.image.as_ref()?;
         let split = ";base64,";
-        if let Some(pos) = image.find(split) {
-            Some(image[pos + split.len()..].to_string())
-        } else {
-            None
-        }
+        image
+            .find(split)
+            .map(|pos| image[pos + split.len()..].to_string())
     }
 
     pub fn mime_type(&self) -> Option<String> {
         let image = self.image.as_ref()?;
-        if let Some(pos) = image.find(';') {
-            Some(image[..pos].replace("data:", ""))
-        } else {
-            None
-        }
+        image.find(';').map(|pos| image[..pos].replace("data:", ""))
     }
 
     pub fn cloudflare_challenge(&self) -> Option<&str> {
@@ -133,38 +267,106 @@ impl Captcha {
 #[derive(Debug)]
 pub enum ExtendResponse {
     Success(String),
-    Failure(String),
+    /// The panel refused because the renewal window is not open.
+    Unavailable(String),
     CaptchaRequired(Captcha),
 }
 
+/// The extend page as shown before submitting.
+#[derive(Debug)]
+pub struct ExtendPreview {
+    pub form: Option<Form>,
+    /// Message of the panel when the renewal window is not open.
+    pub unavailable: Option<String>,
+    pub image_captcha: bool,
+    pub turnstile: bool,
+    /// Expiry date shown on the page, if any.
+    pub expires: Option<NaiveDate>,
+    page: Page,
+}
+
+impl ExtendPreview {
+    /// Error for a page without an extend form, saving the page.
+    pub fn missing_form(&self) -> ExtendError {
+        self.page.fail("No valid extend form found").into()
+    }
+}
+
 #[derive(Debug)]
 pub enum CaptchaResponse {
     Success(String),
-    Failure(String),
+    Unavailable(String),
 }
 
-const EXTEND_URL: &str = "https://secure.xserver.ne.jp/xapanel/xvps/server/freevps/extend/index";
+/// Phrases of the panel saying the renewal window is not open yet.
+const EXTEND_UNAVAILABLE: [&str; 3] = ["以降にお試し", "継続される場合は", "利用期限の1日前"];
 
 pub type ExtendResult<T> = Result<T, ExtendError>;
 
 impl Client {
-    pub async fn extend_vps(&self, id: &str) -> ExtendResult<Form> {
-        let url = format!("{}?id_vps={}", EXTEND_URL, id);
-        let res = self.client.get(&url).send().await?.error_for_status()?;
-        let url = res.url().clone();
+    fn extend_url(&self) -> String {
+        self.page("xvps/server/freevps/extend/index")
+    }
+
+    /// Fetches the VPS list again, e.g. to pick up the new expiry dates after
+    /// an extension.
+    pub async fn list_servers(&self) -> ExtendResult<Vec<Server>> {
+        let res = self
+            .client
+            .get(self.top_page())
+            .send()
+            .await?
+            .error_for_status()?;
         let text = res.text().await?;
-        let forms = extract_forms(&text, Some(&url));
-
-        for form in forms {
-            if form
-                .action
-                .as_ref()
-                .map_or(false, |a| a.contains("extend") && !a.contains("change"))
-            {
-                return Ok(form);
+        let mut servers = get_server_ids(&text);
+        self.fill_expiry(&mut servers).await;
+        Ok(servers)
+    }
+
+    /// Reads the expiry dates the VPS list did not show from the extend
+    /// pages. A page that fails to load leaves the date unknown.
+    pub async fn fill_expiry(&self, servers: &mut [Server]) {
+        for server in servers.iter_mut().filter(|s| s.expires.is_none()) {
+            if let Ok(preview) = self.preview_extend(&server.id).await {
+                server.expires = preview.expires;
             }
         }
-        Err(ExtendError::ParseError("No valid extend form found"))
+    }
+
+    pub async fn extend_vps(&self, id: &str) -> ExtendResult<Form> {
+        let preview = self.preview_extend(id).await?;
+        match preview.form {
+            Some(form) => Ok(form),
+            None => Err(preview.missing_form()),
+        }
+    }
+
+    /// Fetches the extend page of a VPS without submitting anything.
+    pub async fn preview_extend(&self, id: &str) -> ExtendResult<ExtendPreview> {
+        let url = format!("{}?id_vps={}", self.extend_url(), id);
+        let res = self.client.get(&url).send().await?.error_for_status()?;
+        let page = Page::read(res).await?;
+        let text = &page.text;
+        let form = extract_forms(text, Some(&page.url))
+            .into_iter()
+            .find(|form| {
+                form.action
+                    .as_ref()
+                    .is_some_and(|a| a.contains("extend") && !a.contains("change"))
+            });
+        let unavailable = EXTEND_UNAVAILABLE
+            .iter()
+            .any(|s| text.contains(s))
+            .then(|| get_message(text).unwrap_or_else(|| "Extend unavailable".to_string()));
+        let html = Html::parse_document(text);
+        Ok(ExtendPreview {
+            form,
+            unavailable,
+            image_captcha: get_captcha_image(&html).is_some(),
+            turnstile: get_cloudflare_challenge(&html).is_some(),
+            expires: parse_extend_expiry(text),
+            page,
+        })
     }
 
     pub async fn submit_extend_form(&self, form: &Form) -> ExtendResult<ExtendResponse> {
@@ -180,50 +382,47 @@ impl Client {
             .await?
             .error_for_status()?;
 
-        let url = res.url().clone();
-        let text = res.text().await?;
+        let page = Page::read(res).await?;
+        let (url, text) = (&page.url, &page.text);
 
-        let extend_unavailable = ["以降にお試し", "継続される場合は", "利用期限の1日前"];
-        if extend_unavailable.iter().any(|s| text.contains(s)) {
-            return Ok(ExtendResponse::Failure(
-                get_message(&text).unwrap_or_else(|| "Extend unavailable".to_string()),
+        if EXTEND_UNAVAILABLE.iter().any(|s| text.contains(s)) {
+            return Ok(ExtendResponse::Unavailable(
+                get_message(text).unwrap_or_else(|| "Extend unavailable".to_string()),
             ));
         }
 
         let success_message = ["完了しました", "成功しました"];
         if success_message.iter().any(|s| text.contains(s)) {
             return Ok(ExtendResponse::Success(
-                get_message(&text).unwrap_or_else(|| "Extend successful".to_string()),
+                get_message(text).unwrap_or_else(|| "Extend successful".to_string()),
             ));
         }
 
         if text.contains("画像認証") {
-            let forms = extract_forms(&text, Some(&url));
+            let forms = extract_forms(text, Some(url));
             for form in forms {
-                let html = Html::parse_document(&text);
+                let html = Html::parse_document(text);
                 let image = get_captcha_image(&html);
                 let cloudflare = get_cloudflare_challenge(&html);
-                if form.action.as_ref().map_or(false, |a| a.contains("/do")) {
+                if form.action.as_ref().is_some_and(|a| a.contains("/do")) {
                     return Ok(ExtendResponse::CaptchaRequired(Captcha {
                         form,
                         image,
                         cloudflare,
-                        url,
+                        url: url.clone(),
                     }));
                 }
             }
-            return Err(ExtendError::ParseError(
-                "Captcha required but no image found",
-            ));
+            return Err(page.fail("Captcha required but no image found").into());
         }
 
-        return Err(ExtendError::ParseError("Extend failed"));
+        Err(page.fail("Extend failed").into())
     }
 
     pub async fn submit_captcha(
         &self,
         captcha: &Captcha,
-        code: Option<i32>,
+        code: Option<&str>,
         turnstile_response: Option<String>,
     ) -> ExtendResult<CaptchaResponse> {
         let form = &captcha.form;
@@ -235,17 +434,12 @@ impl Client {
             if let Some(field) = params.get_mut("auth_code") {
                 *field = code.to_string();
             } else {
-                'f: {
-                    for (name, value) in &mut params {
-                        if name.contains("code") || name.contains("auth") {
-                            *value = code.to_string();
-                            break 'f;
-                        }
-                        return Err(ExtendError::ParseError(
-                            "Captcha code field not found in form",
-                        ));
-                    }
-                }
+                let field = params
+                    .iter_mut()
+                    .find(|(name, _)| name.contains("code") || name.contains("auth"))
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| form_failure(form, "Captcha code field not found in form"))?;
+                *field = code.to_string();
             }
         }
         if let Some(turnstile) = turnstile_response {
@@ -260,16 +454,16 @@ impl Client {
             .await?
             .error_for_status()?;
 
-        let text = res.text().await?;
+        let page = Page::read(res).await?;
+        let text = &page.text;
 
         if text.contains("入力された認証コードが正しくありません") {
-            return Err(ExtendError::ParseError("Invalid captcha code"));
+            return Err(ExtendError::CaptchaRejected);
         }
 
-        let extend_unavailable = ["以降にお試し", "継続される場合は", "利用期限の1日前"];
-        if extend_unavailable.iter().any(|s| text.contains(s)) {
-            return Ok(CaptchaResponse::Failure(
-                get_message(&text).unwrap_or_else(|| "Extend unavailable".to_string()),
+        if EXTEND_UNAVAILABLE.iter().any(|s| text.contains(s)) {
+            return Ok(CaptchaResponse::Unavailable(
+                get_message(text).unwrap_or_else(|| "Extend unavailable".to_string()),
             ));
         }
 
@@ -282,10 +476,10 @@ impl Client {
         let success_message = ["完了しました", "成功しました"];
         if success_message.iter().any(|s| text.contains(s)) {
             return Ok(CaptchaResponse::Success(
-                get_message(&text).unwrap_or_else(|| "Extend successful".to_string()),
+                get_message(text).unwrap_or_else(|| "Extend successful".to_string()),
             ));
         }
 
-        return Err(ExtendError::ParseError("Captcha submission failed"));
+        Err(page.fail("Captcha submission failed").into())
     }
 }
@@ -0,0 +1,300 @@
+use std::path::PathBuf;
+
+use reqwest::{StatusCode, header::HeaderMap};
+use scraper::{Html, Selector};
+use url::Url;
+
+use super::form::Form;
+#[cfg(not(test))]
+use crate::data::SAVE_DIR;
+
+/// Snapshots kept in the debug directory; older ones are dropped.
+const MAX_SNAPSHOTS: usize = 20;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Headers that carry the session or credentials.
+const SECRET_HEADERS: [&str; 4] = [
+    "set-cookie",
+    "cookie",
+    "authorization",
+    "proxy-authorization",
+];
+
+/// Parts of input and query parameter names whose values are redacted.
+const SECRET_FIELDS: [&str; 9] = [
+    "pass",
+    "pwd",
+    "token",
+    "csrf",
+    "session",
+    "auth_code",
+    "mail",
+    "memberid",
+    "uniqid",
+];
+
+#[cfg(not(test))]
+pub fn debug_dir() -> PathBuf {
+    SAVE_DIR.join("debug")
+}
+
+/// Tests provoke parse failures on purpose; keep their pages out of the
+/// real data directory.
+#[cfg(test)]
+pub fn debug_dir() -> PathBuf {
+    std::env::temp_dir().join("xrenew-test-debug")
+}
+
+/// What the scraper could not find, and where the offending response was
+/// saved.
+#[derive(Debug, thiserror::Error)]
+#[error("{reason}")]
+pub struct ParseFailure {
+    pub reason: &'static str,
+    pub snapshot: Option<PathBuf>,
+}
+
+/// A response read in full, so it can still be saved after parsing failed.
+#[derive(Debug)]
+pub struct Page {
+    pub url: Url,
+    status: StatusCode,
+    headers: HeaderMap,
+    pub text: String,
+}
+
+impl Page {
+    pub async fn read(res: reqwest::Response) -> reqwest::Result<Self> {
+        let url = res.url().clone();
+        let status = res.status();
+        let headers = res.headers().clone();
+        let text = res.text().await?;
+        Ok(Page {
+            url,
+            status,
+            headers,
+            text,
+        })
+    }
+
+    /// Saves the page to the debug directory and describes the failure.
+    pub fn fail(&self, reason: &'static str) -> ParseFailure {
+        let mut header = format!(
+            "xrenew snapshot: {}\nSaved: {}\nURL: {}\nStatus: {}\nHeaders:\n",
+            reason,
+            chrono::Local::now().to_rfc3339(),
+            redact_url(&self.url),
+            self.status
+        );
+        for (name, value) in &self.headers {
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("(binary)")
+            };
+            header.push_str(&format!("  {}: {}\n", name, value));
+        }
+        let content = format!(
+            "<!--\n{}-->\n{}",
+            header.replace("--", "- -"),
+            redact_html(&self.text)
+        );
+        ParseFailure {
+            reason,
+            snapshot: save(reason, "html", &content),
+        }
+    }
+}
+
+/// Saves a form that turned out to be unusable, for errors found before
+/// anything is sent.
+pub fn form_failure(form: &Form, reason: &'static str) -> ParseFailure {
+    let fields: Vec<serde_json::Value> = form
+        .fields
+        .iter()
+        .map(|f| {
+            let value = match &f.value {
+                Some(_) if is_secret(&f.name) => Some(REDACTED.to_string()),
+                value => value.clone(),
+            };
+            serde_json::json!({ "name": f.name, "type": f.r#type, "value": value })
+        })
+        .collect();
+    let content = serde_json::json!({
+        "reason": reason,
+        "saved": chrono::Local::now().to_rfc3339(),
+        "action": form.action.as_deref().map(|a| match Url::parse(a) {
+            Ok(url) => redact_url(&url),
+            Err(_) => a.to_string(),
+        }),
+        "method": form.method,
+        "fields": fields,
+    });
+    ParseFailure {
+        reason,
+        snapshot: save(reason, "json", &format!("{:#}\n", content)),
+    }
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_FIELDS.iter().any(|s| name.contains(s))
+}
+
+fn redact_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.query().is_some() {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| {
+                let v = if is_secret(&k) {
+                    REDACTED.to_string()
+                } else {
+                    v.into_owned()
+                };
+                (k.into_owned(), v)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+/// Blanks out credential-like input values, the address shown on the
+/// two-way auth page and any other email address.
+fn redact_html(html: &str) -> String {
+    let doc = Html::parse_document(html);
+    let input_sel = Selector::parse("input[name][value]").unwrap();
+    let mail_sel = Selector::parse("#mailaddress").unwrap();
+
+    let mut secrets: Vec<String> = doc
+        .select(&input_sel)
+        .filter(|input| {
+            let el = input.value();
+            el.attr("type") == Some("password") || el.attr("name").is_some_and(is_secret)
+        })
+        .filter_map(|input| input.value().attr("value"))
+        .chain(doc.select(&mail_sel).flat_map(|el| el.text()))
+        .map(str::trim)
+        .filter(|s| s.len() >= 3)
+        .map(str::to_owned)
+        .collect();
+    secrets.sort();
+    secrets.dedup();
+    // Longest first, so a value containing another one is replaced whole.
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+    let mut text = html.to_string();
+    for secret in &secrets {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+    redact_emails(&text)
+}
+
+fn redact_emails(text: &str) -> String {
+    let is_local = |c: char| c.is_ascii_alphanumeric() || "._%+-".contains(c);
+    let is_domain = |c: char| c.is_ascii_alphanumeric() || ".-".contains(c);
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('@') {
+        let start = rest[..at]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_local(*c))
+            .last()
+            .map_or(at, |(i, _)| i);
+        let end = rest[at + 1..]
+            .char_indices()
+            .take_while(|(_, c)| is_domain(*c))
+            .last()
+            .map_or(at + 1, |(i, c)| at + 1 + i + c.len_utf8());
+        let domain = &rest[at + 1..end];
+        out.push_str(&rest[..start]);
+        if start < at && domain.contains('.') {
+            out.push_str(REDACTED);
+        } else {
+            out.push_str(&rest[start..end]);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Writes a snapshot and drops the oldest ones beyond `MAX_SNAPSHOTS`.
+fn save(reason: &str, ext: &str, content: &str) -> Option<PathBuf> {
+    let dir = debug_dir();
+    std::fs::create_dir_all(&dir).ok()?;
+    let slug: String = reason
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = format!(
+        "{}-{}.{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"),
+        slug.trim_matches('-'),
+        ext
+    );
+    let path = dir.join(name);
+    if let Err(e) = std::fs::write(&path, content) {
+        eprintln!("Failed to save page snapshot: {}", e);
+        return None;
+    }
+    println!("Unexpected page saved to {}", path.display());
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    files.sort();
+    for old in files.iter().take(files.len().saturating_sub(MAX_SNAPSHOTS)) {
+        std::fs::remove_file(old).ok();
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_credentials_and_addresses() {
+        let html = r#"<form>
+<input type="hidden" name="ethna_csrf" value="csrf-1234">
+<input type="password" name="pw" value="hunter22">
+<input type="hidden" name="id_vps" value="1001">
+</form>
+<p>送信先: <span id="mailaddress">a.b+c@example.co.jp</span></p>
+<p>お問い合わせ: support@xserver.ne.jp</p>
+<style>@media print {}</style>"#;
+        let redacted = redact_html(html);
+        for secret in [
+            "csrf-1234",
+            "hunter22",
+            "a.b+c@example.co.jp",
+            "support@xserver",
+        ] {
+            assert!(!redacted.contains(secret), "{} in {}", secret, redacted);
+        }
+        assert!(redacted.contains(r#"value="1001""#));
+        assert!(redacted.contains("@media print"));
+    }
+
+    #[test]
+    fn redacts_secret_query_parameters() {
+        let url = Url::parse("https://example.com/p?id_vps=1001&token=abc").unwrap();
+        assert_eq!(
+            redact_url(&url),
+            "https://example.com/p?id_vps=1001&token=%5BREDACTED%5D"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Runs the client against the mock panel, one test per page flow.
+
+use xrenew_mock::{self as mock, MockPanel};
+
+use super::client::{create_client, session_cookie};
+use super::{
+    Account, Captcha, CaptchaResponse, Client, ExtendError, ExtendResponse, LoginError,
+    LoginStatus, get_server_ids,
+};
+use crate::data::{
+    lock_keys,
+    value::{set_account, set_cookie, set_cookie_email},
+};
+
+async fn setup() -> (MockPanel, Client) {
+    let panel = MockPanel::start()
+        .await
+        .expect("Failed to start mock panel");
+    let mut client = create_client("xrenew-test".to_string(), None);
+    client.panel = panel.base_url().parse().unwrap();
+    (panel, client)
+}
+
+fn account(email: &str, password: &str) -> Account {
+    Account {
+        email: email.to_string(),
+        password: password.to_string(),
+    }
+}
+
+async fn login(client: &Client, email: &str, password: &str) -> Result<LoginStatus, LoginError> {
+    let form = client.login_page().await?;
+    client.try_login(&form, &account(email, password)).await
+}
+
+async fn logged_in() -> (MockPanel, Client) {
+    let (panel, client) = setup().await;
+    let status = login(&client, mock::EMAIL, mock::PASSWORD).await.unwrap();
+    assert!(matches!(status, LoginStatus::Success(_)), "{:?}", status);
+    (panel, client)
+}
+
+async fn captcha(client: &Client, id: &str) -> Captcha {
+    let form = client.extend_vps(id).await.unwrap();
+    match client.submit_extend_form(&form).await.unwrap() {
+        ExtendResponse::CaptchaRequired(captcha) => captcha,
+        res => panic!("Expected a captcha, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn session_check_follows_login_state() {
+    let (_panel, client) = setup().await;
+    assert!(client.check_session().await.unwrap().is_none());
+
+    let status = login(&client, mock::EMAIL, mock::PASSWORD).await.unwrap();
+    let LoginStatus::Success(page) = status else {
+        panic!("Expected success, got {:?}", status);
+    };
+    let ids: Vec<String> = get_server_ids(&page).into_iter().map(|s| s.id).collect();
+    assert_eq!(ids, mock::FREE_VPS);
+
+    let page = client.check_session().await.unwrap().expect("Logged in");
+    assert_eq!(get_server_ids(&page).len(), mock::FREE_VPS.len());
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_password() {
+    let (_panel, client) = setup().await;
+    let status = login(&client, mock::EMAIL, "wrong").await.unwrap();
+    assert!(matches!(status, LoginStatus::Failure(_)), "{:?}", status);
+    assert!(client.check_session().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn login_reports_unknown_pages() {
+    let (_panel, client) = setup().await;
+    let res = login(&client, mock::MAINTENANCE_EMAIL, mock::PASSWORD).await;
+    let Err(LoginError::ParseError(failure)) = res else {
+        panic!("Expected a parse error, got {:?}", res);
+    };
+    assert_eq!(failure.reason, "Unknown login status");
+
+    let path = failure.snapshot.expect("Page saved");
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.starts_with("<!--\nxrenew snapshot: Unknown login status"));
+    assert!(saved.contains("/xapanel/maintenance"));
+    assert!(saved.contains("Status: 200 OK"));
+    assert!(saved.contains("メンテナンス中"));
+}
+
+#[tokio::test]
+async fn two_way_auth_flow() {
+    let (_panel, client) = setup().await;
+    let status = login(&client, mock::TWO_FACTOR_EMAIL, mock::PASSWORD)
+        .await
+        .unwrap();
+    let LoginStatus::TowWayAuthRequired(form, mail) = status else {
+        panic!("Expected two-way auth, got {:?}", status);
+    };
+    assert_eq!(mail.as_deref(), Some(mock::TWO_FACTOR_EMAIL));
+
+    let form = client.two_way_select_email(&form).await.unwrap();
+    assert!(form.action.as_ref().unwrap().ends_with("/do"));
+
+    let status = client.two_way_auth(&form, "000000").await.unwrap();
+    assert!(matches!(status, LoginStatus::Failure(_)), "{:?}", status);
+
+    let status = client.two_way_auth(&form, mock::AUTH_CODE).await.unwrap();
+    assert!(matches!(status, LoginStatus::Success(_)), "{:?}", status);
+    assert!(client.check_session().await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn lists_free_servers_with_expiry() {
+    let (_panel, client) = logged_in().await;
+    let servers = client.list_servers().await.unwrap();
+    assert_eq!(servers.len(), mock::FREE_VPS.len());
+    assert_eq!(servers[0].id, mock::VPS_SUCCESS);
+    assert_eq!(servers[0].name, "vps-success");
+    assert_eq!(servers[0].expires.unwrap().to_string(), "2025-07-25");
+    assert_eq!(servers[1].expires.unwrap().to_string(), "2025-07-30");
+    assert_eq!(servers[2].expires.unwrap().to_string(), "2025-07-26");
+}
+
+#[tokio::test]
+async fn reads_missing_expiry_from_extend_page() {
+    let (panel, client) = logged_in().await;
+    let servers = client.list_servers().await.unwrap();
+    let image = servers
+        .iter()
+        .find(|s| s.id == mock::VPS_IMAGE_CAPTCHA)
+        .unwrap();
+    assert_eq!(image.expires.unwrap().to_string(), "2025-07-26");
+
+    // Only the VPS without a date in the list is looked up.
+    let extend_pages: Vec<String> = panel
+        .requests()
+        .into_iter()
+        .filter(|r| r.contains("extend/index"))
+        .collect();
+    assert_eq!(extend_pages.len(), 1, "{:?}", extend_pages);
+    assert!(extend_pages[0].ends_with(mock::VPS_IMAGE_CAPTCHA));
+}
+
+#[tokio::test]
+async fn preview_does_not_submit() {
+    let (panel, client) = logged_in().await;
+
+    let preview = client.preview_extend(mock::VPS_SUCCESS).await.unwrap();
+    let form = preview.form.expect("Extend form");
+    assert!(form.action.unwrap().ends_with("extend/conf"));
+    assert!(form.fields.iter().any(|f| f.name == "id_vps"));
+    assert!(preview.unavailable.is_none());
+    assert!(!preview.image_captcha && !preview.turnstile);
+    assert_eq!(preview.expires.unwrap().to_string(), "2025-07-26");
+
+    let preview = client.preview_extend(mock::VPS_CLOSED).await.unwrap();
+    assert!(preview.form.is_none());
+    assert!(preview.unavailable.unwrap().contains("利用期限の1日前"));
+    // The date to try again from is not the expiry.
+    assert!(preview.expires.is_none());
+
+    let res = client.extend_vps(mock::VPS_NO_FORM).await;
+    assert!(matches!(res, Err(ExtendError::ParseError(_))), "{:?}", res);
+
+    let posts: Vec<String> = panel
+        .requests()
+        .into_iter()
+        .filter(|r| r.starts_with("POST") && !r.contains("login"))
+        .collect();
+    assert!(posts.is_empty(), "{:?}", posts);
+}
+
+#[tokio::test]
+async fn extend_form_responses() {
+    let (_panel, client) = logged_in().await;
+    let submit = async |id: &str| {
+        let form = client.extend_vps(id).await.unwrap();
+        client.submit_extend_form(&form).await
+    };
+
+    let res = submit(mock::VPS_SUCCESS).await.unwrap();
+    assert!(matches!(res, ExtendResponse::Success(_)), "{:?}", res);
+
+    let res = submit(mock::VPS_UNAVAILABLE).await.unwrap();
+    let ExtendResponse::Unavailable(message) = res else {
+        panic!("Expected unavailable, got {:?}", res);
+    };
+    assert!(message.contains("以降にお試しください"), "{}", message);
+
+    let res = submit(mock::VPS_CAPTCHA_NO_FORM).await;
+    assert!(matches!(res, Err(ExtendError::ParseError(_))), "{:?}", res);
+
+    let res = submit(mock::VPS_UNKNOWN).await;
+    assert!(matches!(res, Err(ExtendError::ParseError(_))), "{:?}", res);
+}
+
+#[tokio::test]
+async fn captcha_pages_are_parsed() {
+    let (_panel, client) = logged_in().await;
+
+    let both = captcha(&client, mock::VPS_CAPTCHA).await;
+    assert!(both.has_image());
+    assert_eq!(both.mime_type().as_deref(), Some("image/png"));
+    assert!(both.base64_image().is_some_and(|b| !b.is_empty()));
+    assert_eq!(both.cloudflare_challenge(), Some("0x4AAAAAAAmock-sitekey"));
+    assert!(both.form.action.as_ref().unwrap().ends_with("extend/do"));
+
+    let image = captcha(&client, mock::VPS_IMAGE_CAPTCHA).await;
+    assert!(image.has_image());
+    assert!(image.cloudflare_challenge().is_none());
+}
+
+#[tokio::test]
+async fn captcha_responses() {
+    let (_panel, client) = logged_in().await;
+    let captcha = captcha(&client, mock::VPS_CAPTCHA).await;
+    let submit = async |code: &str| {
+        client
+            .submit_captcha(&captcha, Some(code), Some("turnstile-token".to_string()))
+            .await
+    };
+
+    let res = submit(mock::CAPTCHA_SUCCESS).await.unwrap();
+    let CaptchaResponse::Success(message) = res else {
+        panic!("Expected success, got {:?}", res);
+    };
+    assert_eq!(message, "利用期限の更新手続きが完了しました");
+
+    let res = submit(mock::CAPTCHA_SUCCESS_GENERIC).await.unwrap();
+    assert!(matches!(res, CaptchaResponse::Success(_)), "{:?}", res);
+
+    let res = submit(mock::CAPTCHA_UNAVAILABLE).await.unwrap();
+    assert!(matches!(res, CaptchaResponse::Unavailable(_)), "{:?}", res);
+
+    let res = submit("9999").await;
+    assert!(
+        matches!(res, Err(ExtendError::CaptchaRejected)),
+        "{:?}",
+        res
+    );
+
+    let res = submit(mock::CAPTCHA_UNKNOWN).await;
+    assert!(matches!(res, Err(ExtendError::ParseError(_))), "{:?}", res);
+}
+
+#[test]
+fn saved_session_belongs_to_its_account() {
+    let _keys = lock_keys();
+    let profile = "session-owner";
+    assert!(session_cookie(profile).is_none());
+    set_account(profile, &account("stored@example.com", "secret"));
+    set_cookie(profile, &"[]".to_string());
+    // Saved before the owner was recorded, so the stored account's.
+    assert_eq!(session_cookie(profile).as_deref(), Some("[]"));
+    set_cookie_email(profile, &"other@example.com".to_string());
+    assert!(session_cookie(profile).is_none());
+    set_account(profile, &account("other@example.com", "secret"));
+    assert_eq!(session_cookie(profile).as_deref(), Some("[]"));
+}
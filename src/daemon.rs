@@ -0,0 +1,366 @@
+//! `xrenew daemon`: a scheduler for containers and other hosts without
+//! systemd or launchd.
+//!
+//! Each run is a child `xrenew extend --auto --all`, the same command the
+//! timers start, so skipping and run history work the same way. The daemon
+//! itself never opens the database, which leaves it free for `status` and
+//! the other commands (e.g. through `docker exec`) between runs.
+
+use std::{future::Future, path::PathBuf, pin::pin, time::Duration};
+
+use chrono::{DateTime, Local};
+use tokio::{process::Command, time::sleep};
+
+use crate::{
+    config::unstored_schedule,
+    data::DAEMON_PID_FILE,
+    schedule::{Plan, Schedule},
+};
+
+/// How often the wall clock is compared with the next run, so a run missed
+/// while the machine was suspended is noticed soon after waking up.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A run this late counts as missed and is reported as a catch-up.
+const LATE: chrono::Duration = chrono::Duration::minutes(2);
+
+fn log(message: impl std::fmt::Display) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+}
+
+/// Keeps the pid file for as long as the daemon runs.
+struct PidFile;
+
+impl PidFile {
+    fn create() -> Self {
+        if let Err(e) = std::fs::write(&*DAEMON_PID_FILE, std::process::id().to_string()) {
+            log(format!(
+                "Failed to write {}: {}",
+                DAEMON_PID_FILE.display(),
+                e
+            ));
+        }
+        PidFile
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&*DAEMON_PID_FILE).ok();
+    }
+}
+
+/// The pid a daemon wrote, which may have exited without removing it.
+pub fn recorded_pid() -> Option<u32> {
+    std::fs::read_to_string(&*DAEMON_PID_FILE)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn proc_dir(pid: u32) -> Option<PathBuf> {
+    let proc = PathBuf::from("/proc");
+    proc.join("self")
+        .exists()
+        .then(|| proc.join(pid.to_string()))
+}
+
+/// Whether `pid` is an xrenew process. `None` without `/proc` to look in.
+pub fn is_running(pid: u32) -> Option<bool> {
+    let cmdline = std::fs::read(proc_dir(pid)?.join("cmdline"));
+    Some(cmdline.is_ok_and(|c| String::from_utf8_lossy(&c).contains("xrenew")))
+}
+
+/// Whether the environment of `pid`, which its runs inherit, sets `var`.
+/// `None` when it cannot be read, e.g. for another user's process.
+pub fn has_env(pid: u32, var: &str) -> Option<bool> {
+    let environ = std::fs::read(proc_dir(pid)?.join("environ")).ok()?;
+    let prefix = format!("{}=", var);
+    Some(
+        environ
+            .split(|b| *b == 0)
+            .any(|entry| entry.starts_with(prefix.as_bytes())),
+    )
+}
+
+/// Resolves on SIGTERM (e.g. `docker stop`) or Ctrl-C.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                tokio::signal::ctrl_c().await.ok();
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
+
+/// Sleeps until `at` by the wall clock `now`, which keeps going while the
+/// monotonic clock stands still during suspend.
+async fn wait_until(at: DateTime<Local>, now: &impl Fn() -> DateTime<Local>) {
+    loop {
+        let left = at.signed_duration_since(now());
+        let Ok(left) = left.to_std() else {
+            return;
+        };
+        if left.is_zero() {
+            return;
+        }
+        sleep(left.min(CHECK_INTERVAL)).await;
+    }
+}
+
+/// Runs the renewal once and waits for it, even when asked to stop in the
+/// meantime. Returns whether to stop afterwards.
+async fn run_once(shutdown: &mut (impl Future<Output = ()> + Unpin)) -> bool {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            log(format!("Failed to find the xrenew executable: {}", e));
+            return false;
+        }
+    };
+    let mut child = match Command::new(exe)
+        .args(["extend", "--auto", "--all"])
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            log(format!("Failed to start the run: {}", e));
+            return false;
+        }
+    };
+    let (status, stop) = tokio::select! {
+        status = child.wait() => (status, false),
+        _ = shutdown => {
+            log("Stopping after the current run...");
+            (child.wait().await, true)
+        }
+    };
+    match status {
+        Ok(status) if status.success() => log("Run finished"),
+        Ok(status) => match status.code() {
+            Some(code) => log(format!("Run failed with exit code {}", code)),
+            None => log("Run was killed"),
+        },
+        Err(e) => log(format!("Failed to wait for the run: {}", e)),
+    }
+    stop
+}
+
+pub async fn run_daemon() {
+    let schedule = unstored_schedule();
+    let plan = schedule.plan_or_random();
+    log(format!(
+        "xrenew daemon started, runs at {}{}",
+        plan,
+        if schedule.random_delay > 0 {
+            format!(" plus up to {}min", schedule.random_delay)
+        } else {
+            String::new()
+        }
+    ));
+    let _pid_file = PidFile::create();
+    let mut shutdown = pin!(shutdown_signal());
+    run_schedule(
+        &schedule,
+        &plan,
+        Local::now,
+        &mut shutdown,
+        async |shutdown| run_once(shutdown).await,
+    )
+    .await;
+}
+
+/// A catch-up run, then one run per slot of `plan`, until `shutdown`
+/// resolves. `now` is the wall clock; `run` does one run and returns
+/// whether to stop afterwards.
+async fn run_schedule<S: Future<Output = ()> + Unpin>(
+    schedule: &Schedule,
+    plan: &Plan,
+    now: impl Fn() -> DateTime<Local>,
+    shutdown: &mut S,
+    mut run: impl AsyncFnMut(&mut S) -> bool,
+) {
+    // Catch up on a run missed while the daemon was not running; the run
+    // skips itself when the renewal window is not open yet.
+    if run(shutdown).await {
+        return;
+    }
+    let mut last = now();
+    loop {
+        let Some(slot) = plan.next_after(last) else {
+            log("No next run time found");
+            return;
+        };
+        let at = slot + schedule.jitter();
+        log(format!("Next run at {}", at.format("%Y-%m-%d %H:%M:%S")));
+        tokio::select! {
+            _ = wait_until(at, &now) => {}
+            _ = &mut *shutdown => {
+                log("Stopped");
+                return;
+            }
+        }
+        let woke = now();
+        if woke - at > LATE {
+            log(format!(
+                "Missed the run at {} (suspended?), catching up",
+                at.format("%Y-%m-%d %H:%M")
+            ));
+        }
+        // Slots passed during a suspend are folded into this one run.
+        last = woke.max(slot);
+        if run(shutdown).await {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, future::pending};
+
+    use chrono::{NaiveTime, TimeZone};
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn local(day: u32, hour: u32, min: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 7, day, hour, min, 0)
+            .single()
+            .unwrap()
+    }
+
+    /// A wall clock starting at `start` that follows tokio's paused clock,
+    /// and jumps `suspended` ahead once `after` has passed, like a machine
+    /// that was suspended.
+    fn clock(
+        start: DateTime<Local>,
+        after: Duration,
+        suspended: chrono::Duration,
+    ) -> impl Fn() -> DateTime<Local> + Copy {
+        let origin = Instant::now();
+        move || {
+            let elapsed = origin.elapsed();
+            let now = start + chrono::Duration::from_std(elapsed).unwrap();
+            if elapsed >= after {
+                now + suspended
+            } else {
+                now
+            }
+        }
+    }
+
+    fn steady(start: DateTime<Local>) -> impl Fn() -> DateTime<Local> + Copy {
+        clock(start, Duration::MAX, chrono::Duration::zero())
+    }
+
+    fn twice_a_day() -> (Schedule, Plan) {
+        let times = ["09:00", "21:00"].map(|t| NaiveTime::parse_from_str(t, "%H:%M").unwrap());
+        let plan = Plan::Times(times.to_vec());
+        let schedule = Schedule {
+            plan: Some(plan.clone()),
+            random_delay: 0,
+        };
+        (schedule, plan)
+    }
+
+    /// Runs the schedule until `runs` runs happened or `shutdown` resolves,
+    /// returning the wall clock time of each run.
+    async fn runs(
+        now: impl Fn() -> DateTime<Local> + Copy,
+        runs: usize,
+        shutdown: impl Future<Output = ()>,
+    ) -> Vec<DateTime<Local>> {
+        let (schedule, plan) = twice_a_day();
+        let times = RefCell::new(Vec::new());
+        let mut shutdown = pin!(shutdown);
+        run_schedule(&schedule, &plan, now, &mut shutdown, async |_| {
+            times.borrow_mut().push(now());
+            times.borrow().len() == runs
+        })
+        .await;
+        times.into_inner()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_until_the_wall_clock_time() {
+        let now = steady(local(1, 8, 0));
+        let started = Instant::now();
+        wait_until(local(1, 8, 5), &now).await;
+        assert_eq!(started.elapsed(), Duration::from_secs(300));
+        wait_until(local(1, 7, 0), &now).await;
+        assert_eq!(started.elapsed(), Duration::from_secs(300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wakes_up_soon_after_a_suspend() {
+        let now = clock(
+            local(1, 8, 0),
+            Duration::from_secs(30),
+            chrono::Duration::hours(2),
+        );
+        let started = Instant::now();
+        wait_until(local(1, 9, 0), &now).await;
+        assert_eq!(started.elapsed(), CHECK_INTERVAL);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn catches_up_then_runs_at_each_slot() {
+        let times = runs(steady(local(1, 8, 0)), 3, pending()).await;
+        assert_eq!(times, [local(1, 8, 0), local(1, 9, 0), local(1, 21, 0)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn folds_slots_missed_while_suspended_into_one_run() {
+        // Suspended for a day half an hour into the wait for 09:00.
+        let now = clock(
+            local(1, 8, 0),
+            Duration::from_secs(1800),
+            chrono::Duration::days(1),
+        );
+        let times = runs(now, 3, pending()).await;
+        assert_eq!(times, [local(1, 8, 0), local(2, 8, 30), local(2, 9, 0)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_while_waiting() {
+        let started = Instant::now();
+        let times = runs(steady(local(1, 8, 0)), 3, sleep(Duration::from_secs(600))).await;
+        assert_eq!(times, [local(1, 8, 0)]);
+        assert_eq!(started.elapsed(), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn pid_file_replaces_a_stale_pid() {
+        // Above the largest pid Linux hands out.
+        let stale = 4_194_305;
+        std::fs::write(&*DAEMON_PID_FILE, stale.to_string()).unwrap();
+        assert_eq!(recorded_pid(), Some(stale));
+        if let Some(running) = is_running(stale) {
+            assert!(!running);
+            assert_eq!(is_running(std::process::id()), Some(true));
+        }
+
+        let pid_file = PidFile::create();
+        assert_eq!(recorded_pid(), Some(std::process::id()));
+        drop(pid_file);
+        assert_eq!(recorded_pid(), None);
+
+        std::fs::write(&*DAEMON_PID_FILE, "garbage").unwrap();
+        assert_eq!(recorded_pid(), None);
+        std::fs::remove_file(&*DAEMON_PID_FILE).unwrap();
+    }
+}
@@ -1,13 +1,28 @@
-use std::sync::LazyLock;
+use std::{path::PathBuf, sync::LazyLock};
 
-use crate::data::{SAVE_DIR, data_old::transfer_old_data};
+use crate::data::{
+    SAVE_DIR,
+    data_old::{transfer_old_data, transfer_single_account_data, transfer_webhooks},
+    secret,
+    value::seal_plain_secrets,
+};
+use crate::history::transfer_run_logs;
 
 use super::BIN_CONF;
 
-pub static DB: LazyLock<sled::Db> = LazyLock::new(|| {
-    let db_path = SAVE_DIR.join("xrenew.db");
-    sled::open(db_path).expect("Failed to open database")
-});
+fn db_path() -> PathBuf {
+    SAVE_DIR.join("xrenew.db")
+}
+
+pub(super) static DB: LazyLock<sled::Db> =
+    LazyLock::new(|| sled::open(db_path()).expect("Failed to open database"));
+
+/// Opens the database and closes it again, to report why it cannot be
+/// opened instead of panicking. Has to run before anything else uses it,
+/// since the open database stays locked.
+pub fn try_open() -> Result<(), sled::Error> {
+    sled::open(db_path()).map(drop)
+}
 
 pub fn put<K, V>(key: K, value: &V)
 where
@@ -18,6 +33,13 @@ where
         bincode::encode_to_vec(value, BIN_CONF).expect("Failed to serialize value");
     DB.insert(key.as_ref(), serialized_value)
         .expect("Failed to insert into database");
+    flush();
+}
+
+/// The process usually exits right after a write, before sled's background
+/// flush runs.
+fn flush() {
+    DB.flush().expect("Failed to flush database");
 }
 
 pub fn get<K, V>(key: K) -> Option<V>
@@ -31,19 +53,128 @@ where
     Some(data)
 }
 
+/// Like `put`, but the encoded value is encrypted before it is stored.
+pub fn put_secret<K, V>(key: K, value: &V)
+where
+    K: AsRef<[u8]>,
+    V: bincode::Encode,
+{
+    let serialized_value =
+        bincode::encode_to_vec(value, BIN_CONF).expect("Failed to serialize value");
+    DB.insert(key.as_ref(), secret::seal(&serialized_value))
+        .expect("Failed to insert into database");
+    flush();
+}
+
+/// Reads a value written by `put_secret`. Values stored before encryption
+/// was introduced are still read as plain bincode.
+pub fn get_secret<K, V>(key: K) -> Option<V>
+where
+    K: AsRef<[u8]>,
+    V: bincode::Decode<()>,
+{
+    let val = DB.get(key.as_ref()).expect("Failed to get from database")?;
+    let val = if secret::is_sealed(&val) {
+        secret::open(&val)
+    } else {
+        val.to_vec()
+    };
+    let (data, _): (V, usize) =
+        bincode::decode_from_slice(&val, BIN_CONF).expect("Failed to deserialize value");
+    Some(data)
+}
+
+/// Encrypts a value that is still stored in plain form.
+pub fn seal_plain<K>(key: K)
+where
+    K: AsRef<[u8]>,
+{
+    let Some(val) = DB.get(key.as_ref()).expect("Failed to get from database") else {
+        return;
+    };
+    if !secret::is_sealed(&val) {
+        DB.insert(key.as_ref(), secret::seal(&val))
+            .expect("Failed to insert into database");
+        flush();
+    }
+}
+
+/// Values under `prefix`, in key order.
+pub fn scan_prefix<K, V>(prefix: K) -> Vec<V>
+where
+    K: AsRef<[u8]>,
+    V: bincode::Decode<()>,
+{
+    DB.scan_prefix(prefix.as_ref())
+        .values()
+        .map(|val| {
+            let val = val.expect("Failed to scan database");
+            let (data, _): (V, usize) =
+                bincode::decode_from_slice(&val, BIN_CONF).expect("Failed to deserialize value");
+            data
+        })
+        .collect()
+}
+
+/// Keys under `prefix`, in order.
+pub fn scan_keys<K>(prefix: K) -> Vec<Vec<u8>>
+where
+    K: AsRef<[u8]>,
+{
+    DB.scan_prefix(prefix.as_ref())
+        .keys()
+        .map(|key| key.expect("Failed to scan database").to_vec())
+        .collect()
+}
+
 pub fn remove<K>(key: K) -> bool
 where
     K: AsRef<[u8]>,
 {
-    DB.remove(key.as_ref())
+    let removed = DB
+        .remove(key.as_ref())
         .expect("Failed to remove from database")
-        .is_some()
+        .is_some();
+    flush();
+    removed
+}
+
+pub fn remove_prefix<K>(prefix: K)
+where
+    K: AsRef<[u8]>,
+{
+    for key in DB.scan_prefix(prefix.as_ref()).keys() {
+        let key = key.expect("Failed to scan database");
+        DB.remove(key).expect("Failed to remove from database");
+    }
+    flush();
 }
 
 pub fn initialize_db() {
     transfer_old_data();
+    transfer_single_account_data();
+    transfer_webhooks();
+    transfer_run_logs();
+    seal_plain_secrets();
 }
 
+/// Clears the database, then overwrites its files, since sled keeps old
+/// values in its log until the space is reused. The encryption key is
+/// destroyed as well.
 pub fn remove_all() {
     DB.clear().expect("Failed to clear database");
+    flush();
+    if let Ok(entries) = std::fs::read_dir(db_path()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                for file in std::fs::read_dir(&path).into_iter().flatten().flatten() {
+                    secret::wipe_file(&file.path());
+                }
+            }
+            secret::wipe_file(&path);
+        }
+    }
+    std::fs::remove_dir_all(db_path()).ok();
+    secret::destroy_key();
 }
@@ -10,10 +10,14 @@ use bincode::{
 
 use crate::{
     client::Account,
-    data::value::{set_account, set_cookie, set_ua, set_webhook},
+    data::value::{
+        DEFAULT_PROFILE, get_notifiers, list_profiles, profile_key, set_account, set_cookie,
+        set_notifiers, set_ua,
+    },
+    external::{Discord, NotifierConfig},
 };
 
-use super::SAVE_DIR;
+use super::{SAVE_DIR, get, get_secret, remove, wipe_file};
 
 #[derive(Clone, Debug, Encode, Decode)]
 pub struct OldDataV1 {
@@ -47,16 +51,16 @@ pub fn load_old_data(path: &Path) -> OldData {
         let mut reader =
             BufReader::new(std::fs::File::open(path).expect("Failed to open old data file"));
         if let Ok(data) = bincode::decode_from_std_read::<OldDataV2, _, _>(&mut reader, CONF) {
-            return OldData::V2(data);
+            OldData::V2(data)
         } else if let Ok(data) = bincode::decode_from_std_read::<OldDataV1, _, _>(&mut reader, CONF)
         {
-            return OldData::V1(data);
+            OldData::V1(data)
         } else {
             eprintln!("Failed to decode old data");
-            return OldData::None;
+            OldData::None
         }
     } else {
-        return OldData::None;
+        OldData::None
     }
 }
 
@@ -65,24 +69,68 @@ pub fn transfer_old_data() {
     let old_data = load_old_data(&path);
     match old_data {
         OldData::V1(data) => {
-            set_account(&data.account);
+            set_account(DEFAULT_PROFILE, &data.account);
             if let Some(cookie) = data.cookie {
-                set_cookie(&cookie);
+                set_cookie(DEFAULT_PROFILE, &cookie);
             }
-            set_ua(&data.ua);
+            set_ua(DEFAULT_PROFILE, &data.ua);
         }
         OldData::V2(data) => {
-            set_account(&data.account);
+            set_account(DEFAULT_PROFILE, &data.account);
             if let Some(cookie) = data.cookie {
-                set_cookie(&cookie);
+                set_cookie(DEFAULT_PROFILE, &cookie);
             }
-            set_ua(&data.ua);
+            set_ua(DEFAULT_PROFILE, &data.ua);
             if let Some(webhook) = data.webhook {
-                set_webhook(&webhook);
+                add_discord(DEFAULT_PROFILE, webhook);
             }
         }
         OldData::None => return,
     }
     println!("Old data transferred successfully");
-    std::fs::remove_file(&path).ok();
+    wipe_file(&path);
+}
+
+/// Moves the single-account keys used before profiles existed into the
+/// `default` profile.
+pub fn transfer_single_account_data() {
+    let Some(account) = get::<_, Account>(b"account_v1") else {
+        return;
+    };
+    set_account(DEFAULT_PROFILE, &account);
+    if let Some(ua) = get::<_, String>(b"user_agent_v1") {
+        set_ua(DEFAULT_PROFILE, &ua);
+    }
+    if let Some(cookie) = get::<_, String>(b"cookie_v1") {
+        set_cookie(DEFAULT_PROFILE, &cookie);
+    }
+    if let Some(webhook) = get::<_, String>(b"webhook_v1") {
+        add_discord(DEFAULT_PROFILE, webhook);
+    }
+    for key in [
+        b"account_v1".as_slice(),
+        b"user_agent_v1",
+        b"cookie_v1",
+        b"webhook_v1",
+    ] {
+        remove(key);
+    }
+    println!("Account moved to profile '{}'", DEFAULT_PROFILE);
+}
+
+fn add_discord(profile: &str, url: String) {
+    let mut notifiers = get_notifiers(profile).unwrap_or_default();
+    notifiers.push(NotifierConfig::Discord(Discord { url }));
+    set_notifiers(profile, &notifiers);
+}
+
+/// Turns the single Discord webhook of each profile into a notifier.
+pub fn transfer_webhooks() {
+    for profile in list_profiles() {
+        let key = profile_key(&profile, "webhook_v1");
+        if let Some(url) = get_secret::<_, String>(&key) {
+            add_discord(&profile, url);
+            remove(&key);
+        }
+    }
 }
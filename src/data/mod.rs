@@ -1,9 +1,20 @@
+#[allow(clippy::module_inception)]
 mod data;
 mod data_old;
 mod path;
+mod secret;
 mod util;
 
 pub mod value;
-pub use data::{get, initialize_db, put, remove, remove_all};
-pub use path::SAVE_DIR;
+pub use data::{
+    get, get_secret, initialize_db, put, put_secret, remove, remove_all, remove_prefix, scan_keys,
+    scan_prefix, seal_plain, try_open,
+};
+pub use path::{CONFIG_FILE, DAEMON_PID_FILE, KEY_FILE, SAVE_DIR};
+pub use secret::{
+    KeyConfig, KeySource, NewKey, PASSPHRASE_VAR, check_key, rekey, rotate_data_key, wipe_file,
+};
 pub use util::BIN_CONF;
+
+#[cfg(test)]
+pub use secret::tests::lock as lock_keys;
@@ -6,6 +6,12 @@ use std::{
 use directories::ProjectDirs;
 
 pub static SAVE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    if cfg!(test) {
+        // Tests never touch the real data directory.
+        let d = std::env::temp_dir().join(format!("xrenew-test-{}", std::process::id()));
+        std::fs::create_dir_all(&d).ok();
+        return d;
+    }
     if cfg!(target_os = "windows") && Path::new("data").is_dir() {
         PathBuf::from("data")
     } else {
@@ -18,3 +24,32 @@ pub static SAVE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
         }
     }
 });
+
+/// Default master key, kept outside `SAVE_DIR` so a copy of the data
+/// directory alone does not reveal the stored credentials.
+pub static KEY_FILE: LazyLock<PathBuf> = LazyLock::new(|| {
+    if cfg!(test) {
+        return SAVE_DIR.join("key");
+    }
+    ProjectDirs::from("", "", "xrenew")
+        .map(|p| p.config_dir().join("key"))
+        .unwrap_or_else(|| SAVE_DIR.join("key"))
+});
+
+/// Holds the pid of a running `xrenew daemon`, so `doctor` can tell that it
+/// is what schedules the runs.
+pub static DAEMON_PID_FILE: LazyLock<PathBuf> = LazyLock::new(|| SAVE_DIR.join("daemon.pid"));
+
+/// Optional settings file, see `crate::config`. `XRENEW_CONFIG` points
+/// elsewhere, e.g. to a file mounted into a container.
+pub static CONFIG_FILE: LazyLock<PathBuf> = LazyLock::new(|| {
+    if let Some(path) = std::env::var_os("XRENEW_CONFIG") {
+        return PathBuf::from(path);
+    }
+    if cfg!(test) {
+        return SAVE_DIR.join("config.toml");
+    }
+    ProjectDirs::from("", "", "xrenew")
+        .map(|p| p.config_dir().join("config.toml"))
+        .unwrap_or_else(|| SAVE_DIR.join("config.toml"))
+});
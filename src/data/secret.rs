@@ -0,0 +1,530 @@
+use std::{
+    fs,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use argon2::Argon2;
+use bincode::{Decode, Encode};
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, rand_core::RngCore},
+};
+
+use super::{BIN_CONF, KEY_FILE, data::DB};
+use crate::data::value::{KEY_CONFIG_KEY, get_key_config};
+
+/// Where automatic runs get the passphrase from.
+pub const PASSPHRASE_VAR: &str = "XRENEW_PASSPHRASE";
+
+/// Marks a sealed database value.
+const MAGIC: &[u8] = b"xrenc1";
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+type Key = [u8; KEY_LEN];
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub enum KeySource {
+    /// 32 raw bytes in a file only the owner can read.
+    File(String),
+    /// Argon2id over a passphrase.
+    Passphrase { salt: [u8; 16] },
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::File(path) => write!(f, "key file {}", path),
+            KeySource::Passphrase { .. } => write!(f, "passphrase"),
+        }
+    }
+}
+
+/// Secrets are sealed with a random data key, which is in turn sealed with
+/// the master key obtained from `source`.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct KeyConfig {
+    pub source: KeySource,
+    data_key: Vec<u8>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("Key file {0}: {1}")]
+    KeyFile(String, std::io::Error),
+    #[error("Key file {0} must contain exactly 32 bytes")]
+    KeyFileLength(String),
+    #[error("Key file {0} is accessible by other users, run `chmod 600 {0}`")]
+    KeyFilePermissions(String),
+    #[error("Passphrase required: set XRENEW_PASSPHRASE or run from a terminal")]
+    PassphraseRequired,
+    #[error("Passphrases do not match")]
+    PassphraseMismatch,
+    #[error("Wrong passphrase or key file")]
+    WrongKey,
+    #[error("Stored value is corrupted")]
+    Corrupted,
+}
+
+/// Which master key `rekey` should switch to.
+pub enum NewKey {
+    /// A fresh random key in the default key file.
+    Generate,
+    /// An existing key file, or a new one created at this path.
+    File(PathBuf),
+    Passphrase,
+}
+
+#[derive(Clone, Copy)]
+struct Keys {
+    master: Key,
+    data: Key,
+}
+
+static KEYS: Mutex<Option<Keys>> = Mutex::new(None);
+
+fn random_key() -> Key {
+    let mut key = [0; KEY_LEN];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+fn seal_with(key: &Key, plain: &[u8]) -> Vec<u8> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = cipher
+        .encrypt(&nonce, plain)
+        .expect("Failed to encrypt value");
+    [MAGIC, nonce.as_slice(), &sealed].concat()
+}
+
+fn open_with(key: &Key, sealed: &[u8]) -> Result<Vec<u8>, SecretError> {
+    let body = sealed.strip_prefix(MAGIC).ok_or(SecretError::Corrupted)?;
+    if body.len() < NONCE_LEN {
+        return Err(SecretError::Corrupted);
+    }
+    let (nonce, sealed) = body.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), sealed)
+        .map_err(|_| SecretError::WrongKey)
+}
+
+pub fn is_sealed(value: &[u8]) -> bool {
+    value.starts_with(MAGIC)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8; 16]) -> Key {
+    let mut key = [0; KEY_LEN];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .expect("Failed to derive key");
+    key
+}
+
+fn read_passphrase(env: &str, prompt: &str) -> Result<String, SecretError> {
+    if let Ok(passphrase) = std::env::var(env) {
+        return Ok(passphrase);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(SecretError::PassphraseRequired);
+    }
+    rpassword::prompt_password(prompt).map_err(|_| SecretError::PassphraseRequired)
+}
+
+fn read_key_file(path: &Path) -> Result<Key, SecretError> {
+    let name = path.display().to_string();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let meta = fs::metadata(path).map_err(|e| SecretError::KeyFile(name.clone(), e))?;
+        if meta.permissions().mode() & 0o077 != 0 {
+            return Err(SecretError::KeyFilePermissions(name));
+        }
+    }
+    let bytes = fs::read(path).map_err(|e| SecretError::KeyFile(name.clone(), e))?;
+    bytes
+        .try_into()
+        .map_err(|_| SecretError::KeyFileLength(name))
+}
+
+fn create_key_file(path: &Path) -> Result<Key, SecretError> {
+    let key = random_key();
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        file.write_all(&key)?;
+        file.sync_all()
+    };
+    write().map_err(|e| SecretError::KeyFile(path.display().to_string(), e))?;
+    Ok(key)
+}
+
+fn open_or_create_key_file(path: &Path) -> Result<Key, SecretError> {
+    if path.exists() {
+        read_key_file(path)
+    } else {
+        create_key_file(path)
+    }
+}
+
+fn master_key(source: &KeySource) -> Result<Key, SecretError> {
+    match source {
+        KeySource::File(path) => read_key_file(Path::new(path)),
+        KeySource::Passphrase { salt } => Ok(derive_key(
+            &read_passphrase(PASSPHRASE_VAR, "Passphrase for stored credentials: ")?,
+            salt,
+        )),
+    }
+}
+
+fn store_config(config: &KeyConfig, batch: &mut sled::Batch) {
+    let encoded = bincode::encode_to_vec(config, BIN_CONF).expect("Failed to serialize value");
+    batch.insert(KEY_CONFIG_KEY, encoded);
+}
+
+/// Loads the keys, creating the default key file the first time a secret
+/// is stored.
+fn unlock() -> Result<Keys, SecretError> {
+    let mut cached = KEYS.lock().unwrap();
+    if let Some(keys) = *cached {
+        return Ok(keys);
+    }
+    let keys = match get_key_config() {
+        Some(config) => {
+            let master = master_key(&config.source)?;
+            let data = match open_with(&master, &config.data_key) {
+                Err(SecretError::WrongKey) => finish_rekey(&config, &master)?,
+                data => data?,
+            };
+            let data = data.try_into().map_err(|_| SecretError::Corrupted)?;
+            Keys { master, data }
+        }
+        None => {
+            let master = open_or_create_key_file(&KEY_FILE)?;
+            let data = random_key();
+            let mut batch = sled::Batch::default();
+            store_config(
+                &KeyConfig {
+                    source: KeySource::File(KEY_FILE.display().to_string()),
+                    data_key: seal_with(&master, &data),
+                },
+                &mut batch,
+            );
+            DB.apply_batch(batch).expect("Failed to write database");
+            DB.flush().expect("Failed to flush database");
+            Keys { master, data }
+        }
+    };
+    *cached = Some(keys);
+    Ok(keys)
+}
+
+/// Where `rekey` keeps the replaced default key file until the database
+/// refers to the new one.
+fn previous_key_file() -> PathBuf {
+    KEY_FILE.with_extension("old")
+}
+
+/// Completes a `rekey` to a generated key file that was interrupted after
+/// the new file took the place of the old one but before the database was
+/// updated, by sealing the data key with the new master key.
+fn finish_rekey(config: &KeyConfig, master: &Key) -> Result<Vec<u8>, SecretError> {
+    let previous = previous_key_file();
+    let default =
+        matches!(&config.source, KeySource::File(p) if Path::new(p) == KEY_FILE.as_path());
+    if !default || !previous.exists() {
+        return Err(SecretError::WrongKey);
+    }
+    let data = open_with(&read_key_file(&previous)?, &config.data_key)?;
+    let mut batch = sled::Batch::default();
+    store_config(
+        &KeyConfig {
+            source: config.source.clone(),
+            data_key: seal_with(master, &data),
+        },
+        &mut batch,
+    );
+    DB.apply_batch(batch).expect("Failed to write database");
+    DB.flush().expect("Failed to flush database");
+    wipe_file(&previous);
+    Ok(data)
+}
+
+/// Makes a rename in `path`'s directory durable.
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Puts a new key file, already written and synced at `pending`, in place
+/// of the default one. The old file stays reachable as `previous_key_file`
+/// until the database refers to the new key.
+fn replace_key_file(pending: &Path) -> std::io::Result<()> {
+    let previous = previous_key_file();
+    if previous.exists() {
+        wipe_file(&previous);
+    }
+    if KEY_FILE.exists() {
+        fs::hard_link(&*KEY_FILE, &previous)?;
+    }
+    fs::rename(pending, &*KEY_FILE)?;
+    sync_parent(&KEY_FILE)
+}
+
+fn keys() -> Keys {
+    unlock().unwrap_or_else(|e| {
+        eprintln!("Failed to unlock stored credentials: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Whether the stored credentials can be decrypted, without exiting when
+/// they cannot. `None` before the first secret is stored, in which case no
+/// key is created.
+pub fn check_key() -> Result<Option<KeySource>, SecretError> {
+    let Some(config) = get_key_config() else {
+        return Ok(None);
+    };
+    unlock()?;
+    Ok(Some(config.source))
+}
+
+pub fn seal(plain: &[u8]) -> Vec<u8> {
+    seal_with(&keys().data, plain)
+}
+
+pub fn open(sealed: &[u8]) -> Vec<u8> {
+    open_with(&keys().data, sealed).unwrap_or_else(|e| {
+        eprintln!("Failed to decrypt stored credentials: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Re-seals every sealed value under a fresh data key wrapped by `master`.
+fn rotate(master: Key, source: KeySource) -> Result<(), SecretError> {
+    let old = unlock()?;
+    let data = random_key();
+    let mut batch = sled::Batch::default();
+    for entry in DB.iter() {
+        let (key, value) = entry.expect("Failed to read database");
+        if is_sealed(&value) {
+            batch.insert(key, seal_with(&data, &open_with(&old.data, &value)?));
+        }
+    }
+    store_config(
+        &KeyConfig {
+            source,
+            data_key: seal_with(&master, &data),
+        },
+        &mut batch,
+    );
+    DB.apply_batch(batch).expect("Failed to write database");
+    DB.flush().expect("Failed to flush database");
+    *KEYS.lock().unwrap() = Some(Keys { master, data });
+    Ok(())
+}
+
+/// Replaces the data key while keeping the master key, so copies of removed
+/// values left in old database segments can no longer be decrypted.
+pub fn rotate_data_key() -> Result<(), SecretError> {
+    let Some(config) = get_key_config() else {
+        return Ok(());
+    };
+    rotate(unlock()?.master, config.source)
+}
+
+/// Switches to a new master key and re-encrypts everything with it.
+pub fn rekey(new: NewKey) -> Result<KeySource, SecretError> {
+    let old_source = get_key_config().map(|c| c.source);
+    unlock()?;
+    let pending = KEY_FILE.with_extension("new");
+    let (master, source) = match &new {
+        NewKey::Generate => {
+            if pending.exists() {
+                wipe_file(&pending);
+            }
+            let master = create_key_file(&pending)?;
+            replace_key_file(&pending)
+                .map_err(|e| SecretError::KeyFile(KEY_FILE.display().to_string(), e))?;
+            (master, KeySource::File(KEY_FILE.display().to_string()))
+        }
+        NewKey::File(path) => (
+            open_or_create_key_file(path)?,
+            KeySource::File(path.display().to_string()),
+        ),
+        NewKey::Passphrase => {
+            let passphrase = read_passphrase("XRENEW_NEW_PASSPHRASE", "New passphrase: ")?;
+            if std::env::var("XRENEW_NEW_PASSPHRASE").is_err()
+                && rpassword::prompt_password("Repeat passphrase: ")
+                    .map_err(|_| SecretError::PassphraseRequired)?
+                    != passphrase
+            {
+                return Err(SecretError::PassphraseMismatch);
+            }
+            let mut salt = [0; 16];
+            OsRng.fill_bytes(&mut salt);
+            (
+                derive_key(&passphrase, &salt),
+                KeySource::Passphrase { salt },
+            )
+        }
+    };
+    rotate(master, source.clone())?;
+
+    let default_file = KEY_FILE.display().to_string();
+    let was_default = matches!(&old_source, Some(KeySource::File(p)) if *p == default_file);
+    match new {
+        NewKey::Generate => wipe_file(&previous_key_file()),
+        NewKey::File(path) if path == *KEY_FILE => {}
+        _ if was_default => wipe_file(&KEY_FILE),
+        _ => {}
+    }
+    Ok(source)
+}
+
+/// Destroys the default key file, which makes any sealed value that
+/// survives in the database files unreadable.
+pub fn destroy_key() {
+    *KEYS.lock().unwrap() = None;
+    if KEY_FILE.exists() {
+        wipe_file(&KEY_FILE);
+    }
+}
+
+/// Overwrites a file with random bytes before deleting it.
+pub fn wipe_file(path: &Path) {
+    if let Ok(meta) = fs::metadata(path)
+        && let Ok(mut file) = fs::OpenOptions::new().write(true).open(path)
+    {
+        let mut noise = vec![0; meta.len() as usize];
+        OsRng.fill_bytes(&mut noise);
+        file.write_all(&noise).ok();
+        file.sync_all().ok();
+    }
+    fs::remove_file(path).ok();
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::MutexGuard;
+
+    use super::*;
+    use crate::data::{
+        get_secret, put, put_secret,
+        value::{add_profile, get_cookie, profile_key, seal_plain_secrets},
+    };
+
+    /// The key configuration is shared by the whole test process.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Held by tests that change the keys, and by other tests that store
+    /// secrets, which a concurrent rotation would leave unreadable.
+    pub fn lock() -> MutexGuard<'static, ()> {
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        forget_keys();
+        guard
+    }
+
+    /// Makes the next access read the keys from disk again.
+    fn forget_keys() {
+        *KEYS.lock().unwrap() = None;
+    }
+
+    fn raw(key: &str) -> Vec<u8> {
+        DB.get(key).unwrap().unwrap().to_vec()
+    }
+
+    fn read(key: &str) -> Option<String> {
+        get_secret(key)
+    }
+
+    #[test]
+    fn key_file_round_trip() {
+        let _guard = lock();
+        put_secret("test/key_file", &"secret".to_string());
+        assert!(is_sealed(&raw("test/key_file")));
+        assert!(KEY_FILE.exists());
+        forget_keys();
+        assert_eq!(read("test/key_file").as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let salt = [7; 16];
+        let data = random_key();
+        let sealed = seal_with(&derive_key("correct horse", &salt), &data);
+        let opened = open_with(&derive_key("correct horse", &salt), &sealed).unwrap();
+        assert_eq!(opened, data);
+        assert!(matches!(
+            open_with(&derive_key("wrong horse", &salt), &sealed),
+            Err(SecretError::WrongKey)
+        ));
+    }
+
+    #[test]
+    fn rotation_keeps_values_readable() {
+        let _guard = lock();
+        put_secret("test/rotate", &"secret".to_string());
+        let before = raw("test/rotate");
+        rotate_data_key().unwrap();
+        assert_ne!(raw("test/rotate"), before);
+        forget_keys();
+        assert_eq!(read("test/rotate").as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn generated_key_replaces_key_file() {
+        let _guard = lock();
+        put_secret("test/rekey", &"secret".to_string());
+        let old = fs::read(&*KEY_FILE).unwrap();
+        rekey(NewKey::Generate).unwrap();
+        assert_ne!(fs::read(&*KEY_FILE).unwrap(), old);
+        assert!(!previous_key_file().exists());
+        forget_keys();
+        assert_eq!(read("test/rekey").as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn interrupted_rekey_is_finished_on_unlock() {
+        let _guard = lock();
+        put_secret("test/interrupted", &"secret".to_string());
+        // Stop `rekey` right after the new key file took the old one's place.
+        let pending = KEY_FILE.with_extension("new");
+        wipe_file(&pending);
+        create_key_file(&pending).unwrap();
+        replace_key_file(&pending).unwrap();
+        forget_keys();
+        assert_eq!(read("test/interrupted").as_deref(), Some("secret"));
+        assert!(!previous_key_file().exists());
+        forget_keys();
+        assert_eq!(read("test/interrupted").as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn plain_secrets_are_sealed() {
+        let _guard = lock();
+        add_profile("sealtest");
+        let key = profile_key("sealtest", "cookie_v1");
+        put(&key, &"cookie".to_string());
+        assert!(!is_sealed(&raw(&key)));
+        seal_plain_secrets();
+        assert!(is_sealed(&raw(&key)));
+        assert_eq!(get_cookie("sealtest").as_deref(), Some("cookie"));
+    }
+}
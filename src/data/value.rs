@@ -1,6 +1,12 @@
 #[macro_export]
 macro_rules! db_accessors {
     ($base:ident, $key:expr, $ty:ty $(,)?) => {
+        $crate::db_accessors!(@impl $base, $key, $ty, put, get);
+    };
+    ($base:ident, $key:expr, $ty:ty, secret $(,)?) => {
+        $crate::db_accessors!(@impl $base, $key, $ty, put_secret, get_secret);
+    };
+    (@impl $base:ident, $key:expr, $ty:ty, $put:ident, $get:ident) => {
         ::paste::paste! {
             /// Setter：`set_<base>(value)`
             #[allow(dead_code)]
@@ -8,7 +14,7 @@ macro_rules! db_accessors {
             where
                 $ty: ::bincode::Encode,
             {
-                $crate::data::put($key, value);
+                $crate::data::$put($key, value);
             }
 
             #[allow(dead_code)]
@@ -17,7 +23,7 @@ macro_rules! db_accessors {
             where
                 $ty: ::bincode::Decode<()>,
             {
-                $crate::data::get($key)
+                $crate::data::$get($key)
             }
 
             #[allow(dead_code)]
@@ -29,8 +35,160 @@ macro_rules! db_accessors {
     };
 }
 
-db_accessors!(account, b"account_v1", crate::client::Account);
-db_accessors!(ua, b"user_agent_v1", String);
-db_accessors!(cookie, b"cookie_v1", String);
-db_accessors!(webhook, b"webhook_v1", String);
-db_accessors!(two_captcha_key, b"two_captcha_key", String);
+#[macro_export]
+macro_rules! profile_accessors {
+    ($base:ident, $key:expr, $ty:ty $(,)?) => {
+        $crate::profile_accessors!(@impl $base, $key, $ty, put, get);
+    };
+    ($base:ident, $key:expr, $ty:ty, secret $(,)?) => {
+        $crate::profile_accessors!(@impl $base, $key, $ty, put_secret, get_secret);
+    };
+    (@impl $base:ident, $key:expr, $ty:ty, $put:ident, $get:ident) => {
+        ::paste::paste! {
+            /// Setter：`set_<base>(profile, value)`
+            #[allow(dead_code)]
+            pub fn [<set_ $base>](profile: &str, value: &$ty)
+            where
+                $ty: ::bincode::Encode,
+            {
+                $crate::data::value::add_profile(profile);
+                $crate::data::$put($crate::data::value::profile_key(profile, $key), value);
+            }
+
+            #[allow(dead_code)]
+            /// Getter：`get_<base>(profile) -> Option<_>`
+            pub fn [<get_ $base>](profile: &str) -> Option<$ty>
+            where
+                $ty: ::bincode::Decode<()>,
+            {
+                $crate::data::$get($crate::data::value::profile_key(profile, $key))
+            }
+
+            #[allow(dead_code)]
+            /// Remover：`remove_<base>(profile) -> bool`
+            pub fn [<remove_ $base>](profile: &str) -> bool {
+                $crate::data::remove($crate::data::value::profile_key(profile, $key))
+            }
+        }
+    };
+}
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub fn profile_key(profile: &str, key: &str) -> String {
+    format!("profile/{}/{}", profile, key)
+}
+
+/// Checks a profile name: it becomes part of database keys and log file
+/// names, so only ASCII letters, digits, `_` and `-` are accepted.
+pub fn parse_profile(name: &str) -> Result<String, String> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if name.is_empty() || !valid {
+        return Err(format!(
+            "'{}' is not a valid profile name (use A-Z, a-z, 0-9, '_' and '-')",
+            name
+        ));
+    }
+    Ok(name.to_string())
+}
+
+db_accessors!(stored_profiles, b"profiles_v1", Vec<String>);
+
+/// Registered profile names, `default` first when present.
+pub fn list_profiles() -> Vec<String> {
+    get_stored_profiles().unwrap_or_default()
+}
+
+/// Stores the profile list, refusing names `parse_profile` rejects.
+pub fn set_profiles(profiles: &[String]) {
+    for profile in profiles {
+        if let Err(e) = parse_profile(profile) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    set_stored_profiles(&profiles.to_vec());
+}
+
+pub fn add_profile(profile: &str) {
+    let mut profiles = list_profiles();
+    if !profiles.iter().any(|p| p == profile) {
+        profiles.push(profile.to_string());
+        profiles.sort_by_key(|p| (p != DEFAULT_PROFILE, p.clone()));
+        set_profiles(&profiles);
+    }
+}
+
+/// Removes the profile and every value stored under it.
+pub fn remove_profile(profile: &str) -> bool {
+    let mut profiles = list_profiles();
+    let len = profiles.len();
+    profiles.retain(|p| p != profile);
+    set_profiles(&profiles);
+    crate::data::remove_prefix(profile_key(profile, ""));
+    profiles.len() != len
+}
+
+profile_accessors!(account, "account_v1", crate::client::Account, secret);
+profile_accessors!(imap, "imap_v1", crate::external::ImapConfig, secret);
+profile_accessors!(ua, "user_agent_v1", String);
+profile_accessors!(cookie, "cookie_v1", String, secret);
+// Email of the account the saved cookie was issued to.
+profile_accessors!(cookie_email, "cookie_email_v1", String, secret);
+profile_accessors!(
+    notifiers,
+    "notifiers_v1",
+    Vec<crate::external::NotifierConfig>,
+    secret,
+);
+profile_accessors!(servers, "servers_v1", Vec<crate::client::Server>);
+profile_accessors!(
+    notify_policy,
+    "notify_policy_v1",
+    crate::policy::NotifyPolicy
+);
+profile_accessors!(notify_snapshot, "notify_snapshot_v1", bool);
+db_accessors!(telemetry, b"telemetry_v1", crate::external::Telemetry);
+db_accessors!(two_captcha_key, b"two_captcha_key", String, secret);
+db_accessors!(anti_captcha_key, b"anti_captcha_key_v1", String, secret);
+db_accessors!(capsolver_key, b"capsolver_key_v1", String, secret);
+db_accessors!(
+    ocr_templates,
+    b"ocr_templates_v1",
+    crate::external::OcrTemplates
+);
+db_accessors!(
+    captcha_solvers,
+    b"captcha_solvers_v1",
+    Vec<crate::external::SolverKind>
+);
+db_accessors!(retry_policy, b"retry_policy_v1", crate::retry::RetryPolicy);
+db_accessors!(schedule, b"schedule_v1", crate::schedule::Plan);
+// The schedule the timer was last registered with, for `status`.
+db_accessors!(timer_plan, b"timer_plan_v1", crate::schedule::Plan);
+db_accessors!(timer_backend, b"timer_backend_v1", crate::task::Backend);
+
+pub const KEY_CONFIG_KEY: &[u8] = b"key_config_v1";
+db_accessors!(key_config, KEY_CONFIG_KEY, crate::data::KeyConfig);
+
+/// Keys declared with `secret` above.
+const SECRET_KEYS: [&[u8]; 3] = [
+    b"two_captcha_key",
+    b"anti_captcha_key_v1",
+    b"capsolver_key_v1",
+];
+const PROFILE_SECRET_KEYS: [&str; 4] = ["account_v1", "imap_v1", "cookie_v1", "notifiers_v1"];
+
+/// Encrypts credentials written by versions without encryption.
+pub fn seal_plain_secrets() {
+    for key in SECRET_KEYS {
+        crate::data::seal_plain(key);
+    }
+    for profile in list_profiles() {
+        for key in PROFILE_SECRET_KEYS {
+            crate::data::seal_plain(profile_key(&profile, key));
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! `xrenew doctor`: checks that automatic runs can work, from the timer
+//! down to the panel being reachable, and says how to fix what cannot.
+
+use std::fmt::Display;
+
+use chrono::Local;
+
+use crate::{
+    client::{EMAIL_VAR, account_profiles},
+    config::{self, Source},
+    daemon,
+    data::{self, KeySource, PASSPHRASE_VAR, SAVE_DIR, initialize_db, value},
+    endpoint::ENDPOINTS,
+    external::{CaptchaKind, SolverKind},
+    history::{self, Outcome},
+    task::{Backend, installed_backend, lingering, user},
+};
+
+#[derive(Default)]
+struct Report {
+    failed: bool,
+    /// What has been printed so far.
+    lines: Vec<String>,
+}
+
+impl Report {
+    fn print(&mut self, line: String) {
+        println!("{}", line);
+        self.lines.push(line);
+    }
+
+    fn pass(&mut self, name: &str, detail: impl Display) {
+        self.print(format!("[ OK ] {}: {}", name, detail));
+    }
+
+    fn fail(&mut self, name: &str, detail: impl Display, hint: impl Display) {
+        self.failed = true;
+        self.print(format!("[FAIL] {}: {}", name, detail));
+        self.print(format!("       -> {}", hint));
+    }
+
+    /// A check that does not apply or cannot tell.
+    fn skip(&mut self, name: &str, detail: impl Display) {
+        self.print(format!("[SKIP] {}: {}", name, detail));
+    }
+}
+
+fn check_save_dir(report: &mut Report) {
+    let probe = SAVE_DIR.join(".doctor");
+    match std::fs::write(&probe, b"").and_then(|_| std::fs::remove_file(&probe)) {
+        Ok(()) => report.pass("Data directory", SAVE_DIR.display()),
+        Err(e) => report.fail(
+            "Data directory",
+            format!("{} is not writable: {}", SAVE_DIR.display(), e),
+            format!(
+                "make it writable for {}, or point XDG_DATA_HOME elsewhere",
+                user()
+            ),
+        ),
+    }
+}
+
+/// Whether the database opens. It stays open afterwards, so this runs
+/// before anything else reads it.
+fn check_database(report: &mut Report) -> bool {
+    let path = SAVE_DIR.join("xrenew.db");
+    match data::try_open() {
+        Ok(()) => {
+            report.pass("Database", path.display());
+            initialize_db();
+            true
+        }
+        Err(e) => {
+            report.fail(
+                "Database",
+                format!("{}: {}", path.display(), e),
+                "wait for the running xrenew (e.g. a timer run) to finish, or check the owner of the directory",
+            );
+            false
+        }
+    }
+}
+
+fn check_config(report: &mut Report) -> bool {
+    let errors = config::errors();
+    if errors.is_empty() {
+        report.pass("Configuration", data::CONFIG_FILE.display());
+        return true;
+    }
+    for e in &errors {
+        report.fail(
+            "Configuration",
+            e,
+            "fix the value, then check again with 'xrenew config validate'",
+        );
+    }
+    false
+}
+
+/// Returns whether the key is derived from a passphrase, which automatic
+/// runs then need in their environment. The accounts are only listed with
+/// a valid configuration, which may define them.
+fn check_credentials(report: &mut Report, config_ok: bool) -> bool {
+    let passphrase = match data::check_key() {
+        Ok(Some(source)) => {
+            report.pass("Encryption key", &source);
+            matches!(source, KeySource::Passphrase { .. })
+        }
+        Ok(None) => {
+            report.skip(
+                "Encryption key",
+                "no key yet, one is created on 'xrenew login'",
+            );
+            false
+        }
+        Err(e) => {
+            report.fail(
+                "Encryption key",
+                e,
+                "automatic runs need the same key; restore it, or start over with 'xrenew clear'",
+            );
+            return false;
+        }
+    };
+    if !config_ok {
+        report.skip("Credentials", "they need a valid configuration");
+        return passphrase;
+    }
+    let profiles = account_profiles();
+    if profiles.is_empty() {
+        report.fail(
+            "Credentials",
+            "no account",
+            format!("run 'xrenew login', or set {} for each run", EMAIL_VAR),
+        );
+    }
+    for profile in profiles {
+        let name = format!("Credentials ({})", profile);
+        match config::account(&profile) {
+            (Some(account), Source::Stored) => report.pass(&name, account.email),
+            (Some(account), source) => {
+                report.pass(&name, format!("{} (from {})", account.email, source))
+            }
+            (None, _) => report.fail(
+                &name,
+                "no account",
+                format!("run 'xrenew --profile {} login'", profile),
+            ),
+        }
+    }
+    passphrase
+}
+
+fn check_solvers(report: &mut Report) {
+    // Automatic runs have no terminal to ask on.
+    let order: Vec<SolverKind> = config::captcha_order()
+        .into_iter()
+        .filter(|k| *k != SolverKind::Manual)
+        .collect();
+    for kind in &order {
+        let name = format!("Captcha solver ({})", kind);
+        let key = match kind {
+            SolverKind::Local => {
+                match value::get_ocr_templates().filter(|t| t.digits.len() == 10) {
+                    Some(_) => report.pass(&name, "trained"),
+                    None => report.skip(&name, "not trained ('xrenew ocr train'), so not used"),
+                }
+                continue;
+            }
+            SolverKind::Hosted => {
+                report.pass(&name, "no key needed");
+                continue;
+            }
+            SolverKind::TwoCaptcha => value::get_two_captcha_key(),
+            SolverKind::AntiCaptcha => value::get_anti_captcha_key(),
+            SolverKind::CapSolver => value::get_capsolver_key(),
+            SolverKind::Manual => unreachable!("filtered out"),
+        };
+        match key {
+            Some(_) => report.pass(&name, "API key set"),
+            None => report.skip(&name, "API key not set, so not used"),
+        }
+    }
+
+    let able = |kind: CaptchaKind| -> Vec<String> {
+        order
+            .iter()
+            .filter(|k| k.solver().supports(kind))
+            .map(ToString::to_string)
+            .collect()
+    };
+    let image = able(CaptchaKind::Image);
+    if image.is_empty() {
+        report.fail(
+            "Image captcha",
+            "no solver can answer it",
+            "add hosted with 'xrenew captcha --order', or set an API key with 'xrenew captcha <KEY>'",
+        );
+    } else {
+        report.pass("Image captcha", image.join(", "));
+    }
+    let turnstile = able(CaptchaKind::Turnstile);
+    if turnstile.is_empty() {
+        report.fail(
+            "Turnstile",
+            "no solver can answer it, so runs fail when the panel shows one",
+            "set an API key with 'xrenew captcha <KEY> --service 2captcha|anti-captcha|capsolver'",
+        );
+    } else {
+        report.pass("Turnstile", turnstile.join(", "));
+    }
+}
+
+/// The innermost error, e.g. the DNS failure behind "error sending
+/// request".
+fn root_cause(e: &reqwest::Error) -> String {
+    let mut e: &dyn std::error::Error = e;
+    while let Some(source) = e.source() {
+        e = source;
+    }
+    e.to_string()
+}
+
+async fn check_endpoints(report: &mut Report) {
+    let mut urls = vec![("Panel", ENDPOINTS.panel.to_string())];
+    // Solvers without a key are never asked.
+    let used = config::captcha_order().into_iter().filter(|k| {
+        let solver = k.solver();
+        solver.supports(CaptchaKind::Image) || solver.supports(CaptchaKind::Turnstile)
+    });
+    for kind in used {
+        match kind {
+            SolverKind::Hosted => urls.push(("Hosted solver", ENDPOINTS.service.to_string())),
+            SolverKind::TwoCaptcha => urls.push((
+                "2captcha",
+                format!(
+                    "https://{}/",
+                    ENDPOINTS
+                        .two_captcha_host
+                        .as_deref()
+                        .unwrap_or("2captcha.com")
+                ),
+            )),
+            SolverKind::AntiCaptcha => {
+                urls.push(("Anti-Captcha", ENDPOINTS.anti_captcha.to_string()))
+            }
+            SolverKind::CapSolver => urls.push(("CapSolver", ENDPOINTS.capsolver.to_string())),
+            SolverKind::Local | SolverKind::Manual => {}
+        }
+    }
+    let (telemetry, _) = config::telemetry();
+    if telemetry.enabled {
+        urls.push(("Telemetry", telemetry.endpoint()));
+    }
+
+    let client = config::http_client();
+    for (name, url) in urls {
+        let name = format!("Endpoint ({})", name);
+        // Any response will do; only the connection is checked.
+        match client.get(&url).send().await {
+            Ok(res) => report.pass(&name, format!("{} (HTTP {})", url, res.status().as_u16())),
+            Err(e) => report.fail(
+                &name,
+                format!("{}: {}", url, root_cause(&e)),
+                "check the network, DNS and the proxy (XRENEW_PROXY or proxy in config.toml)",
+            ),
+        }
+    }
+}
+
+fn check_passphrase(report: &mut Report, passed: Option<bool>, hint: &str) {
+    let name = "Passphrase for runs";
+    match passed {
+        Some(true) => report.pass(name, format!("{} is set", PASSPHRASE_VAR)),
+        Some(false) => report.fail(
+            name,
+            format!(
+                "{} is not set, so runs cannot unlock the credentials",
+                PASSPHRASE_VAR
+            ),
+            format!("{}, or switch to a key file with 'xrenew rekey'", hint),
+        ),
+        None => report.skip(
+            name,
+            format!("cannot tell whether {} is set", PASSPHRASE_VAR),
+        ),
+    }
+}
+
+/// `xrenew daemon` run by hand, e.g. as the main process of a container.
+fn check_daemon(report: &mut Report, passphrase: bool) {
+    let not_enabled = "run 'xrenew enable', or keep 'xrenew daemon' running";
+    let Some(pid) = daemon::recorded_pid() else {
+        report.fail("Automatic extension", "not enabled", not_enabled);
+        return;
+    };
+    match daemon::is_running(pid) {
+        Some(true) => report.pass(
+            "Automatic extension",
+            format!("xrenew daemon (pid {})", pid),
+        ),
+        Some(false) => {
+            report.fail(
+                "Automatic extension",
+                format!("xrenew daemon (pid {}) is no longer running", pid),
+                not_enabled,
+            );
+            return;
+        }
+        None => report.skip(
+            "Automatic extension",
+            format!(
+                "xrenew daemon (pid {}) is recorded, but cannot be looked up",
+                pid
+            ),
+        ),
+    }
+    if passphrase {
+        check_passphrase(
+            report,
+            daemon::has_env(pid, PASSPHRASE_VAR),
+            &format!("restart 'xrenew daemon' with {} set", PASSPHRASE_VAR),
+        );
+    }
+    report.skip("Next run", "see the output of 'xrenew daemon'");
+    check_history(report, "the output of 'xrenew daemon'");
+}
+
+fn check_scheduler(report: &mut Report, passphrase: bool) {
+    let Some(backend) = installed_backend() else {
+        check_daemon(report, passphrase);
+        return;
+    };
+    report.pass("Automatic extension", format!("enabled ({})", backend));
+    let refresh = "run 'xrenew refresh' with the executable to use";
+
+    let exe = std::env::current_exe().expect("get exe path");
+    match backend.runs(&exe) {
+        Ok(true) => report.pass("Executable", exe.display()),
+        Ok(false) => report.fail(
+            "Executable",
+            format!("the registration does not run {}", exe.display()),
+            refresh,
+        ),
+        Err(e) => report.fail("Executable", e, refresh),
+    }
+
+    match backend.active() {
+        Ok(()) => report.pass("Timer", "active"),
+        Err(state) => report.fail("Timer", state, refresh),
+    }
+
+    if backend == Backend::SystemdUser {
+        match lingering() {
+            Some(true) => report.pass("Lingering", format!("enabled for {}", user())),
+            Some(false) => report.fail(
+                "Lingering",
+                format!("off, so the timer only runs while {} is logged in", user()),
+                format!("run 'loginctl enable-linger {}'", user()),
+            ),
+            None => report.skip("Lingering", "loginctl cannot tell"),
+        }
+    }
+
+    if passphrase {
+        // The supervised daemon passes its environment on to the runs.
+        let passed = backend
+            .passes_env(PASSPHRASE_VAR)
+            .or_else(|| daemon::has_env(daemon::recorded_pid()?, PASSPHRASE_VAR));
+        let hint = match backend {
+            Backend::SystemdUser | Backend::SystemdSystem => format!(
+                "add Environment={}=... with 'systemctl{} edit xrenew.service'",
+                PASSPHRASE_VAR,
+                if backend == Backend::SystemdUser {
+                    " --user"
+                } else {
+                    ""
+                }
+            ),
+            Backend::Launchd => format!(
+                "add {} to EnvironmentVariables in the plist",
+                PASSPHRASE_VAR
+            ),
+            Backend::Crontab => format!("add a {}=... line to 'crontab -e'", PASSPHRASE_VAR),
+            Backend::Openrc | Backend::Runit => format!(
+                "export {} in the service script, then restart it",
+                PASSPHRASE_VAR
+            ),
+        };
+        check_passphrase(report, passed, &hint);
+    }
+
+    let plan = value::get_timer_plan();
+    let next = backend.next_run().or_else(|| {
+        let next = plan.as_ref()?.next_after(Local::now())?;
+        Some(next.format("%Y-%m-%d %H:%M").to_string())
+    });
+    match (next, plan) {
+        (Some(next), Some(plan)) => report.pass("Next run", format!("{} ({})", next, plan)),
+        (Some(next), None) => report.pass("Next run", next),
+        (None, _) => report.fail("Next run", "unknown", refresh),
+    }
+
+    check_last_run(report, backend);
+}
+
+/// The exit status the init system saw, or the run history when it does
+/// not keep one.
+fn check_last_run(report: &mut Report, backend: Backend) {
+    match backend.last_exit() {
+        Some(Ok(exit)) => report.pass("Last run", exit),
+        Some(Err(exit)) => report.fail("Last run", exit, format!("see {}", backend.log())),
+        None => check_history(report, &backend.log()),
+    }
+}
+
+/// The newest run in the history of any profile. `log` is where its
+/// output went.
+fn check_history(report: &mut Report, log: &str) {
+    let last = value::list_profiles()
+        .iter()
+        .filter_map(|p| history::list(p).pop())
+        .max_by_key(|r| r.at);
+    match last {
+        Some(record) if record.outcome == Outcome::Failure => report.fail(
+            "Last run",
+            record,
+            format!("see 'xrenew history' and {}", log),
+        ),
+        Some(record) => report.pass("Last run", record),
+        None => report.skip("Last run", "none yet"),
+    }
+}
+
+/// Runs every check and exits with 1 when one of them failed.
+pub async fn run_doctor() {
+    let mut report = Report::default();
+    check_save_dir(&mut report);
+    if check_database(&mut report) {
+        let config_ok = check_config(&mut report);
+        let passphrase = check_credentials(&mut report, config_ok);
+        if config_ok {
+            check_solvers(&mut report);
+            check_endpoints(&mut report).await;
+        } else {
+            report.skip("Solvers and endpoints", "they need a valid configuration");
+        }
+        check_scheduler(&mut report, passphrase);
+    } else {
+        report.skip("Remaining checks", "they need the database");
+    }
+    if report.failed {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn report_prints_each_result() {
+        let mut report = Report::default();
+        report.pass("Database", "/data/xrenew.db");
+        report.skip("Last run", "none yet");
+        assert!(!report.failed);
+        report.fail("Next run", "unknown", "run 'xrenew refresh'");
+        assert!(report.failed);
+        assert_eq!(
+            report.lines,
+            [
+                "[ OK ] Database: /data/xrenew.db",
+                "[SKIP] Last run: none yet",
+                "[FAIL] Next run: unknown",
+                "       -> run 'xrenew refresh'",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn root_cause_is_the_innermost_error() {
+        // A port that was just free, so the connection is refused.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let e = reqwest::get(format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap_err();
+        let cause = root_cause(&e);
+        assert!(!e.to_string().contains("refused"), "{}", e);
+        assert!(cause.to_lowercase().contains("refused"), "{}", cause);
+    }
+
+    #[test]
+    fn check_history_reports_the_newest_run() {
+        let profile = "doctor-history";
+        value::add_profile(profile);
+        let at = |day| Utc.with_ymd_and_hms(2100, 1, day, 0, 0, 0).unwrap();
+        let mut failure = history::RunRecord::new(profile, Outcome::Failure, "Login failed");
+        failure.at = at(1);
+        history::store(&[failure]);
+
+        let mut report = Report::default();
+        check_history(&mut report, "/var/log/xrenew.log");
+        assert!(report.failed);
+        assert!(
+            report.lines[0].starts_with("[FAIL] Last run: "),
+            "{:?}",
+            report.lines
+        );
+        assert!(report.lines[0].ends_with("FAILURE Login failed"));
+        assert_eq!(
+            report.lines[1],
+            "       -> see 'xrenew history' and /var/log/xrenew.log"
+        );
+
+        let mut success = history::RunRecord::new(profile, Outcome::Success, "Extended");
+        success.at = at(2);
+        history::store(&[success]);
+        let mut report = Report::default();
+        check_history(&mut report, "/var/log/xrenew.log");
+        assert!(!report.failed);
+        assert!(
+            report.lines[0].ends_with("SUCCESS Extended"),
+            "{:?}",
+            report.lines
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::LazyLock;
+
+use url::Url;
+
+pub const DEFAULT_PANEL: &str = "https://secure.xserver.ne.jp/xapanel/";
+pub const DEFAULT_SERVICE: &str = "https://xrenew.hiro.red/";
+pub const DEFAULT_ANTI_CAPTCHA: &str = "https://api.anti-captcha.com/";
+pub const DEFAULT_CAPSOLVER: &str = "https://api.capsolver.com/";
+
+/// Base URLs of the services xrenew talks to. Each one can be replaced in
+/// the `[endpoints]` section of config.toml or with an environment variable,
+/// e.g. to run the whole flow against a local mock of the panel.
+pub struct Endpoints {
+    /// Xserver panel (`XRENEW_PANEL_URL`).
+    pub panel: Url,
+    /// Hosted captcha solver and telemetry collector (`XRENEW_SERVICE_URL`).
+    pub service: Url,
+    /// `XRENEW_ANTI_CAPTCHA_URL`
+    pub anti_captcha: Url,
+    /// `XRENEW_CAPSOLVER_URL`
+    pub capsolver: Url,
+    /// Host name only, the 2captcha client always uses HTTPS
+    /// (`XRENEW_TWO_CAPTCHA_HOST`).
+    pub two_captcha_host: Option<String>,
+}
+
+pub static ENDPOINTS: LazyLock<Endpoints> = LazyLock::new(crate::config::endpoints);
+
+/// Parses a base URL, making sure it ends with a slash so paths are joined
+/// below it.
+pub fn base_url(value: &str) -> Result<Url, String> {
+    let value = if value.ends_with('/') {
+        value.to_string()
+    } else {
+        format!("{}/", value)
+    };
+    Url::parse(&value).map_err(|e| format!("'{}' is not a URL ({})", value, e))
+}
+
+fn join(base: &Url, path: &str) -> String {
+    base.join(path)
+        .expect("Endpoint paths are relative")
+        .to_string()
+}
+
+impl Endpoints {
+    pub fn service(&self, path: &str) -> String {
+        join(&self.service, path)
+    }
+
+    /// Overridden endpoints, for `status`.
+    pub fn overrides(&self) -> Vec<(&'static str, String)> {
+        let mut overrides = Vec::new();
+        for (name, url, default) in [
+            ("Panel", &self.panel, DEFAULT_PANEL),
+            ("Service", &self.service, DEFAULT_SERVICE),
+            ("Anti-Captcha", &self.anti_captcha, DEFAULT_ANTI_CAPTCHA),
+            ("CapSolver", &self.capsolver, DEFAULT_CAPSOLVER),
+        ] {
+            if url.as_str() != default {
+                overrides.push((name, url.to_string()));
+            }
+        }
+        if let Some(host) = &self.two_captcha_host {
+            overrides.push(("2captcha", host.clone()));
+        }
+        overrides
+    }
+}
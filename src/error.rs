@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::StatusCode;
+
+use crate::{
+    client::{ExtendError, LoginError},
+    external::{CaptchaError, CaptchaKind, ImapError},
+    retry::RetryClass,
+};
+
+/// Why a login or extend run failed. Each variant belongs to one category,
+/// which decides the process exit code.
+#[derive(Debug, thiserror::Error)]
+pub enum RunError {
+    #[error("{step}: network error: {source}")]
+    Network {
+        step: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{step}: HTTP {status}")]
+    HttpStatus {
+        step: &'static str,
+        status: StatusCode,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{step}: unexpected page, the panel layout may have changed ({reason})")]
+    Layout {
+        step: &'static str,
+        reason: &'static str,
+        /// Where the offending response was saved, see `client::snapshot`.
+        snapshot: Option<PathBuf>,
+    },
+    #[error("{0}")]
+    BadCredentials(String),
+    #[error("Two-way authentication required; configure IMAP or run 'xrenew login'")]
+    TwoFactorRequired,
+    #[error("Two-way authentication failed: {0}")]
+    TwoFactorFailed(String),
+    #[error("Two-way authentication mail: {0}")]
+    Imap(#[from] ImapError),
+    #[error("Renewal not open yet: {0}")]
+    NotRenewable(String),
+    #[error("Captcha answer was rejected")]
+    CaptchaWrong,
+    #[error("{kind:?} captcha: {source}")]
+    CaptchaFailed {
+        kind: CaptchaKind,
+        #[source]
+        source: CaptchaError,
+    },
+    #[error("{kind:?} captcha: {source}")]
+    SolverUnavailable {
+        kind: CaptchaKind,
+        #[source]
+        source: CaptchaError,
+    },
+    #[error("No account found, run 'xrenew login' first or set XRENEW_EMAIL and XRENEW_PASSWORD")]
+    NoAccount,
+    #[error("No VPS found")]
+    NoServers,
+    #[error("No VPS matched: {0}")]
+    NoServerMatched(String),
+}
+
+impl RunError {
+    fn request(step: &'static str, source: reqwest::Error) -> Self {
+        match source.status() {
+            Some(status) => RunError::HttpStatus {
+                step,
+                status,
+                source,
+            },
+            None => RunError::Network { step, source },
+        }
+    }
+
+    pub fn captcha(kind: CaptchaKind, source: CaptchaError) -> Self {
+        match source {
+            CaptchaError::NoSolver(_) | CaptchaError::ApiKeyNotSet => {
+                RunError::SolverUnavailable { kind, source }
+            }
+            source => RunError::CaptchaFailed { kind, source },
+        }
+    }
+
+    /// Which retry setting covers the error, if it may go away on its own.
+    pub fn retry_class(&self) -> Option<RetryClass> {
+        match self {
+            RunError::Network { .. } => Some(RetryClass::Network),
+            RunError::HttpStatus { status, .. }
+                if status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                Some(RetryClass::Server)
+            }
+            RunError::Layout { .. } => Some(RetryClass::Layout),
+            RunError::CaptchaWrong | RunError::CaptchaFailed { .. } => Some(RetryClass::Captcha),
+            _ => None,
+        }
+    }
+
+    /// Saved page of a layout error, to attach to the notification.
+    pub fn snapshot(&self) -> Option<&Path> {
+        match self {
+            RunError::Layout { snapshot, .. } => snapshot.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Process exit code of the failure category, as listed in the README.
+    /// Success and skipped runs exit with 0, clap usage errors with 2.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::BadCredentials(_) | RunError::NoAccount => 3,
+            RunError::TwoFactorRequired | RunError::TwoFactorFailed(_) | RunError::Imap(_) => 4,
+            RunError::CaptchaWrong
+            | RunError::CaptchaFailed { .. }
+            | RunError::SolverUnavailable { .. } => 5,
+            RunError::NotRenewable(_) => 6,
+            RunError::Network { .. } | RunError::HttpStatus { .. } => 7,
+            RunError::Layout { .. } => 8,
+            RunError::NoServers | RunError::NoServerMatched(_) => 1,
+        }
+    }
+}
+
+/// Attaches the name of the step to a client error.
+pub trait Step<T> {
+    fn step(self, step: &'static str) -> Result<T, RunError>;
+}
+
+impl<T> Step<T> for Result<T, LoginError> {
+    fn step(self, step: &'static str) -> Result<T, RunError> {
+        self.map_err(|e| match e {
+            LoginError::RequestError(source) => RunError::request(step, source),
+            LoginError::ParseError(failure) => RunError::Layout {
+                step,
+                reason: failure.reason,
+                snapshot: failure.snapshot,
+            },
+        })
+    }
+}
+
+impl<T> Step<T> for Result<T, ExtendError> {
+    fn step(self, step: &'static str) -> Result<T, RunError> {
+        self.map_err(|e| match e {
+            ExtendError::RequestError(source) => RunError::request(step, source),
+            ExtendError::ParseError(failure) => RunError::Layout {
+                step,
+                reason: failure.reason,
+                snapshot: failure.snapshot,
+            },
+            ExtendError::CaptchaRejected => RunError::CaptchaWrong,
+        })
+    }
+}
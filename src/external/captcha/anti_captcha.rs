@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::time::{Instant, sleep};
+
+use super::{CaptchaError, CaptchaKind, CaptchaSolver, digits};
+use crate::{
+    client::Captcha,
+    data::value::{get_anti_captcha_key, get_capsolver_key},
+    endpoint::ENDPOINTS,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(180);
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Anti-Captcha and CapSolver share the same `createTask`/`getTaskResult`
+/// API and only differ in endpoint and task type names.
+pub struct TaskApi {
+    name: &'static str,
+    api: &'static url::Url,
+    key: fn() -> Option<String>,
+    image_task: fn(&str) -> Value,
+    turnstile_task: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskResponse {
+    error_id: i32,
+    error_code: Option<String>,
+    error_description: Option<String>,
+    task_id: Option<Value>,
+    status: Option<String>,
+    solution: Option<Value>,
+}
+
+impl TaskResponse {
+    fn check(self) -> Result<Self, CaptchaError> {
+        if self.error_id != 0 {
+            return Err(CaptchaError::CaptchaFailure(
+                self.error_description
+                    .or(self.error_code)
+                    .unwrap_or_else(|| format!("error {}", self.error_id)),
+            ));
+        }
+        Ok(self)
+    }
+}
+
+impl TaskApi {
+    pub fn anti_captcha() -> Self {
+        TaskApi {
+            name: "anti-captcha",
+            api: &ENDPOINTS.anti_captcha,
+            key: get_anti_captcha_key,
+            image_task: |body| json!({"type": "ImageToTextTask", "body": body, "numeric": 1}),
+            turnstile_task: "TurnstileTaskProxyless",
+        }
+    }
+
+    pub fn capsolver() -> Self {
+        TaskApi {
+            name: "capsolver",
+            api: &ENDPOINTS.capsolver,
+            key: get_capsolver_key,
+            image_task: |body| json!({"type": "ImageToTextTask", "body": body}),
+            turnstile_task: "AntiTurnstileTaskProxyLess",
+        }
+    }
+
+    async fn run(&self, task: Value, field: &str) -> Result<String, CaptchaError> {
+        let key = (self.key)().ok_or(CaptchaError::ApiKeyNotSet)?;
+        let client = crate::config::http_client();
+        let created = client
+            .post(self.api.join("createTask").expect("valid path"))
+            .json(&json!({"clientKey": key, "task": task}))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TaskResponse>()
+            .await?
+            .check()?;
+
+        let deadline = Instant::now() + TIMEOUT;
+        let mut res = created;
+        loop {
+            if res.status.as_deref() == Some("ready")
+                && let Some(value) = res.solution.as_ref().and_then(|s| s.get(field))
+            {
+                return Ok(value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_owned));
+            }
+            let task_id = res.task_id.clone().ok_or(CaptchaError::CaptchaFailure(
+                "No task id returned".to_string(),
+            ))?;
+            if Instant::now() >= deadline {
+                return Err(CaptchaError::CaptchaFailure(format!(
+                    "{} timed out",
+                    self.name
+                )));
+            }
+            sleep(POLL_INTERVAL).await;
+            res = client
+                .post(self.api.join("getTaskResult").expect("valid path"))
+                .json(&json!({"clientKey": key, "taskId": task_id}))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<TaskResponse>()
+                .await?
+                .check()?;
+            if res.task_id.is_none() {
+                res.task_id = Some(task_id);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CaptchaSolver for TaskApi {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn supports(&self, _kind: CaptchaKind) -> bool {
+        (self.key)().is_some()
+    }
+
+    async fn solve_image(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let body = captcha
+            .base64_image()
+            .ok_or(CaptchaError::InvalidSrcFormat)?;
+        let text = self.run((self.image_task)(&body), "text").await?;
+        digits(&text)
+            .ok_or_else(|| CaptchaError::CaptchaFailure(format!("Non-numeric answer: {}", text)))
+    }
+
+    async fn solve_turnstile(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let site_key = captcha
+            .cloudflare_challenge()
+            .ok_or(CaptchaError::InvalidSrcFormat)?;
+        let task = json!({
+            "type": self.turnstile_task,
+            "websiteURL": captcha.url.as_str(),
+            "websiteKey": site_key,
+        });
+        self.run(task, "token").await
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{CaptchaError, CaptchaKind, CaptchaSolver};
+use crate::{client::Captcha, endpoint::ENDPOINTS};
+
+#[derive(Debug, Serialize)]
+pub struct Request {
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub data: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    /// Digits as sent; a number would lose leading zeros.
+    #[serde(deserialize_with = "digits")]
+    pub code: String,
+}
+
+/// Takes the code as a string, and still as a number from older versions
+/// of the service.
+fn digits<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Code {
+        Text(String),
+        Number(u64),
+    }
+    Ok(match Code::deserialize(d)? {
+        Code::Text(text) => text,
+        Code::Number(n) => n.to_string(),
+    })
+}
+
+/// The xrenew image captcha endpoint.
+pub struct Hosted;
+
+#[async_trait::async_trait]
+impl CaptchaSolver for Hosted {
+    fn name(&self) -> &'static str {
+        "hosted"
+    }
+
+    fn supports(&self, kind: CaptchaKind) -> bool {
+        kind == CaptchaKind::Image
+    }
+
+    async fn solve_image(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let client = crate::config::http_client();
+        let request = Request {
+            mime_type: captcha
+                .mime_type()
+                .unwrap_or_else(|| "image/png".to_string()),
+            data: captcha
+                .base64_image()
+                .ok_or(CaptchaError::InvalidSrcFormat)?,
+        };
+        let res = client
+            .post(ENDPOINTS.service("solve"))
+            .json(&request)
+            .send()
+            .await?;
+
+        let code = res.status();
+
+        if !code.is_success() {
+            let error_text = res
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(CaptchaError::ServerError {
+                code,
+                message: error_text,
+            });
+        }
+
+        let result = res.json::<Response>().await?;
+        super::digits(&result.code).ok_or_else(|| {
+            CaptchaError::CaptchaFailure(format!("Non-numeric answer: {}", result.code))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(json: &str) -> String {
+        serde_json::from_str::<Response>(json).unwrap().code
+    }
+
+    #[test]
+    fn keeps_leading_zeros() {
+        assert_eq!(code(r#"{"code":"0123"}"#), "0123");
+        assert_eq!(code(r#"{"code":4567}"#), "4567");
+        assert!(serde_json::from_str::<Response>(r#"{"code":null}"#).is_err());
+    }
+}
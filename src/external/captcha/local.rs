@@ -0,0 +1,481 @@
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use bincode::{Decode, Encode};
+use image::GrayImage;
+
+use super::{CaptchaError, CaptchaKind, CaptchaSolver};
+use crate::{
+    client::Captcha,
+    data::{
+        SAVE_DIR,
+        value::{get_ocr_templates, set_ocr_templates},
+    },
+};
+
+const GLYPH_W: usize = 12;
+const GLYPH_H: usize = 16;
+/// Mean squared difference above which a glyph is treated as unrecognized,
+/// so the chain falls through to the next solver instead of guessing.
+const MAX_DISTANCE: f32 = 0.12;
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct DigitTemplate {
+    pub digit: u8,
+    pub count: u32,
+    pub pixels: Vec<f32>,
+}
+
+/// Averaged glyph per digit, learned from labelled captcha images.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct OcrTemplates {
+    pub digits: Vec<DigitTemplate>,
+}
+
+impl OcrTemplates {
+    fn learn(&mut self, digit: u8, glyph: &[f32]) {
+        match self.digits.iter_mut().find(|t| t.digit == digit) {
+            Some(t) => {
+                t.count += 1;
+                let n = t.count as f32;
+                for (p, g) in t.pixels.iter_mut().zip(glyph) {
+                    *p += (g - *p) / n;
+                }
+            }
+            None => self.digits.push(DigitTemplate {
+                digit,
+                count: 1,
+                pixels: glyph.to_vec(),
+            }),
+        }
+    }
+
+    fn classify(&self, glyph: &[f32]) -> Option<(u8, f32)> {
+        self.digits
+            .iter()
+            .map(|t| {
+                let d = t
+                    .pixels
+                    .iter()
+                    .zip(glyph)
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum::<f32>()
+                    / glyph.len() as f32;
+                (t.digit, d)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Reads the digits in an image, or `None` when segmentation or matching
+    /// is not confident.
+    pub fn recognize(&self, image: &GrayImage) -> Option<String> {
+        let mut code = String::new();
+        for glyph in segment(image) {
+            let (digit, distance) = self.classify(&glyph)?;
+            if distance > MAX_DISTANCE {
+                return None;
+            }
+            code.push(char::from(b'0' + digit));
+        }
+        (!code.is_empty()).then_some(code)
+    }
+}
+
+fn otsu_threshold(image: &GrayImage) -> u8 {
+    let mut hist = [0u64; 256];
+    for p in image.pixels() {
+        hist[p.0[0] as usize] += 1;
+    }
+    let total = image.pixels().len() as f64;
+    let sum: f64 = hist
+        .iter()
+        .enumerate()
+        .map(|(i, c)| i as f64 * *c as f64)
+        .sum();
+    let (mut sum_b, mut w_b, mut best, mut threshold) = (0.0, 0.0, 0.0, 127u8);
+    for (t, count) in hist.iter().enumerate() {
+        w_b += *count as f64;
+        if w_b == 0.0 {
+            continue;
+        }
+        let w_f = total - w_b;
+        if w_f == 0.0 {
+            break;
+        }
+        sum_b += t as f64 * *count as f64;
+        let m_b = sum_b / w_b;
+        let m_f = (sum - sum_b) / w_f;
+        let between = w_b * w_f * (m_b - m_f) * (m_b - m_f);
+        if between > best {
+            best = between;
+            threshold = t as u8;
+        }
+    }
+    threshold
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    area: usize,
+}
+
+/// Splits the image into normalized digit glyphs, left to right.
+fn segment(image: &GrayImage) -> Vec<Vec<f32>> {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let threshold = otsu_threshold(image);
+    let mut ink: Vec<bool> = image.pixels().map(|p| p.0[0] <= threshold).collect();
+    // Text is the minority colour; flip for light-on-dark images.
+    if ink.iter().filter(|i| **i).count() * 2 > ink.len() {
+        ink.iter_mut().for_each(|i| *i = !*i);
+    }
+
+    let mut seen = vec![false; w * h];
+    let mut rects = Vec::new();
+    for start in 0..w * h {
+        if !ink[start] || seen[start] {
+            continue;
+        }
+        let mut rect = Rect {
+            x0: w,
+            y0: h,
+            x1: 0,
+            y1: 0,
+            area: 0,
+        };
+        let mut stack = vec![start];
+        seen[start] = true;
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            rect.x0 = rect.x0.min(x);
+            rect.y0 = rect.y0.min(y);
+            rect.x1 = rect.x1.max(x);
+            rect.y1 = rect.y1.max(y);
+            rect.area += 1;
+            for dy in -1i64..=1 {
+                for dx in -1i64..=1 {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    if nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 {
+                        continue;
+                    }
+                    let j = ny as usize * w + nx as usize;
+                    if ink[j] && !seen[j] {
+                        seen[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+        rects.push(rect);
+    }
+
+    // Drop specks and merge pieces of the same digit that overlap horizontally.
+    let largest = rects.iter().map(|r| r.area).max().unwrap_or(0);
+    rects.retain(|r| r.area * 5 >= largest);
+    rects.sort_by_key(|r| r.x0);
+    let mut merged: Vec<Rect> = Vec::new();
+    for r in rects {
+        if let Some(last) = merged.last_mut() {
+            let overlap = last.x1.min(r.x1) as i64 - last.x0.max(r.x0) as i64;
+            let narrower = (last.x1 - last.x0).min(r.x1 - r.x0) as i64 + 1;
+            if overlap * 2 >= narrower {
+                *last = Rect {
+                    x0: last.x0.min(r.x0),
+                    y0: last.y0.min(r.y0),
+                    x1: last.x1.max(r.x1),
+                    y1: last.y1.max(r.y1),
+                    area: last.area + r.area,
+                };
+                continue;
+            }
+        }
+        merged.push(r);
+    }
+
+    // Touching digits show up as one wide component; cut it evenly.
+    let mut widths: Vec<usize> = merged.iter().map(|r| r.x1 - r.x0 + 1).collect();
+    widths.sort_unstable();
+    let typical = widths.get(widths.len() / 2).copied().unwrap_or(1).max(1);
+    let mut glyphs = Vec::new();
+    for r in merged {
+        let width = r.x1 - r.x0 + 1;
+        let parts = if width * 10 >= typical * 16 {
+            (width + typical / 2) / typical
+        } else {
+            1
+        }
+        .max(1);
+        for p in 0..parts {
+            let x0 = r.x0 + width * p / parts;
+            let x1 = r.x0 + width * (p + 1) / parts - 1;
+            glyphs.push(normalize(&ink, w, x0, r.y0, x1, r.y1));
+        }
+    }
+    glyphs
+}
+
+/// Area-samples the box into a GLYPH_W x GLYPH_H grid of ink coverage.
+fn normalize(ink: &[bool], w: usize, x0: usize, y0: usize, x1: usize, y1: usize) -> Vec<f32> {
+    let (bw, bh) = (x1 - x0 + 1, y1 - y0 + 1);
+    let mut out = vec![0.0; GLYPH_W * GLYPH_H];
+    for gy in 0..GLYPH_H {
+        for gx in 0..GLYPH_W {
+            let sx0 = x0 + gx * bw / GLYPH_W;
+            let sx1 = (x0 + (gx + 1) * bw / GLYPH_W).max(sx0 + 1);
+            let sy0 = y0 + gy * bh / GLYPH_H;
+            let sy1 = (y0 + (gy + 1) * bh / GLYPH_H).max(sy0 + 1);
+            let mut count = 0;
+            let mut total = 0;
+            for y in sy0..sy1.min(y1 + 1) {
+                for x in sx0..sx1.min(x1 + 1) {
+                    total += 1;
+                    if ink[y * w + x] {
+                        count += 1;
+                    }
+                }
+            }
+            out[gy * GLYPH_W + gx] = if total == 0 {
+                0.0
+            } else {
+                count as f32 / total as f32
+            };
+        }
+    }
+    out
+}
+
+fn decode(data: &[u8]) -> Option<GrayImage> {
+    image::load_from_memory(data).ok().map(|i| i.to_luma8())
+}
+
+fn captcha_bytes(captcha: &Captcha) -> Result<Vec<u8>, CaptchaError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(
+            captcha
+                .base64_image()
+                .ok_or(CaptchaError::InvalidSrcFormat)?,
+        )
+        .map_err(|_| CaptchaError::InvalidSrcFormat)
+}
+
+/// In-process template matcher. Only offered once templates were trained
+/// with `xrenew ocr train`.
+pub struct LocalOcr;
+
+#[async_trait::async_trait]
+impl CaptchaSolver for LocalOcr {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn supports(&self, kind: CaptchaKind) -> bool {
+        kind == CaptchaKind::Image && get_ocr_templates().is_some_and(|t| t.digits.len() == 10)
+    }
+
+    async fn solve_image(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let templates = get_ocr_templates().unwrap_or_default();
+        let image = decode(&captcha_bytes(captcha)?).ok_or(CaptchaError::InvalidSrcFormat)?;
+        templates
+            .recognize(&image)
+            .ok_or(CaptchaError::CaptchaFailure(
+                "Local OCR is not confident".to_string(),
+            ))
+    }
+}
+
+pub fn corpus_dir() -> PathBuf {
+    SAVE_DIR.join("captcha")
+}
+
+/// Keeps an image whose answer was accepted by the panel, to grow the
+/// training corpus, when `captcha.keep_samples` is set. The oldest images
+/// beyond that many are removed.
+pub fn save_sample(captcha: &Captcha, code: &str) {
+    let keep = crate::config::keep_samples();
+    if keep == 0 {
+        return;
+    }
+    let Ok(data) = captcha_bytes(captcha) else {
+        return;
+    };
+    let ext = captcha
+        .mime_type()
+        .and_then(|m| m.strip_prefix("image/").map(str::to_owned))
+        .unwrap_or_else(|| "png".to_string());
+    let dir = corpus_dir();
+    std::fs::create_dir_all(&dir).ok();
+    let name = format!("{}_{}.{}", code, chrono::Local::now().timestamp(), ext);
+    std::fs::write(dir.join(name), data).ok();
+
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = corpus(&dir)
+        .into_iter()
+        .filter_map(|(path, _)| Some((path.metadata().ok()?.modified().ok()?, path)))
+        .collect();
+    files.sort();
+    for (_, path) in files.iter().take(files.len().saturating_sub(keep)) {
+        std::fs::remove_file(path).ok();
+    }
+}
+
+/// Labelled images in `dir`; the label is the file stem up to the first `_`.
+fn corpus(dir: &Path) -> Vec<(PathBuf, String)> {
+    let mut files: Vec<(PathBuf, String)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?;
+            let label = stem.split('_').next()?.to_string();
+            (!label.is_empty() && label.bytes().all(|b| b.is_ascii_digit()))
+                .then_some((path, label))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Learns the digit templates from labelled images, returning them with the
+/// numbers of images used and skipped.
+fn fit(files: &[(PathBuf, String)]) -> (OcrTemplates, usize, usize) {
+    let mut templates = OcrTemplates::default();
+    let (mut used, mut skipped) = (0, 0);
+    for (path, label) in files {
+        let Some(image) = std::fs::read(path).ok().and_then(|d| decode(&d)) else {
+            skipped += 1;
+            continue;
+        };
+        let glyphs = segment(&image);
+        if glyphs.len() != label.len() {
+            println!(
+                "Skip {}: found {} glyphs, expected {}",
+                path.display(),
+                glyphs.len(),
+                label.len()
+            );
+            skipped += 1;
+            continue;
+        }
+        for (digit, glyph) in label.bytes().zip(&glyphs) {
+            templates.learn(digit - b'0', glyph);
+        }
+        used += 1;
+    }
+    templates.digits.sort_by_key(|t| t.digit);
+    (templates, used, skipped)
+}
+
+pub fn train(dir: &Path) {
+    let (templates, used, skipped) = fit(&corpus(dir));
+    let missing: Vec<String> = (0..10u8)
+        .filter(|d| !templates.digits.iter().any(|t| t.digit == *d))
+        .map(|d| d.to_string())
+        .collect();
+    set_ocr_templates(&templates);
+    println!("Trained on {} images ({} skipped)", used, skipped);
+    if !missing.is_empty() {
+        println!(
+            "No samples for digits {}; local OCR stays disabled until all are covered",
+            missing.join(", ")
+        );
+    }
+}
+
+pub fn evaluate(dir: &Path) {
+    let Some(templates) = get_ocr_templates() else {
+        println!("No OCR templates. Run 'xrenew ocr train' first.");
+        return;
+    };
+    let files = corpus(dir);
+    if files.is_empty() {
+        println!("No labelled images in {}", dir.display());
+        return;
+    }
+    let mut correct = 0;
+    for (path, label) in &files {
+        let answer = std::fs::read(path)
+            .ok()
+            .and_then(|d| decode(&d))
+            .and_then(|i| templates.recognize(&i));
+        let ok = answer.as_deref() == Some(label.as_str());
+        if ok {
+            correct += 1;
+        }
+        println!(
+            "{} {}: expected {}, got {}",
+            if ok { "OK  " } else { "MISS" },
+            path.display(),
+            label,
+            answer.as_deref().unwrap_or("-")
+        );
+    }
+    println!(
+        "Accuracy: {}/{} ({:.1}%)",
+        correct,
+        files.len(),
+        correct as f64 * 100.0 / files.len() as f64
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> Vec<(PathBuf, String)> {
+        corpus(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/captcha"))
+    }
+
+    fn read(path: &Path) -> GrayImage {
+        decode(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn recognizes_held_out_images() {
+        let (train, test): (Vec<_>, Vec<_>) = fixtures()
+            .into_iter()
+            .enumerate()
+            .partition(|(i, _)| i % 3 != 0);
+        let train: Vec<_> = train.into_iter().map(|(_, f)| f).collect();
+        let (templates, used, _) = fit(&train);
+        assert_eq!(templates.digits.len(), 10);
+        assert!(
+            used * 10 >= train.len() * 9,
+            "segmented {}/{}",
+            used,
+            train.len()
+        );
+
+        let answers: Vec<_> = test
+            .iter()
+            .map(|(_, (path, label))| (templates.recognize(&read(path)), label))
+            .collect();
+        let correct = answers
+            .iter()
+            .filter(|(a, l)| a.as_ref() == Some(l))
+            .count();
+        // Unsure glyphs get no answer, so the next solver is asked instead;
+        // a wrong answer would waste the captcha.
+        let wrong: Vec<_> = answers
+            .iter()
+            .filter(|(a, l)| a.as_ref().is_some_and(|a| a != *l))
+            .collect();
+        assert!(wrong.is_empty(), "wrong answers: {:?}", wrong);
+        assert!(
+            correct * 4 >= test.len() * 3,
+            "recognized {}/{}",
+            correct,
+            test.len()
+        );
+    }
+
+    #[test]
+    fn keeps_leading_zeros() {
+        let files = fixtures();
+        let (templates, ..) = fit(&files);
+        let (path, label) = files.iter().find(|(_, l)| l.starts_with('0')).unwrap();
+        assert_eq!(templates.recognize(&read(path)).as_ref(), Some(label));
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::IsTerminal;
+
+use base64::Engine;
+
+use super::{CaptchaError, CaptchaKind, CaptchaSolver, digits};
+use crate::{client::Captcha, data::SAVE_DIR, prompt};
+
+/// Saves the image and asks the user to type the digits. Only offered on an
+/// interactive terminal.
+pub struct Manual;
+
+#[async_trait::async_trait]
+impl CaptchaSolver for Manual {
+    fn name(&self) -> &'static str {
+        "manual"
+    }
+
+    fn supports(&self, kind: CaptchaKind) -> bool {
+        kind == CaptchaKind::Image && std::io::stdin().is_terminal()
+    }
+
+    async fn solve_image(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(
+                captcha
+                    .base64_image()
+                    .ok_or(CaptchaError::InvalidSrcFormat)?,
+            )
+            .map_err(|_| CaptchaError::InvalidSrcFormat)?;
+        let ext = captcha
+            .mime_type()
+            .and_then(|m| m.strip_prefix("image/").map(str::to_owned))
+            .unwrap_or_else(|| "png".to_string());
+        let path = SAVE_DIR.join(format!("captcha.{}", ext));
+        std::fs::write(&path, data)
+            .map_err(|e| CaptchaError::CaptchaFailure(format!("save image: {}", e)))?;
+
+        let opener = if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        let _ = std::process::Command::new(opener)
+            .arg(&path)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        println!("Captcha image saved to {}", path.display());
+        // Reading stdin blocks, which must not stall the runtime's workers.
+        let answer = tokio::task::spawn_blocking(|| {
+            prompt::line("Please enter the digits shown in the image:")
+        })
+        .await
+        .map_err(|e| CaptchaError::CaptchaFailure(e.to_string()))?;
+        std::fs::remove_file(&path).ok();
+        answer
+            .as_deref()
+            .and_then(digits)
+            .ok_or(CaptchaError::CaptchaFailure("Not a number".to_string()))
+    }
+}
@@ -0,0 +1,171 @@
+mod anti_captcha;
+mod hosted;
+mod local;
+mod manual;
+mod two_captcha;
+
+use bincode::{Decode, Encode};
+use reqwest::StatusCode;
+
+use crate::{client::Captcha, config::captcha_order};
+
+pub use anti_captcha::TaskApi;
+pub use hosted::Hosted;
+pub use local::{
+    LocalOcr, OcrTemplates, corpus_dir, evaluate as ocr_evaluate, save_sample, train as ocr_train,
+};
+pub use manual::Manual;
+pub use two_captcha::TwoCaptchaSolver;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptchaError {
+    #[error("invalid src format")]
+    InvalidSrcFormat,
+    #[error("Failed to send captcha request: {0}")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Failed to parse captcha response: {code} - {message}")]
+    ServerError { code: StatusCode, message: String },
+    #[error("Api key not set")]
+    ApiKeyNotSet,
+    #[error("TwoCaptcha error: {0}")]
+    TwoCaptchaError(#[from] twocaptcha::TwoCaptchaError),
+    #[error("Captcha solving failed: {0}")]
+    CaptchaFailure(String),
+    #[error("No captcha solver available: {0}")]
+    NoSolver(String),
+}
+
+/// The two challenges the extend page can show, often together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptchaKind {
+    /// Numeric image captcha.
+    Image,
+    /// Cloudflare Turnstile widget.
+    Turnstile,
+}
+
+#[async_trait::async_trait]
+pub trait CaptchaSolver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether the solver can currently handle `kind` (e.g. its API key is set).
+    fn supports(&self, kind: CaptchaKind) -> bool;
+
+    /// The digits shown in the image, as text so leading zeros are kept.
+    async fn solve_image(&self, _captcha: &Captcha) -> Result<String, CaptchaError> {
+        Err(CaptchaError::NoSolver(format!(
+            "{} does not solve image captchas",
+            self.name()
+        )))
+    }
+
+    async fn solve_turnstile(&self, _captcha: &Captcha) -> Result<String, CaptchaError> {
+        Err(CaptchaError::NoSolver(format!(
+            "{} does not solve Turnstile",
+            self.name()
+        )))
+    }
+}
+
+/// `answer` when it is all digits, e.g. "0123".
+fn digits(answer: &str) -> Option<String> {
+    let answer = answer.trim();
+    (!answer.is_empty() && answer.bytes().all(|b| b.is_ascii_digit())).then(|| answer.to_string())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, clap::ValueEnum)]
+pub enum SolverKind {
+    /// In-process template matching (after `xrenew ocr train`)
+    Local,
+    /// xrenew.hiro.red image solver
+    Hosted,
+    #[value(name = "2captcha")]
+    TwoCaptcha,
+    AntiCaptcha,
+    #[value(name = "capsolver")]
+    CapSolver,
+    /// Show the image and ask on the terminal
+    Manual,
+}
+
+impl SolverKind {
+    pub const DEFAULT_ORDER: [SolverKind; 6] = [
+        SolverKind::Local,
+        SolverKind::Hosted,
+        SolverKind::TwoCaptcha,
+        SolverKind::AntiCaptcha,
+        SolverKind::CapSolver,
+        SolverKind::Manual,
+    ];
+
+    pub fn solver(self) -> Box<dyn CaptchaSolver> {
+        match self {
+            SolverKind::Local => Box::new(LocalOcr),
+            SolverKind::Hosted => Box::new(Hosted),
+            SolverKind::TwoCaptcha => Box::new(TwoCaptchaSolver),
+            SolverKind::AntiCaptcha => Box::new(TaskApi::anti_captcha()),
+            SolverKind::CapSolver => Box::new(TaskApi::capsolver()),
+            SolverKind::Manual => Box::new(Manual),
+        }
+    }
+}
+
+impl std::fmt::Display for SolverKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        let value = self.to_possible_value().expect("no skipped variants");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// Configured solvers in priority order.
+pub fn solver_chain() -> Vec<Box<dyn CaptchaSolver>> {
+    captcha_order()
+        .into_iter()
+        .map(SolverKind::solver)
+        .collect()
+}
+
+type SolveFuture<'a, T> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, CaptchaError>> + Send + 'a>>;
+
+/// Tries each solver that supports `kind` in order until one succeeds, and
+/// returns its answer with the solver's name.
+async fn solve_with<T>(
+    chain: &[Box<dyn CaptchaSolver>],
+    captcha: &Captcha,
+    kind: CaptchaKind,
+    solve: for<'a> fn(&'a dyn CaptchaSolver, &'a Captcha) -> SolveFuture<'a, T>,
+) -> Result<(T, &'static str), CaptchaError> {
+    let mut errors = Vec::new();
+    for solver in chain.iter().filter(|s| s.supports(kind)) {
+        match solve(solver.as_ref(), captcha).await {
+            Ok(res) => return Ok((res, solver.name())),
+            Err(e) => {
+                println!("Captcha solver {} failed: {}", solver.name(), e);
+                errors.push(format!("{}: {}", solver.name(), e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        return Err(CaptchaError::NoSolver(format!("{:?}", kind)));
+    }
+    Err(CaptchaError::CaptchaFailure(errors.join("; ")))
+}
+
+pub async fn solve_image(
+    chain: &[Box<dyn CaptchaSolver>],
+    captcha: &Captcha,
+) -> Result<(String, &'static str), CaptchaError> {
+    solve_with(chain, captcha, CaptchaKind::Image, |s, c| s.solve_image(c)).await
+}
+
+pub async fn solve_turnstile(
+    chain: &[Box<dyn CaptchaSolver>],
+    captcha: &Captcha,
+) -> Result<(String, &'static str), CaptchaError> {
+    solve_with(chain, captcha, CaptchaKind::Turnstile, |s, c| {
+        s.solve_turnstile(c)
+    })
+    .await
+}
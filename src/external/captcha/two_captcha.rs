@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use twocaptcha::{TwoCaptcha, TwoCaptchaConfig};
+
+use super::{CaptchaError, CaptchaKind, CaptchaSolver, digits};
+use crate::{client::Captcha, data::value::get_two_captcha_key, endpoint::ENDPOINTS};
+
+pub struct TwoCaptchaSolver;
+
+fn solver() -> Result<TwoCaptcha, CaptchaError> {
+    Ok(TwoCaptcha::new(
+        get_two_captcha_key().ok_or(CaptchaError::ApiKeyNotSet)?,
+        TwoCaptchaConfig {
+            server: ENDPOINTS.two_captcha_host.clone(),
+            ..TwoCaptchaConfig::default()
+        },
+    ))
+}
+
+#[async_trait::async_trait]
+impl CaptchaSolver for TwoCaptchaSolver {
+    fn name(&self) -> &'static str {
+        "2captcha"
+    }
+
+    fn supports(&self, _kind: CaptchaKind) -> bool {
+        get_two_captcha_key().is_some()
+    }
+
+    async fn solve_image(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let image = captcha
+            .base64_image()
+            .ok_or(CaptchaError::InvalidSrcFormat)?;
+        let params = HashMap::from([("numeric".to_string(), "1".to_string())]);
+        let res = solver()?.normal(&image, Some(params)).await?;
+        res.code
+            .and_then(|c| digits(&c))
+            .ok_or(CaptchaError::CaptchaFailure(
+                "TwoCaptcha did not return a numeric code".to_string(),
+            ))
+    }
+
+    async fn solve_turnstile(&self, captcha: &Captcha) -> Result<String, CaptchaError> {
+        let res = solver()?
+            .turnstile(
+                captcha
+                    .cloudflare_challenge()
+                    .ok_or(CaptchaError::InvalidSrcFormat)?,
+                captcha.url.as_str(),
+                None,
+            )
+            .await?;
+
+        res.code.ok_or(CaptchaError::CaptchaFailure(
+            "TwoCaptcha did not return a code".to_string(),
+        ))
+    }
+}
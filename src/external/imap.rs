@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use bincode::{Decode, Encode};
+use mail_parser::MessageParser;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::{Instant, sleep},
+};
+
+use super::tls::{self, Stream, bounded};
+
+/// Mailbox that receives the two-factor authentication mails.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    /// Implicit TLS (IMAPS). Disable only for a local stand-in server.
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    pub mailbox: String,
+    /// Only mails whose `From` contains this text are considered.
+    pub from: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImapError {
+    #[error("IMAP connection failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("IMAP command failed: {0}")]
+    Command(String),
+    #[error("No authentication code received within {0} seconds")]
+    Timeout(u64),
+}
+
+struct Session {
+    stream: BufReader<Box<dyn Stream>>,
+    tag: u32,
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Session {
+    async fn connect(config: &ImapConfig) -> Result<Self, ImapError> {
+        let tcp = bounded(TcpStream::connect((config.host.as_str(), config.port))).await?;
+        let stream: Box<dyn Stream> = if config.tls {
+            Box::new(bounded(tls::connect(&config.host, tcp)).await?)
+        } else {
+            Box::new(tcp)
+        };
+        let mut session = Session {
+            stream: BufReader::new(stream),
+            tag: 0,
+        };
+        let greeting = session.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(ImapError::Command(greeting));
+        }
+        Ok(session)
+    }
+
+    async fn read_line(&mut self) -> Result<String, ImapError> {
+        let mut line = String::new();
+        if bounded(self.stream.read_line(&mut line)).await? == 0 {
+            return Err(ImapError::Command("connection closed".into()));
+        }
+        Ok(line)
+    }
+
+    /// Sends a command and returns the untagged response lines, with any
+    /// literal payloads appended to the line that announced them.
+    async fn command(&mut self, cmd: &str) -> Result<Vec<Vec<u8>>, ImapError> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        let stream = self.stream.get_mut();
+        bounded(stream.write_all(format!("{} {}\r\n", tag, cmd).as_bytes())).await?;
+        bounded(stream.flush()).await?;
+
+        let mut responses = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if let Some(status) = line.strip_prefix(&format!("{} ", tag)) {
+                if status.starts_with("OK") {
+                    return Ok(responses);
+                }
+                return Err(ImapError::Command(status.trim().to_string()));
+            }
+            let mut response = line.clone().into_bytes();
+            let mut current = line;
+            while let Some(len) = literal_len(&current) {
+                let mut literal = vec![0; len];
+                bounded(self.stream.read_exact(&mut literal)).await?;
+                response.extend_from_slice(&literal);
+                current = self.read_line().await?;
+                response.extend_from_slice(current.as_bytes());
+            }
+            responses.push(response);
+        }
+    }
+
+    async fn login(&mut self, config: &ImapConfig) -> Result<(), ImapError> {
+        self.command(&format!(
+            "LOGIN {} {}",
+            quote(&config.username),
+            quote(&config.password)
+        ))
+        .await?;
+        self.command(&format!("SELECT {}", quote(&config.mailbox)))
+            .await?;
+        Ok(())
+    }
+
+    async fn search(&mut self, config: &ImapConfig, after_uid: u32) -> Result<Vec<u32>, ImapError> {
+        let responses = self
+            .command(&format!(
+                "UID SEARCH UID {}:* FROM {}",
+                after_uid + 1,
+                quote(&config.from)
+            ))
+            .await?;
+        Ok(responses
+            .iter()
+            .filter_map(|r| {
+                String::from_utf8_lossy(r)
+                    .strip_prefix("* SEARCH")
+                    .map(str::to_owned)
+            })
+            .flat_map(|r| {
+                r.split_whitespace()
+                    .filter_map(|n| n.parse().ok())
+                    .collect::<Vec<u32>>()
+            })
+            .filter(|uid| *uid > after_uid)
+            .collect())
+    }
+
+    async fn fetch(&mut self, uid: u32) -> Result<Vec<u8>, ImapError> {
+        let responses = self
+            .command(&format!("UID FETCH {} BODY.PEEK[]", uid))
+            .await?;
+        let response = responses
+            .into_iter()
+            .find(|r| r.starts_with(b"* ") && literal_len(&first_line(r)).is_some())
+            .ok_or_else(|| ImapError::Command(format!("message {} not returned", uid)))?;
+        let head = first_line(&response);
+        let len = literal_len(&head).unwrap_or_default();
+        let start = head.len();
+        Ok(response[start..start + len].to_vec())
+    }
+
+    async fn logout(mut self) {
+        self.command("LOGOUT").await.ok();
+    }
+}
+
+fn first_line(response: &[u8]) -> String {
+    let end = response
+        .iter()
+        .position(|b| *b == b'\n')
+        .map_or(response.len(), |p| p + 1);
+    String::from_utf8_lossy(&response[..end]).into_owned()
+}
+
+fn literal_len(line: &str) -> Option<usize> {
+    let line = line.trim_end();
+    let open = line.rfind('{')?;
+    line.strip_suffix('}')?[open + 1..].parse().ok()
+}
+
+/// Pulls the numeric code out of an authentication mail. Prefers digits
+/// following "コード", otherwise the first standalone 4-8 digit number.
+pub fn extract_code(raw: &[u8]) -> Option<String> {
+    let message = MessageParser::default().parse(raw)?;
+    let body = message
+        .body_text(0)
+        .or_else(|| message.body_html(0))?
+        .into_owned();
+
+    let numbers = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_ascii_digit())
+            .filter(|n| (4..=8).contains(&n.len()))
+            .map(str::to_owned)
+            .collect()
+    };
+    if let Some(pos) = body.find("コード")
+        && let Some(code) = numbers(&body[pos..]).into_iter().next()
+    {
+        return Some(code);
+    }
+    numbers(&body).into_iter().next()
+}
+
+/// Highest UID currently in the mailbox, used as the baseline before the
+/// code mail is requested.
+pub async fn latest_uid(config: &ImapConfig) -> Result<u32, ImapError> {
+    let mut session = Session::connect(config).await?;
+    session.login(config).await?;
+    let responses = session.command("UID SEARCH ALL").await?;
+    session.logout().await;
+    Ok(responses
+        .iter()
+        .filter_map(|r| {
+            String::from_utf8_lossy(r)
+                .strip_prefix("* SEARCH")
+                .map(str::to_owned)
+        })
+        .flat_map(|r| {
+            r.split_whitespace()
+                .filter_map(|n| n.parse().ok())
+                .collect::<Vec<u32>>()
+        })
+        .max()
+        .unwrap_or(0))
+}
+
+/// Polls the mailbox until a mail newer than `after_uid` containing a code
+/// arrives.
+pub async fn wait_for_code(
+    config: &ImapConfig,
+    after_uid: u32,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<String, ImapError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut session = Session::connect(config).await?;
+        session.login(config).await?;
+        let mut uids = session.search(config, after_uid).await?;
+        uids.sort_unstable();
+        let mut code = None;
+        for uid in uids.into_iter().rev() {
+            if let Some(c) = extract_code(&session.fetch(uid).await?) {
+                code = Some(c);
+                break;
+            }
+        }
+        session.logout().await;
+        if let Some(code) = code {
+            return Ok(code);
+        }
+        if Instant::now() + interval > deadline {
+            return Err(ImapError::Timeout(timeout.as_secs()));
+        }
+        sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    const MAIL: &str = "From: XServer <support@xserver.ne.jp>\r\n\
+        Subject: =?UTF-8?B?6KqN6Ki844Kz44O844OJ?=\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        お問い合わせ番号 20250101\r\n\
+        認証コード: 482913\r\n";
+
+    /// A one-connection IMAP server that answers LOGIN, SELECT, UID SEARCH
+    /// and UID FETCH with a single mail of UID 7, and records the commands.
+    async fn stand_in() -> (ImapConfig, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            socket
+                .get_mut()
+                .write_all(b"* OK IMAP4rev1 ready\r\n")
+                .await
+                .unwrap();
+            let mut commands = Vec::new();
+            loop {
+                let mut line = String::new();
+                if socket.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let (tag, cmd) = line.trim_end().split_once(' ').unwrap();
+                let reply = if cmd.starts_with("UID SEARCH") {
+                    "* SEARCH 7\r\n".to_string()
+                } else if cmd.starts_with("UID FETCH 7") {
+                    format!(
+                        "* 1 FETCH (UID 7 BODY[] {{{}}}\r\n{})\r\n",
+                        MAIL.len(),
+                        MAIL
+                    )
+                } else {
+                    String::new()
+                };
+                let done = cmd == "LOGOUT";
+                commands.push(cmd.to_string());
+                let reply = format!("{}{} OK done\r\n", reply, tag);
+                socket.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                if done {
+                    break;
+                }
+            }
+            commands
+        });
+        (config(port), server)
+    }
+
+    fn config(port: u16) -> ImapConfig {
+        ImapConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+            username: "user".to_string(),
+            password: "pa\"ss".to_string(),
+            mailbox: "INBOX".to_string(),
+            from: "xserver".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_code_from_stand_in() {
+        let (config, server) = stand_in().await;
+        let code = wait_for_code(&config, 3, Duration::from_secs(5), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(code, "482913");
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                r#"LOGIN "user" "pa\"ss""#,
+                r#"SELECT "INBOX""#,
+                r#"UID SEARCH UID 4:* FROM "xserver""#,
+                "UID FETCH 7 BODY.PEEK[]",
+                "LOGOUT",
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_on_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = config(listener.local_addr().unwrap().port());
+        // Accepts but never greets.
+        let _server = tokio::spawn(async move { listener.accept().await });
+        let res = latest_uid(&config).await;
+        assert!(
+            matches!(&res, Err(ImapError::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut),
+            "{:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn prefers_digits_after_code_label() {
+        assert_eq!(extract_code(MAIL.as_bytes()).as_deref(), Some("482913"));
+    }
+}
@@ -1,7 +1,17 @@
 mod captcha;
-mod webhook;
+mod imap;
+mod notify;
+mod tls;
 mod weblog;
 
-pub use captcha::{solve_captcha, two_captcha_solve};
-pub use webhook::send as send_webhook;
-pub use weblog::send_log;
+pub use captcha::{
+    CaptchaError, CaptchaKind, OcrTemplates, SolverKind, corpus_dir, ocr_evaluate, ocr_train,
+    save_sample, solve_image, solve_turnstile, solver_chain,
+};
+pub use imap::{
+    ImapConfig, ImapError, latest_uid as imap_latest_uid, wait_for_code as imap_wait_for_code,
+};
+pub use notify::{
+    Attachment, Discord, NotifierConfig, send as send_notification, title as notification_title,
+};
+pub use weblog::{Telemetry, default_endpoint as default_telemetry_endpoint, send_log};
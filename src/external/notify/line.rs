@@ -0,0 +1,50 @@
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+
+use super::{Notifier, NotifyError};
+
+const PUSH_API: &str = "https://api.line.me/v2/bot/message/push";
+
+/// LINE Messaging API push messages. LINE Notify has been shut down, so
+/// `to` is required; it stays optional in the stored form only so that
+/// entries added for LINE Notify can still be read and reported.
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Line {
+    /// Channel access token of the Messaging API
+    pub token: String,
+    /// User, group or room ID to push to
+    #[arg(long, required = true)]
+    #[serde(deserialize_with = "required")]
+    pub to: Option<String>,
+}
+
+fn required<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    String::deserialize(d).map(Some)
+}
+
+#[async_trait::async_trait]
+impl Notifier for Line {
+    fn describe(&self) -> String {
+        match &self.to {
+            Some(to) => format!("line (to {})", to),
+            None => "line (LINE Notify, discontinued)".to_string(),
+        }
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        let to = self.to.as_ref().ok_or(NotifyError::LineNotify)?;
+        crate::config::http_client()
+            .post(PUSH_API)
+            .json(&json!({
+                "to": to,
+                "messages": [{ "type": "text", "text": format!("{}\n{}", title, message) }],
+            }))
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
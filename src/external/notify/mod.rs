@@ -0,0 +1,156 @@
+mod line;
+mod push;
+mod smtp;
+mod webhook;
+
+use std::path::Path;
+
+use bincode::{Decode, Encode};
+
+use crate::{config, data::value::DEFAULT_PROFILE};
+
+pub use line::Line;
+pub use push::{Gotify, Ntfy, Telegram};
+pub use smtp::Email;
+pub use webhook::{Discord, Json, Slack, Teams};
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotifyError {
+    #[error("Failed to send notification: {0}")]
+    RequestError(reqwest::Error),
+    #[error("SMTP connection failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("SMTP server rejected {0}: {1}")]
+    Smtp(&'static str, String),
+    #[error("Invalid body template: {0}")]
+    Template(String),
+    #[error(
+        "LINE Notify has been shut down; remove this notifier and add it again with the Messaging API (--to)"
+    )]
+    LineNotify,
+}
+
+impl From<reqwest::Error> for NotifyError {
+    /// Webhook and bot URLs carry credentials, so they are left out.
+    fn from(e: reqwest::Error) -> Self {
+        NotifyError::RequestError(e.without_url())
+    }
+}
+
+/// A file sent along with a notification.
+pub struct Attachment {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Ok(Attachment {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "attachment".to_string()),
+            content: std::fs::read(path)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Notifier: Send + Sync {
+    /// Kind and destination for `notify list`, without tokens.
+    fn describe(&self) -> String;
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError>;
+
+    /// Sends `message` with `file` attached. Channels that cannot carry
+    /// files send the message alone.
+    async fn send_file(
+        &self,
+        title: &str,
+        message: &str,
+        _file: &Attachment,
+    ) -> Result<(), NotifyError> {
+        self.send(title, message).await
+    }
+}
+
+/// A configured notification channel. Doubles as the `notify add`
+/// subcommand, so each variant's fields are its command line arguments, and
+/// as a `notifiers` entry of config.toml, tagged with `type`.
+#[derive(Clone, Debug, Encode, Decode, clap::Subcommand, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    /// Discord webhook
+    Discord(Discord),
+    /// Slack incoming webhook
+    Slack(Slack),
+    /// Microsoft Teams workflow webhook
+    Teams(Teams),
+    /// LINE Messaging API push message
+    Line(Line),
+    /// ntfy topic
+    Ntfy(Ntfy),
+    /// Gotify server
+    Gotify(Gotify),
+    /// Telegram bot
+    Telegram(Telegram),
+    /// Email over SMTP
+    Email(Email),
+    /// Any webhook taking a JSON body built from a template
+    Json(Json),
+}
+
+impl NotifierConfig {
+    pub fn notifier(&self) -> &dyn Notifier {
+        match self {
+            NotifierConfig::Discord(n) => n,
+            NotifierConfig::Slack(n) => n,
+            NotifierConfig::Teams(n) => n,
+            NotifierConfig::Line(n) => n,
+            NotifierConfig::Ntfy(n) => n,
+            NotifierConfig::Gotify(n) => n,
+            NotifierConfig::Telegram(n) => n,
+            NotifierConfig::Email(n) => n,
+            NotifierConfig::Json(n) => n,
+        }
+    }
+}
+
+impl std::fmt::Display for NotifierConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.notifier().describe())
+    }
+}
+
+fn host(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_owned))
+        .unwrap_or_else(|| url.to_string())
+}
+
+pub fn title(profile: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        "xrenew".to_string()
+    } else {
+        format!("xrenew ({})", profile)
+    }
+}
+
+/// Sends `content` to every notifier of the profile, with `file` attached
+/// where the channel supports it. Failures are printed and do not stop the
+/// other notifiers.
+pub async fn send(profile: &str, content: &str, file: Option<&Attachment>) {
+    let title = title(profile);
+    let (notifiers, _) = config::notifiers(profile);
+    for config in notifiers {
+        let notifier = config.notifier();
+        let res = match file {
+            Some(file) => notifier.send_file(&title, content, file).await,
+            None => notifier.send(&title, content).await,
+        };
+        if let Err(e) = res {
+            println!("Notification via {} failed: {}", config, e);
+        }
+    }
+}
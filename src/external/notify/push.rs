@@ -0,0 +1,145 @@
+use bincode::{Decode, Encode};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{Attachment, Notifier, NotifyError, host};
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ntfy {
+    /// Topic URL, e.g. https://ntfy.sh/my-topic
+    pub url: String,
+    /// Access token for protected topics
+    #[arg(long)]
+    pub token: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl Notifier for Ntfy {
+    fn describe(&self) -> String {
+        let topic = self.url.trim_end_matches('/').rsplit('/').next();
+        format!("ntfy ({}, {})", host(&self.url), topic.unwrap_or_default())
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        // ntfy reads the title from a header, which must be ASCII unless
+        // RFC 2047 encoded.
+        let mut request = crate::config::http_client()
+            .post(&self.url)
+            .header("Title", title.replace(|c: char| !c.is_ascii(), "?"))
+            .body(message.to_string());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// The message goes out first: with a file as the body, ntfy would only
+    /// take the text from a header, which has to be ASCII.
+    async fn send_file(
+        &self,
+        title: &str,
+        message: &str,
+        file: &Attachment,
+    ) -> Result<(), NotifyError> {
+        self.send(title, message).await?;
+        let mut request = crate::config::http_client()
+            .put(&self.url)
+            .header("Filename", file.name.replace(|c: char| !c.is_ascii(), "_"))
+            .body(file.content.clone());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gotify {
+    /// Server URL, e.g. https://gotify.example.com
+    pub url: String,
+    /// Application token
+    #[arg(long)]
+    pub token: String,
+}
+
+#[async_trait::async_trait]
+impl Notifier for Gotify {
+    fn describe(&self) -> String {
+        format!("gotify ({})", host(&self.url))
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        crate::config::http_client()
+            .post(format!("{}/message", self.url.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .json(&json!({ "title": title, "message": message, "priority": 5 }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Telegram {
+    /// Bot token from @BotFather
+    #[arg(long)]
+    pub token: String,
+    /// Chat ID to send to
+    #[arg(long)]
+    pub chat_id: String,
+}
+
+#[async_trait::async_trait]
+impl Notifier for Telegram {
+    fn describe(&self) -> String {
+        format!("telegram (chat {})", self.chat_id)
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        crate::config::http_client()
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.token
+            ))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}\n{}", title, message),
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Document captions are limited to 1024 characters, so the message is
+    /// sent on its own first.
+    async fn send_file(
+        &self,
+        title: &str,
+        message: &str,
+        file: &Attachment,
+    ) -> Result<(), NotifyError> {
+        self.send(title, message).await?;
+        let form = Form::new().text("chat_id", self.chat_id.clone()).part(
+            "document",
+            Part::bytes(file.content.clone()).file_name(file.name.clone()),
+        );
+        crate::config::http_client()
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendDocument",
+                self.token
+            ))
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use bincode::{Decode, Encode};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use super::{Attachment, Notifier, NotifyError};
+use crate::external::tls::{self, Stream, bounded};
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode, clap::ValueEnum, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Implicit TLS, usually port 465
+    Tls,
+    /// Upgrade with STARTTLS, usually port 587
+    #[value(name = "starttls")]
+    #[default]
+    StartTls,
+    /// No encryption; only for a local relay
+    None,
+}
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    /// Recipient addresses
+    #[arg(required = true)]
+    pub to: Vec<String>,
+    /// SMTP server host
+    #[arg(long)]
+    pub host: String,
+    /// SMTP server port
+    #[arg(long, default_value_t = 587)]
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[arg(long, value_enum, default_value_t = SmtpSecurity::StartTls)]
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// Login name; the password is asked for interactively
+    #[arg(long)]
+    pub user: Option<String>,
+    #[arg(skip)]
+    pub password: Option<String>,
+    /// Sender address (defaults to --user)
+    #[arg(long)]
+    pub from: Option<String>,
+}
+
+fn default_port() -> u16 {
+    587
+}
+
+impl Email {
+    fn sender(&self) -> &str {
+        self.from
+            .as_deref()
+            .or(self.user.as_deref())
+            .unwrap_or("xrenew@localhost")
+    }
+}
+
+struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl Connection {
+    /// Reads a possibly multi-line reply and checks its status code.
+    async fn reply(&mut self, step: &'static str, expect: &[u16]) -> Result<String, NotifyError> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            if bounded(self.stream.read_line(&mut line)).await? == 0 {
+                return Err(NotifyError::Smtp(step, "connection closed".into()));
+            }
+            text.push_str(&line);
+            if line.as_bytes().get(3) != Some(&b'-') {
+                let code = line.get(..3).and_then(|c| c.parse().ok()).unwrap_or(0);
+                if !expect.contains(&code) {
+                    return Err(NotifyError::Smtp(step, text.trim().to_string()));
+                }
+                return Ok(text);
+            }
+        }
+    }
+
+    async fn command(
+        &mut self,
+        step: &'static str,
+        line: &str,
+        expect: &[u16],
+    ) -> Result<String, NotifyError> {
+        let stream = self.stream.get_mut();
+        bounded(stream.write_all(format!("{}\r\n", line).as_bytes())).await?;
+        bounded(stream.flush()).await?;
+        self.reply(step, expect).await
+    }
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|c| String::from_utf8_lossy(c).into_owned())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+fn encode_body(text: &str) -> String {
+    encode_base64(text.replace('\n', "\r\n").as_bytes())
+}
+
+const TEXT_HEADERS: &str =
+    "Content-Type: text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: base64\r\n";
+
+/// Content headers and body of the mail, as multipart/mixed when a file is
+/// attached.
+fn mime_body(message: &str, file: Option<&Attachment>) -> String {
+    let Some(file) = file else {
+        return format!("{}\r\n{}", TEXT_HEADERS, encode_body(message));
+    };
+    let boundary = format!("xrenew-{:016x}", rand::random::<u64>());
+    let name = file.name.replace(['"', '\r', '\n'], "_");
+    format!(
+        "Content-Type: multipart/mixed; boundary=\"{b}\"\r\n\r\n\
+         --{b}\r\n{}\r\n{}\r\n\
+         --{b}\r\nContent-Type: application/octet-stream; name=\"{n}\"\r\n\
+         Content-Disposition: attachment; filename=\"{n}\"\r\n\
+         Content-Transfer-Encoding: base64\r\n\r\n{}\r\n\
+         --{b}--",
+        TEXT_HEADERS,
+        encode_body(message),
+        encode_base64(&file.content),
+        b = boundary,
+        n = name,
+    )
+}
+
+impl Email {
+    async fn deliver(
+        &self,
+        title: &str,
+        message: &str,
+        file: Option<&Attachment>,
+    ) -> Result<(), NotifyError> {
+        let tcp = bounded(TcpStream::connect((self.host.as_str(), self.port))).await?;
+        let stream: Box<dyn Stream> = match self.security {
+            SmtpSecurity::Tls => Box::new(bounded(tls::connect(&self.host, tcp)).await?),
+            _ => Box::new(tcp),
+        };
+        let mut conn = Connection {
+            stream: BufReader::new(stream),
+        };
+        conn.reply("greeting", &[220]).await?;
+        conn.command("EHLO", "EHLO localhost", &[250]).await?;
+        if self.security == SmtpSecurity::StartTls {
+            conn.command("STARTTLS", "STARTTLS", &[220]).await?;
+            // Anything sent before the handshake would be read as if it had
+            // come over TLS.
+            if !conn.stream.buffer().is_empty() {
+                return Err(NotifyError::Smtp(
+                    "STARTTLS",
+                    "server sent data before the TLS handshake".into(),
+                ));
+            }
+            let plain = conn.stream.into_inner();
+            conn = Connection {
+                stream: BufReader::new(Box::new(bounded(tls::connect(&self.host, plain)).await?)),
+            };
+            conn.command("EHLO", "EHLO localhost", &[250]).await?;
+        }
+        if let Some(user) = &self.user {
+            let password = self.password.as_deref().unwrap_or_default();
+            let auth = BASE64.encode(format!("\0{}\0{}", user, password));
+            conn.command("AUTH", &format!("AUTH PLAIN {}", auth), &[235])
+                .await?;
+        }
+        conn.command(
+            "MAIL FROM",
+            &format!("MAIL FROM:<{}>", self.sender()),
+            &[250],
+        )
+        .await?;
+        for to in &self.to {
+            conn.command("RCPT TO", &format!("RCPT TO:<{}>", to), &[250, 251])
+                .await?;
+        }
+        conn.command("DATA", "DATA", &[354]).await?;
+        let mail = format!(
+            "From: {}\r\nTo: {}\r\nSubject: =?UTF-8?B?{}?=\r\nDate: {}\r\n\
+             MIME-Version: 1.0\r\n{}\r\n.",
+            self.sender(),
+            self.to.join(", "),
+            BASE64.encode(title),
+            chrono::Local::now().to_rfc2822(),
+            mime_body(message, file)
+        );
+        conn.command("message", &mail, &[250]).await?;
+        conn.command("QUIT", "QUIT", &[221]).await.ok();
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Email {
+    fn describe(&self) -> String {
+        format!("email ({} via {})", self.to.join(", "), self.host)
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        self.deliver(title, message, None).await
+    }
+
+    async fn send_file(
+        &self,
+        title: &str,
+        message: &str,
+        file: &Attachment,
+    ) -> Result<(), NotifyError> {
+        self.deliver(title, message, Some(file)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::TcpListener, task::JoinHandle};
+
+    use super::*;
+
+    async fn send(socket: &mut BufReader<TcpStream>, text: &str) {
+        socket.get_mut().write_all(text.as_bytes()).await.unwrap();
+    }
+
+    /// A one-connection SMTP server that accepts everything. Returns the
+    /// commands it received, with the message as the one after DATA.
+    /// `after_starttls` is sent right behind the reply to STARTTLS.
+    async fn stand_in(after_starttls: &'static str) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            send(&mut socket, "220 stand-in ESMTP\r\n").await;
+            let mut commands = Vec::new();
+            loop {
+                let mut line = String::new();
+                if socket.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply = match line.split(' ').next().unwrap() {
+                    "EHLO" => "250-stand-in\r\n250 AUTH PLAIN\r\n".to_string(),
+                    "STARTTLS" => format!("220 go ahead\r\n{}", after_starttls),
+                    "AUTH" => "235 ok\r\n".to_string(),
+                    "DATA" => {
+                        commands.push(line);
+                        send(&mut socket, "354 go on\r\n").await;
+                        let mut mail = String::new();
+                        while !mail.ends_with("\r\n.\r\n") {
+                            if socket.read_line(&mut mail).await.unwrap() == 0 {
+                                break;
+                            }
+                        }
+                        commands.push(mail);
+                        send(&mut socket, "250 queued\r\n").await;
+                        continue;
+                    }
+                    "QUIT" => {
+                        commands.push(line);
+                        send(&mut socket, "221 bye\r\n").await;
+                        break;
+                    }
+                    _ => "250 ok\r\n".to_string(),
+                };
+                commands.push(line);
+                send(&mut socket, &reply).await;
+            }
+            commands
+        });
+        (port, server)
+    }
+
+    fn email(port: u16, security: SmtpSecurity) -> Email {
+        Email {
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            host: "127.0.0.1".to_string(),
+            port,
+            security,
+            user: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            from: None,
+        }
+    }
+
+    fn decode(body: &str) -> String {
+        let joined: String = body.split("\r\n").collect();
+        String::from_utf8(BASE64.decode(joined).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_to_stand_in() {
+        let (port, server) = stand_in("").await;
+        email(port, SmtpSecurity::None)
+            .send("xrenew", "Extend successful\nVPS 1001")
+            .await
+            .unwrap();
+        let commands = server.await.unwrap();
+        assert_eq!(
+            commands[..6],
+            [
+                "EHLO localhost".to_string(),
+                format!("AUTH PLAIN {}", BASE64.encode("\0user\0secret")),
+                "MAIL FROM:<user>".to_string(),
+                "RCPT TO:<a@example.com>".to_string(),
+                "RCPT TO:<b@example.com>".to_string(),
+                "DATA".to_string(),
+            ]
+        );
+        assert_eq!(commands[7], "QUIT");
+
+        let mail = &commands[6];
+        let (headers, body) = mail.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("To: a@example.com, b@example.com\r\n"));
+        assert!(headers.contains(&format!("Subject: =?UTF-8?B?{}?=", BASE64.encode("xrenew"))));
+        assert!(headers.contains("Content-Type: text/plain; charset=UTF-8"));
+        let body = body.strip_suffix("\r\n.\r\n").unwrap();
+        assert_eq!(decode(body), "Extend successful\r\nVPS 1001");
+    }
+
+    #[tokio::test]
+    async fn refuses_data_before_starttls_handshake() {
+        let (port, _server) = stand_in("250 injected\r\n").await;
+        let res = email(port, SmtpSecurity::StartTls)
+            .send("xrenew", "message")
+            .await;
+        assert!(
+            matches!(&res, Err(NotifyError::Smtp("STARTTLS", _))),
+            "{:?}",
+            res
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_on_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Accepts but never greets.
+        let _server = tokio::spawn(async move { listener.accept().await });
+        let res = email(port, SmtpSecurity::None)
+            .send("xrenew", "message")
+            .await;
+        assert!(
+            matches!(&res, Err(NotifyError::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut),
+            "{:?}",
+            res
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use bincode::{Decode, Encode};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{Attachment, Notifier, NotifyError, host};
+
+async fn post_json(url: &str, body: &Value) -> Result<(), NotifyError> {
+    crate::config::http_client()
+        .post(url)
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Discord {
+    /// Webhook URL
+    pub url: String,
+}
+
+impl Discord {
+    fn body(message: &str) -> Value {
+        json!({ "content": message })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Discord {
+    fn describe(&self) -> String {
+        format!("discord ({})", host(&self.url))
+    }
+
+    async fn send(&self, _title: &str, message: &str) -> Result<(), NotifyError> {
+        post_json(&self.url, &Discord::body(message)).await
+    }
+
+    async fn send_file(
+        &self,
+        _title: &str,
+        message: &str,
+        file: &Attachment,
+    ) -> Result<(), NotifyError> {
+        let form = Form::new()
+            .text("payload_json", Discord::body(message).to_string())
+            .part(
+                "files[0]",
+                Part::bytes(file.content.clone()).file_name(file.name.clone()),
+            );
+        crate::config::http_client()
+            .post(&self.url)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Slack {
+    /// Incoming webhook URL
+    pub url: String,
+}
+
+impl Slack {
+    fn body(title: &str, message: &str) -> Value {
+        json!({ "text": format!("*{}*\n{}", title, message) })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Slack {
+    fn describe(&self) -> String {
+        format!("slack ({})", host(&self.url))
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        post_json(&self.url, &Slack::body(title, message)).await
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Teams {
+    /// Workflow ("When a Teams webhook request is received") URL
+    pub url: String,
+}
+
+#[async_trait::async_trait]
+impl Notifier for Teams {
+    fn describe(&self) -> String {
+        format!("teams ({})", host(&self.url))
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        let body = json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "body": [
+                        { "type": "TextBlock", "text": title, "weight": "Bolder" },
+                        { "type": "TextBlock", "text": message, "wrap": true },
+                    ],
+                },
+            }],
+        });
+        post_json(&self.url, &body).await
+    }
+}
+
+pub const DEFAULT_TEMPLATE: &str = r#"{"title": "{{title}}", "message": "{{message}}"}"#;
+
+#[derive(Clone, Debug, Encode, Decode, clap::Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Json {
+    /// Webhook URL
+    pub url: String,
+    /// JSON body; `{{title}}` and `{{message}}` are replaced with the
+    /// JSON-escaped text, so keep them inside quotes
+    #[arg(long, default_value = DEFAULT_TEMPLATE)]
+    #[serde(default = "default_template")]
+    pub template: String,
+}
+
+fn default_template() -> String {
+    DEFAULT_TEMPLATE.to_string()
+}
+
+impl Json {
+    pub fn render(&self, title: &str, message: &str) -> Result<Value, NotifyError> {
+        let escape = |s: &str| {
+            let quoted = Value::String(s.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        let body = self
+            .template
+            .replace("{{title}}", &escape(title))
+            .replace("{{message}}", &escape(message));
+        serde_json::from_str(&body).map_err(|e| NotifyError::Template(e.to_string()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Json {
+    fn describe(&self) -> String {
+        format!("json ({})", host(&self.url))
+    }
+
+    async fn send(&self, title: &str, message: &str) -> Result<(), NotifyError> {
+        post_json(&self.url, &self.render(title, message)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "Extend failed: \"1001\"\nsee C:\\logs";
+
+    fn json(template: &str) -> Json {
+        Json {
+            url: "https://example.com/hook".to_string(),
+            template: template.to_string(),
+        }
+    }
+
+    #[test]
+    fn json_default_template_escapes_text() {
+        let body = json(DEFAULT_TEMPLATE)
+            .render("xrenew (work)", MESSAGE)
+            .unwrap();
+        assert_eq!(
+            body,
+            json!({ "title": "xrenew (work)", "message": MESSAGE })
+        );
+    }
+
+    #[test]
+    fn json_custom_template() {
+        let body =
+            json(r#"{"event": "renewal", "data": {"text": "{{title}}: {{message}}"}, "n": 1}"#)
+                .render("xrenew", "ok")
+                .unwrap();
+        assert_eq!(
+            body,
+            json!({ "event": "renewal", "data": { "text": "xrenew: ok" }, "n": 1 })
+        );
+        // Outside quotes the text is not valid JSON.
+        let res = json(r#"{"message": {{message}}}"#).render("xrenew", "ok");
+        assert!(matches!(res, Err(NotifyError::Template(_))), "{:?}", res);
+    }
+
+    #[test]
+    fn slack_puts_title_in_bold() {
+        assert_eq!(
+            Slack::body("xrenew", MESSAGE),
+            json!({ "text": format!("*xrenew*\n{}", MESSAGE) })
+        );
+    }
+
+    #[test]
+    fn discord_sends_message_as_content() {
+        assert_eq!(Discord::body(MESSAGE), json!({ "content": MESSAGE }));
+    }
+}
@@ -0,0 +1,52 @@
+use std::{future::Future, io, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time::timeout,
+};
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    rustls::{ClientConfig, RootCertStore, pki_types::ServerName},
+};
+
+/// Byte stream that may or may not be wrapped in TLS.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// How long a single connect, handshake, read or write may take before the
+/// server is given up on.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bounds one socket operation by [`IO_TIMEOUT`].
+pub async fn bounded<T>(op: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    timeout(IO_TIMEOUT, op)
+        .await
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")))
+}
+
+/// Starts TLS on `stream`, verifying `host` against the bundled web PKI roots.
+pub async fn connect<S>(host: &str, stream: S) -> io::Result<TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(io::Error::other)?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid host: {}", host),
+        )
+    })?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+}
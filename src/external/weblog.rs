@@ -1,14 +1,38 @@
-const API: &str = "https://xrenew.hiro.red/log";
+use bincode::{Decode, Encode};
 
-pub async fn send_log(txt: &str) -> reqwest::Result<String> {
-    let client = reqwest::Client::new();
+use crate::endpoint::ENDPOINTS;
+
+pub fn default_endpoint() -> String {
+    ENDPOINTS.service("log")
+}
+
+/// Remote log shipping. Off unless the user opts in.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct Telemetry {
+    pub enabled: bool,
+    /// Collector URL; `default_endpoint()` when unset.
+    pub endpoint: Option<String>,
+}
+
+impl Telemetry {
+    pub fn endpoint(&self) -> String {
+        self.endpoint.clone().unwrap_or_else(default_endpoint)
+    }
+}
+
+pub async fn send_log(txt: &str) -> reqwest::Result<Option<String>> {
+    let (telemetry, _) = crate::config::telemetry();
+    if !telemetry.enabled {
+        return Ok(None);
+    }
+    let client = crate::config::http_client();
     let res = client
-        .post(API)
+        .post(telemetry.endpoint())
         .body(txt.to_string())
         .send()
         .await?
         .error_for_status()?;
 
     let res = res.text().await?;
-    Ok(res)
+    Ok(Some(res))
 }
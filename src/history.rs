@@ -0,0 +1,467 @@
+use std::{fs::read_to_string, path::PathBuf, time::Duration};
+
+use bincode::{Decode, Encode};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::data::value::{DEFAULT_PROFILE, add_profile, list_profiles, profile_key};
+use crate::data::{self, SAVE_DIR, wipe_file};
+use crate::external::send_log;
+
+/// Records kept per profile; older ones are dropped.
+const MAX_RECORDS: usize = 1000;
+
+/// How far a run got before it finished or failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    #[default]
+    Session,
+    Login,
+    TwoFactor,
+    ServerList,
+    ExtendForm,
+    Extend,
+    Captcha,
+    CaptchaSubmit,
+    Done,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Phase::Session => "session",
+            Phase::Login => "login",
+            Phase::TwoFactor => "two-factor",
+            Phase::ServerList => "server-list",
+            Phase::ExtendForm => "extend-form",
+            Phase::Extend => "extend",
+            Phase::Captcha => "captcha",
+            Phase::CaptchaSubmit => "captcha-submit",
+            Phase::Done => "done",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Success,
+    Failure,
+    Skipped,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Outcome::Success => "SUCCESS",
+            Outcome::Failure => "FAILURE",
+            Outcome::Skipped => "SKIPPED",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Challenges shown on the extend page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptchaUsed {
+    Image,
+    Turnstile,
+    Both,
+}
+
+impl std::fmt::Display for CaptchaUsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CaptchaUsed::Image => "image",
+            CaptchaUsed::Turnstile => "turnstile",
+            CaptchaUsed::Both => "image+turnstile",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Progress of a run, filled in as it goes.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub phase: Phase,
+    pub captcha: Option<CaptchaUsed>,
+    pub solvers: Vec<&'static str>,
+}
+
+impl Trace {
+    pub fn solver(&self) -> Option<String> {
+        (!self.solvers.is_empty()).then(|| self.solvers.join("+"))
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode, Serialize)]
+pub struct RunRecord {
+    #[bincode(with_serde)]
+    pub at: DateTime<Utc>,
+    pub profile: String,
+    /// `None` when the run ended before any VPS was tried.
+    pub vps: Option<String>,
+    /// `None` for skipped runs and records imported from run.log.
+    pub phase: Option<Phase>,
+    pub captcha: Option<CaptchaUsed>,
+    pub solver: Option<String>,
+    pub duration_ms: u64,
+    pub outcome: Outcome,
+    pub message: String,
+    #[bincode(with_serde)]
+    pub expires: Option<NaiveDate>,
+}
+
+impl RunRecord {
+    pub fn new(profile: &str, outcome: Outcome, message: impl Into<String>) -> Self {
+        RunRecord {
+            at: Utc::now(),
+            profile: profile.to_string(),
+            vps: None,
+            phase: None,
+            captcha: None,
+            solver: None,
+            duration_ms: 0,
+            outcome,
+            message: message.into(),
+            expires: None,
+        }
+    }
+
+    pub fn with_trace(mut self, trace: &Trace, duration: Duration) -> Self {
+        self.phase = Some(trace.phase);
+        self.captcha = trace.captcha;
+        self.solver = trace.solver();
+        self.duration_ms = duration.as_millis() as u64;
+        self
+    }
+
+    pub fn local_time(&self) -> DateTime<Local> {
+        self.at.with_timezone(&Local)
+    }
+}
+
+impl std::fmt::Display for RunRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.local_time().to_rfc3339(), self.outcome)?;
+        if let Some(vps) = &self.vps {
+            write!(f, " {}:", vps)?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+fn history_prefix(profile: &str) -> String {
+    profile_key(profile, "history/")
+}
+
+/// Stores the records of one run and ships them as log lines when
+/// telemetry is enabled. The records get a common timestamp, which is how
+/// they are later grouped back into runs.
+pub async fn record(mut records: Vec<RunRecord>) {
+    let at = Utc::now();
+    for record in &mut records {
+        record.at = at;
+    }
+    store(&records);
+    for record in &records {
+        send_log(&record.to_string()).await.ok();
+    }
+}
+
+/// Stores records of one profile, dropping its oldest ones beyond
+/// `MAX_RECORDS`.
+pub fn store(records: &[RunRecord]) {
+    for (i, record) in records.iter().enumerate() {
+        let key = format!(
+            "{}{:020}-{:03}",
+            history_prefix(&record.profile),
+            record.at.timestamp_micros(),
+            i
+        );
+        data::put(key, record);
+    }
+    if let Some(profile) = records.first().map(|r| r.profile.as_str()) {
+        let keys = data::scan_keys(history_prefix(profile));
+        for key in keys.iter().take(keys.len().saturating_sub(MAX_RECORDS)) {
+            data::remove(key);
+        }
+    }
+}
+
+/// Records of a profile, oldest first.
+pub fn list(profile: &str) -> Vec<RunRecord> {
+    data::scan_prefix(history_prefix(profile))
+}
+
+pub fn last_success(profile: &str) -> Option<RunRecord> {
+    list(profile)
+        .into_iter()
+        .rev()
+        .find(|r| r.outcome == Outcome::Success)
+}
+
+fn log_path(profile: &str) -> PathBuf {
+    if profile == DEFAULT_PROFILE {
+        SAVE_DIR.join("run.log")
+    } else {
+        SAVE_DIR.join(format!("run.{}.log", profile))
+    }
+}
+
+/// Imports the free-text run.log files written by earlier versions.
+pub fn transfer_run_logs() {
+    let mut profiles = list_profiles();
+    if !profiles.iter().any(|p| p == DEFAULT_PROFILE) {
+        profiles.push(DEFAULT_PROFILE.to_string());
+    }
+    for profile in profiles {
+        let path = log_path(&profile);
+        let Ok(content) = read_to_string(&path) else {
+            continue;
+        };
+        add_profile(&profile);
+        for (i, line) in content.lines().enumerate() {
+            let Some((ts, msg)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(at) = DateTime::parse_from_rfc3339(ts) else {
+                continue;
+            };
+            let (outcome, message) = if let Some(m) = msg.strip_prefix("SUCCESS") {
+                (Outcome::Success, m.trim())
+            } else if let Some(m) = msg.strip_prefix("FAILURE") {
+                (Outcome::Failure, m.trim())
+            } else {
+                (Outcome::Skipped, msg)
+            };
+            let mut record = RunRecord::new(&profile, outcome, message);
+            record.at = at.with_timezone(&Utc);
+            let key = format!(
+                "{}{:020}-{:03}",
+                history_prefix(&profile),
+                record.at.timestamp_micros(),
+                i % 1000
+            );
+            data::put(key, &record);
+        }
+        wipe_file(&path);
+        println!("Run log of profile '{}' moved to history", profile);
+    }
+}
+
+/// The last `limit` records of `profiles` matching the filters, oldest
+/// first.
+fn select(
+    profiles: &[String],
+    outcome: Option<Outcome>,
+    vps: Option<&str>,
+    days: Option<i64>,
+    limit: usize,
+) -> Vec<RunRecord> {
+    let since = days.map(|d| Utc::now() - chrono::Duration::days(d));
+    let mut records: Vec<RunRecord> = profiles
+        .iter()
+        .flat_map(|p| list(p))
+        .filter(|r| outcome.is_none_or(|o| r.outcome == o))
+        .filter(|r| vps.is_none_or(|v| r.vps.as_deref() == Some(v)))
+        .filter(|r| since.is_none_or(|s| r.at >= s))
+        .collect();
+    records.sort_by_key(|r| r.at);
+    records.split_off(records.len().saturating_sub(limit))
+}
+
+fn json_line(record: &RunRecord) -> String {
+    serde_json::to_string(record).expect("Failed to serialize record")
+}
+
+/// Prints records matching the filters as a table (newest last) or as JSON
+/// Lines.
+pub fn show_history(
+    profiles: &[String],
+    outcome: Option<Outcome>,
+    vps: Option<&str>,
+    days: Option<i64>,
+    limit: usize,
+    json: bool,
+) {
+    let records = select(profiles, outcome, vps, days, limit);
+    if json {
+        for record in &records {
+            println!("{}", json_line(record));
+        }
+        return;
+    }
+    if records.is_empty() {
+        println!("No history");
+        return;
+    }
+
+    let header = [
+        "TIME", "PROFILE", "VPS", "OUTCOME", "PHASE", "CAPTCHA", "SOLVER", "DURATION", "EXPIRES",
+        "MESSAGE",
+    ];
+    let dash = || "-".to_string();
+    let rows: Vec<[String; 10]> = records
+        .iter()
+        .map(|r| {
+            [
+                r.local_time().format("%Y-%m-%d %H:%M").to_string(),
+                r.profile.clone(),
+                r.vps.clone().unwrap_or_else(dash),
+                r.outcome.to_string(),
+                r.phase.map(|p| p.to_string()).unwrap_or_else(dash),
+                r.captcha.map(|c| c.to_string()).unwrap_or_else(dash),
+                r.solver.clone().unwrap_or_else(dash),
+                if r.duration_ms > 0 {
+                    format!("{:.1}s", r.duration_ms as f64 / 1000.0)
+                } else {
+                    dash()
+                },
+                r.expires.map(|e| e.to_string()).unwrap_or_else(dash),
+                r.message.clone(),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(|h| h.chars().count());
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+    let print_row = |cells: &[String]| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (c, w))| {
+                if i + 1 == cells.len() {
+                    c.clone()
+                } else {
+                    format!("{}{}", c, " ".repeat(w - c.chars().count()))
+                }
+            })
+            .collect();
+        println!("{}", line.join("  "));
+    };
+    print_row(&header.map(String::from));
+    for row in &rows {
+        print_row(row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use Outcome::{Failure, Skipped, Success};
+
+    fn at(day: u32, secs: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2001, 1, day, 0, 0, secs).unwrap()
+    }
+
+    fn record(profile: &str, outcome: Outcome, message: &str, at: DateTime<Utc>) -> RunRecord {
+        let mut record = RunRecord::new(profile, outcome, message);
+        record.at = at;
+        record
+    }
+
+    #[test]
+    fn store_keeps_the_newest_records() {
+        let profile = "pruned";
+        for i in 0..MAX_RECORDS + 5 {
+            let at = at(1, 0) + chrono::Duration::seconds(i as i64);
+            store(&[record(profile, Success, &i.to_string(), at)]);
+        }
+        let records = list(profile);
+        assert_eq!(records.len(), MAX_RECORDS);
+        assert_eq!(records[0].message, "5");
+        assert_eq!(
+            records[MAX_RECORDS - 1].message,
+            (MAX_RECORDS + 4).to_string()
+        );
+    }
+
+    #[test]
+    fn run_logs_are_moved_into_the_history() {
+        let profile = "legacy";
+        add_profile(profile);
+        let path = log_path(profile);
+        std::fs::write(
+            &path,
+            "2024-05-01T09:00:00+09:00 SUCCESS Extended vps-1\n\
+             not a record\n\
+             2024-05-02T09:00:00+09:00 FAILURE Login failed\n\
+             2024-05-03T09:00:00+09:00 Renewal not open yet\n",
+        )
+        .unwrap();
+        transfer_run_logs();
+        assert!(!path.exists());
+
+        let records = list(profile);
+        let summary: Vec<(Outcome, &str)> = records
+            .iter()
+            .map(|r| (r.outcome, r.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Success, "Extended vps-1"),
+                (Failure, "Login failed"),
+                (Skipped, "Renewal not open yet"),
+            ]
+        );
+        assert_eq!(
+            records[0].at,
+            Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()
+        );
+        assert!(records.iter().all(|r| r.phase.is_none()));
+    }
+
+    #[test]
+    fn json_lines_hold_every_field() {
+        let profile = "json";
+        let mut failure = record(profile, Failure, "Wrong captcha", at(1, 0));
+        failure.vps = Some("vps-1".to_string());
+        failure.phase = Some(Phase::CaptchaSubmit);
+        failure.captcha = Some(CaptchaUsed::Both);
+        failure.solver = Some("local+hosted".to_string());
+        failure.duration_ms = 1500;
+        failure.expires = NaiveDate::from_ymd_opt(2001, 1, 3);
+        store(&[failure]);
+        store(&[record(profile, Skipped, "Not due", at(2, 0))]);
+        let mut success = record(profile, Success, "Extended", at(3, 0));
+        success.vps = Some("vps-1".to_string());
+        store(&[success]);
+
+        let profiles = [profile.to_string()];
+        let lines: Vec<String> = select(&profiles, Some(Failure), Some("vps-1"), None, 10)
+            .iter()
+            .map(json_line)
+            .collect();
+        assert_eq!(
+            lines,
+            [concat!(
+                r#"{"at":"2001-01-01T00:00:00Z","profile":"json","vps":"vps-1","#,
+                r#""phase":"captcha-submit","captcha":"both","solver":"local+hosted","#,
+                r#""duration_ms":1500,"outcome":"failure","message":"Wrong captcha","#,
+                r#""expires":"2001-01-03"}"#
+            )]
+        );
+
+        let newest = select(&profiles, None, None, None, 1);
+        assert_eq!(
+            json_line(&newest[0]),
+            concat!(
+                r#"{"at":"2001-01-03T00:00:00Z","profile":"json","vps":"vps-1","phase":null,"#,
+                r#""captcha":null,"solver":null,"duration_ms":0,"outcome":"success","#,
+                r#""message":"Extended","expires":null}"#
+            )
+        );
+        assert_eq!(select(&profiles, None, Some("vps-2"), None, 10).len(), 0);
+    }
+}
@@ -1,266 +1,821 @@
-use std::time::Duration;
-use tokio::time::sleep;
+use std::{
+    io::{IsTerminal, Read},
+    time::Duration,
+};
+use tokio::time::{Instant, sleep};
 
 use crate::{
-    cli::{Cli, Commands},
+    cli::{Cli, Commands, ConfigAction, LoginArgs, NotifyAction, OcrAction, Switch},
     client::{
-        Account, CaptchaResponse, DEFAULT_CLIENT, ExtendResponse, LoginStatus, get_server_id,
-        save_default_client,
+        Account, CaptchaResponse, EMAIL_VAR, ExtendResponse, LoginStatus, PASSWORD_VAR, Server,
+        account, account_profiles, get_server_ids, load_client, save_client,
     },
     data::{
-        initialize_db,
-        value::{get_account, get_two_captcha_key, set_account},
+        NewKey, initialize_db,
+        value::{
+            DEFAULT_PROFILE, get_account, get_two_captcha_key, remove_cookie, remove_servers,
+            set_account, set_servers,
+        },
+    },
+    error::{RunError, Step},
+    external::{
+        CaptchaKind, Discord, ImapConfig, NotifierConfig, corpus_dir, imap_latest_uid,
+        imap_wait_for_code, ocr_evaluate, ocr_train, save_sample, solve_image, solve_turnstile,
+        solver_chain,
     },
-    external::{send_webhook, two_captcha_solve},
+    history::{CaptchaUsed, Outcome, Phase, RunRecord, Trace},
 };
 use clap::Parser;
 
-use crate::external::solve_captcha;
-
 mod cli;
 mod client;
+mod config;
+mod daemon;
 mod data;
+mod doctor;
+mod endpoint;
+mod error;
 mod external;
-mod logger;
+mod history;
 mod ops;
+mod policy;
+mod prompt;
+mod retry;
+mod schedule;
 mod task;
 mod update;
 
-use ops::{clear_data, set_two_captcha_key, set_webhook, show_status};
-use task::{disable_auto, enable_auto, refresh_auto, should_run};
+use ops::{
+    add_notifier, clear_data, list_notifiers, prompt_telemetry, rekey, remove_imap,
+    remove_notifier, set_captcha_key, set_captcha_order, set_imap, set_notify_policy, set_retry,
+    set_telemetry, set_two_captcha_key, show_captcha, show_imap, show_profiles, show_status,
+    test_notifiers,
+};
+use policy::notify_run;
+use retry::RetryPolicy;
+use task::{disable_auto, enable_auto, refresh_auto, skip_reason};
 use update::update;
 
-#[derive(Debug)]
-enum ExtendError {
-    CaptchaFailure(String),
-    Other(String),
-}
-
-impl std::fmt::Display for ExtendError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ExtendError::CaptchaFailure(msg) | ExtendError::Other(msg) => write!(f, "{}", msg),
-        }
-    }
-}
-
-impl std::error::Error for ExtendError {}
-
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    if let Commands::Daemon = cli.command {
+        // Runs are child processes; keep the database free for them.
+        daemon::run_daemon().await;
+        return;
+    }
+    let profile = cli.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+    client::select_profile(profile);
+    if let Commands::Doctor = cli.command {
+        // Opens the database itself, to report when it cannot.
+        doctor::run_doctor().await;
+        return;
+    }
     initialize_db();
     match cli.command {
-        Commands::Login => login_flow().await,
-        Commands::Extend { auto } => extend_flow(auto).await,
+        Commands::Login(args) => login_flow(profile, args).await,
+        Commands::Extend {
+            auto,
+            all,
+            vps,
+            dry_run,
+        } => {
+            if dry_run {
+                let profiles = if all {
+                    account_profiles()
+                } else {
+                    vec![profile.to_string()]
+                };
+                dry_run_all(&profiles, &vps).await;
+            } else if all {
+                extend_all(auto, &vps).await;
+            } else if let Err(failed) = extend_flow(profile, auto, &vps).await {
+                std::process::exit(failed.code);
+            }
+        }
         Commands::Status => {
-            show_status();
+            show_status(profile);
         }
-        Commands::Enable => enable_auto(),
+        Commands::Enable(args) => enable_auto(args.plan.plan(), args.backend),
+        Commands::Daemon => unreachable!("handled before opening the database"),
         Commands::Disable => disable_auto(),
-        Commands::Clear => clear_data(),
-        Commands::Captcha { key } => set_two_captcha_key(&key),
-        Commands::Webhook { url } => set_webhook(&url),
+        Commands::Profiles => show_profiles(),
+        Commands::Clear => clear_data(cli.profile.as_deref()),
+        Commands::Captcha {
+            key,
+            service,
+            order,
+        } => {
+            if let Some(key) = key {
+                set_captcha_key(service, &key);
+            }
+            if !order.is_empty() {
+                set_captcha_order(&order);
+            }
+            show_captcha();
+        }
+        Commands::Ocr { action } => match action {
+            OcrAction::Train { dir } => ocr_train(&dir.unwrap_or_else(corpus_dir)),
+            OcrAction::Eval { dir } => ocr_evaluate(&dir.unwrap_or_else(corpus_dir)),
+        },
+        Commands::Notify { action } => match action {
+            NotifyAction::Add { channel } => add_notifier(profile, channel),
+            NotifyAction::List => list_notifiers(profile),
+            NotifyAction::Remove { index } => remove_notifier(profile, index),
+            NotifyAction::Test { index } => test_notifiers(profile, index).await,
+            NotifyAction::Policy(args) => set_notify_policy(profile, args),
+        },
+        Commands::Webhook { url } => {
+            add_notifier(profile, NotifierConfig::Discord(Discord { url }))
+        }
+        Commands::Imap {
+            host,
+            port,
+            user,
+            mailbox,
+            from,
+            plain,
+            remove,
+        } => {
+            if remove {
+                remove_imap(profile);
+            } else if let Some(host) = host {
+                let Some(username) = user.or_else(|| account(profile).map(|a| a.email)) else {
+                    println!("No IMAP user given and no account configured");
+                    return;
+                };
+                let question = format!("Please enter the IMAP password for {}:", username);
+                let Some(password) = prompt::secret(&question) else {
+                    println!("No IMAP password given");
+                    return;
+                };
+                let config = ImapConfig {
+                    host,
+                    port,
+                    tls: !plain,
+                    username,
+                    password,
+                    mailbox,
+                    from,
+                };
+                set_imap(profile, &config);
+            } else {
+                show_imap(profile);
+            }
+        }
+        Commands::Retry {
+            attempts,
+            backoff,
+            max_backoff,
+            on,
+            reset,
+        } => set_retry(attempts, backoff, max_backoff, on, reset),
+        Commands::Telemetry {
+            state,
+            endpoint,
+            reset_endpoint,
+        } => {
+            let endpoint = if reset_endpoint {
+                Some(None)
+            } else {
+                endpoint.map(Some)
+            };
+            set_telemetry(state.map(Switch::enabled), endpoint);
+        }
+        Commands::Config { action } => match action {
+            ConfigAction::Show => config::show(),
+            ConfigAction::Validate => config::validate(),
+            ConfigAction::Edit => config::edit(),
+        },
         Commands::Update { auto } => update(auto).await,
+        Commands::History {
+            outcome,
+            vps,
+            days,
+            limit,
+            all,
+            json,
+        } => {
+            let profiles = if all {
+                config::profiles()
+            } else {
+                vec![profile.to_string()]
+            };
+            history::show_history(&profiles, outcome, vps.as_deref(), days, limit, json);
+        }
         Commands::Refresh => refresh_auto(),
+        Commands::Doctor => unreachable!("handled before opening the database"),
+        Commands::Rekey {
+            passphrase,
+            key_file,
+        } => rekey(match (passphrase, key_file) {
+            (true, _) => NewKey::Passphrase,
+            (false, Some(path)) => NewKey::File(path),
+            (false, None) => NewKey::Generate,
+        }),
     }
 }
 
-async fn login_flow() {
-    // handle account input/update
-    {
-        if let Some(account) = get_account() {
+async fn login_flow(profile: &str, args: LoginArgs) {
+    let interactive = std::io::stdin().is_terminal() && !args.password_stdin;
+    let password = match read_password(&args) {
+        Ok(password) => password,
+        Err(e) => {
+            eprintln!("Failed to read the password: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let email = args
+        .email
+        .or_else(|| std::env::var(EMAIL_VAR).ok())
+        .filter(|e| !e.is_empty());
+
+    let current = get_account(profile);
+    let update = match &current {
+        Some(account) if email.is_none() && password.is_none() => {
             println!("Current account: {}", account.email);
-            println!("Update credentials? (y/N)");
-            let mut buf = String::new();
-            std::io::stdin().read_line(&mut buf).unwrap();
-            if buf.trim().eq_ignore_ascii_case("y") {
-                buf.clear();
-                println!("Please enter your email:");
-                std::io::stdin().read_line(&mut buf).unwrap();
-                let email = buf.trim().to_string();
-                buf.clear();
-                println!("Please enter your password:");
-                std::io::stdin().read_line(&mut buf).unwrap();
-                let password = buf.trim().to_string();
-                let acc = Account { email, password };
-                set_account(&acc);
+            interactive && prompt::confirm("Update credentials? (y/N)")
+        }
+        _ => true,
+    };
+    if update {
+        // A new password alone keeps the stored email.
+        let kept = current
+            .as_ref()
+            .filter(|_| email.is_none() && password.is_some())
+            .map(|a| a.email.clone());
+        let ask = |question: &str, read: fn(&str) -> Option<String>| {
+            if interactive {
+                read(question).filter(|s| !s.is_empty())
+            } else {
+                None
             }
-        } else {
-            let mut buf = String::new();
-            println!("Please enter your email:");
-            std::io::stdin().read_line(&mut buf).unwrap();
-            let email = buf.trim().to_string();
-            buf.clear();
-            println!("Please enter your password:");
-            std::io::stdin().read_line(&mut buf).unwrap();
-            let password = buf.trim().to_string();
-            let acc = Account { email, password };
-            set_account(&acc);
+        };
+        let email = email
+            .or(kept)
+            .or_else(|| ask("Please enter your email:", prompt::line));
+        let password = password.or_else(|| ask("Please enter your password:", prompt::secret));
+        let (Some(email), Some(password)) = (email, password) else {
+            eprintln!(
+                "Email and password required: use --email with --password-stdin or --password-file, or set {} and {}",
+                EMAIL_VAR, PASSWORD_VAR
+            );
+            std::process::exit(2);
+        };
+        let changed = current
+            .as_ref()
+            .is_none_or(|a| a.email != email || a.password != password);
+        if changed {
+            // The saved session and VPS list belong to the old credentials,
+            // which would skip checking the new ones against the panel.
+            remove_cookie(profile);
+            remove_servers(profile);
+        }
+        set_account(profile, &Account { email, password });
+        if config::account(profile).1 == config::Source::File {
+            println!(
+                "Note: config.toml sets the account of profile '{}', so this one is not used",
+                profile
+            );
         }
     }
 
-    if get_two_captcha_key().is_none() {
-        let mut buf = String::new();
-        println!("Please enter your TwoCaptcha API key:");
-        std::io::stdin().read_line(&mut buf).unwrap();
-        let key = buf.trim().to_string();
-        if !key.is_empty() {
+    if interactive {
+        prompt_telemetry();
+        if get_two_captcha_key().is_none()
+            && let Some(key) =
+                prompt::line("Please enter your TwoCaptcha API key:").filter(|k| !k.is_empty())
+        {
             set_two_captcha_key(&key);
         }
     }
 
-    match do_login_and_extend_with_retry(&DEFAULT_CLIENT, true).await {
-        Ok(msg) => {
-            logger::log_message(&format!("SUCCESS {}", msg)).await;
-            send_webhook(&format!("Extend successful: {}", msg)).await;
+    if let Err(failed) = run(profile, interactive, &[]).await {
+        std::process::exit(failed.code);
+    }
+}
+
+/// Password given with `--password-stdin`, `--password-file` or
+/// `XRENEW_PASSWORD`. Only the first line of a file or stdin is used.
+fn read_password(args: &LoginArgs) -> std::io::Result<Option<String>> {
+    let text = if args.password_stdin {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else if let Some(path) = &args.password_file {
+        std::fs::read_to_string(path)?
+    } else {
+        return Ok(std::env::var(PASSWORD_VAR).ok());
+    };
+    Ok(text.lines().next().map(str::to_owned))
+}
+
+async fn extend_flow(profile: &str, auto: bool, vps: &[String]) -> Result<String, Failed> {
+    if auto && let Some(msg) = skip_reason(profile, vps) {
+        history::record(vec![RunRecord::new(profile, Outcome::Skipped, &msg)]).await;
+        notify_run(profile, &msg, None).await;
+        return Ok(msg);
+    }
+
+    run(profile, false, vps).await
+}
+
+async fn run(profile: &str, interactive: bool, vps: &[String]) -> Result<String, Failed> {
+    let client = load_client(profile);
+    let retry = config::retry_policy();
+    let started = Instant::now();
+    let mut trace = Trace::default();
+    let res = do_login_and_extend(profile, &client, &retry, interactive, vps, &mut trace).await;
+    report(profile, res.map_err(|e| (e, trace, started.elapsed()))).await
+}
+
+struct VpsResult {
+    server: Server,
+    result: Result<String, RunError>,
+    trace: Trace,
+    duration: Duration,
+}
+
+/// A failed run: what went wrong and the exit code of its first error.
+struct Failed {
+    summary: String,
+    code: i32,
+}
+
+/// Records and notifies the outcome of a run. The run succeeds only when
+/// every selected VPS was extended.
+async fn report(
+    profile: &str,
+    res: Result<Vec<VpsResult>, (RunError, Trace, Duration)>,
+) -> Result<String, Failed> {
+    let results = match res {
+        Ok(results) => results,
+        Err((e, trace, duration)) => {
+            history::record(vec![
+                RunRecord::new(profile, Outcome::Failure, e.to_string())
+                    .with_trace(&trace, duration),
+            ])
+            .await;
+            notify_run(profile, &format!("Extend failed: {}", e), e.snapshot()).await;
+            return Err(Failed {
+                summary: e.to_string(),
+                code: e.exit_code(),
+            });
         }
-        Err(e) => {
-            logger::log_message(&format!("FAILURE {}", e)).await;
-            send_webhook(&format!("Extend failed: {}", e)).await;
+    };
+    let records: Vec<RunRecord> = results
+        .iter()
+        .map(|r| {
+            let (outcome, message) = match &r.result {
+                Ok(msg) => (Outcome::Success, msg.clone()),
+                Err(e) => (Outcome::Failure, e.to_string()),
+            };
+            let mut record =
+                RunRecord::new(profile, outcome, message).with_trace(&r.trace, r.duration);
+            record.vps = Some(r.server.id.clone());
+            record.expires = r.server.expires;
+            record
+        })
+        .collect();
+    history::record(records).await;
+    let lines: Vec<String> = results
+        .iter()
+        .map(|r| match &r.result {
+            Ok(msg) => match r.server.expires {
+                Some(expires) => format!("{}: OK {} (expires {})", r.server, msg, expires),
+                None => format!("{}: OK {}", r.server, msg),
+            },
+            Err(e) => format!("{}: FAILED {}", r.server, e),
+        })
+        .collect();
+    let summary = lines.join("; ");
+    let first_error = results.iter().find_map(|r| r.result.as_ref().err());
+    if let Some(e) = first_error {
+        let snapshot = results
+            .iter()
+            .find_map(|r| r.result.as_ref().err().and_then(RunError::snapshot));
+        notify_run(
+            profile,
+            &format!("Extend failed:\n{}", lines.join("\n")),
+            snapshot,
+        )
+        .await;
+        Err(Failed {
+            summary,
+            code: e.exit_code(),
+        })
+    } else {
+        notify_run(
+            profile,
+            &format!("Extend successful:\n{}", lines.join("\n")),
+            None,
+        )
+        .await;
+        Ok(summary)
+    }
+}
+
+async fn extend_all(auto: bool, vps: &[String]) {
+    let profiles = account_profiles();
+    if profiles.is_empty() {
+        println!("No account configured. Run 'xrenew login' first.");
+        std::process::exit(RunError::NoAccount.exit_code());
+    }
+    let mut results = Vec::new();
+    for profile in &profiles {
+        println!("[{}] Extending...", profile);
+        results.push((profile, extend_flow(profile, auto, vps).await));
+    }
+    println!("Results:");
+    let mut code = 0;
+    for (profile, res) in results {
+        match res {
+            Ok(msg) => println!("  {}: OK - {}", profile, msg),
+            Err(failed) => {
+                println!("  {}: FAILED - {}", profile, failed.summary);
+                if code == 0 {
+                    code = failed.code;
+                }
+            }
         }
     }
+    if code != 0 {
+        std::process::exit(code);
+    }
 }
 
-async fn extend_flow(auto: bool) {
-    if auto && !should_run() {
-        let msg = "Skip: last success within 23h";
-        logger::log_message(msg).await;
-        send_webhook(msg).await;
-        return;
+async fn dry_run_all(profiles: &[String], vps: &[String]) {
+    let mut code = 0;
+    for profile in profiles {
+        if profiles.len() > 1 {
+            println!("[{}]", profile);
+        }
+        if let Err(e) = dry_run(profile, vps).await {
+            println!("Dry run failed: {}", e);
+            if code == 0 {
+                code = e.exit_code();
+            }
+        }
+    }
+    if profiles.is_empty() {
+        println!("No account configured. Run 'xrenew login' first.");
+        code = RunError::NoAccount.exit_code();
+    }
+    if code != 0 {
+        std::process::exit(code);
     }
+}
+
+/// Logs in and inspects the extend page of each VPS without submitting it,
+/// so neither a renewal attempt nor solver credit is used.
+async fn dry_run(profile: &str, vps: &[String]) -> Result<(), RunError> {
+    let client = load_client(profile);
+    let retry = config::retry_policy();
+    let mut trace = Trace::default();
+    let servers = open_servers(profile, &client, &retry, false, vps, &mut trace).await?;
+    save_client(profile, &client);
+    println!("Login OK");
 
-    match do_login_and_extend_with_retry(&DEFAULT_CLIENT, false).await {
-        Ok(msg) => {
-            logger::log_message(&format!("SUCCESS {}", msg)).await;
-            send_webhook(&format!("Extend successful: {}", msg)).await;
+    let history = history::list(profile);
+    let mut missing_form = None;
+    for server in servers {
+        let preview = retry
+            .step("extend vps", || async {
+                client.preview_extend(&server.id).await.step("extend vps")
+            })
+            .await?;
+        let window = match (&preview.unavailable, server.renewal_opens_at()) {
+            (Some(msg), _) => format!("not open ({})", msg),
+            (None, Some(opens_at)) if chrono::Local::now() < opens_at => format!(
+                "opens {} according to the stored expiry",
+                opens_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+            ),
+            (None, _) => "open".to_string(),
+        };
+        match server.expires {
+            Some(expires) => println!("VPS {}: expires {}", server, expires),
+            None => println!("VPS {}: expiry unknown", server),
         }
-        Err(e) => {
-            logger::log_message(&format!("FAILURE {}", e)).await;
-            send_webhook(&format!("Extend failed: {}", e)).await;
+        println!("  Renewal window: {}", window);
+        match &preview.form {
+            Some(form) => {
+                let fields: Vec<String> = form
+                    .fields
+                    .iter()
+                    .map(|f| format!("{} ({})", f.name, f.r#type))
+                    .collect();
+                println!(
+                    "  Extend form: {} {}",
+                    form.method.as_deref().unwrap_or("GET"),
+                    form.action.as_deref().unwrap_or("-")
+                );
+                println!("  Fields: {}", fields.join(", "));
+            }
+            None if preview.unavailable.is_some() => println!("  Extend form: not shown"),
+            None => {
+                println!("  Extend form: not found, the panel layout may have changed");
+                missing_form.get_or_insert_with(|| preview.missing_form());
+            }
         }
+        let shown = match (preview.image_captcha, preview.turnstile) {
+            (true, true) => Some(CaptchaUsed::Both),
+            (true, false) => Some(CaptchaUsed::Image),
+            (false, true) => Some(CaptchaUsed::Turnstile),
+            (false, false) => None,
+        };
+        let last = history
+            .iter()
+            .rev()
+            .find(|r| r.vps.as_ref() == Some(&server.id) && r.captcha.is_some())
+            .and_then(|r| r.captcha);
+        println!(
+            "  Captcha: {}{}",
+            match shown {
+                Some(captcha) => format!("{} on the form page", captcha),
+                None => "none on the form page".to_string(),
+            },
+            match last {
+                Some(captcha) => format!(", last run asked for {}", captcha),
+                None => String::new(),
+            }
+        );
+    }
+    match missing_form {
+        Some(e) => Err(e).step("extend vps"),
+        None => Ok(()),
     }
 }
 
-async fn do_login_and_extend_with_retry(
+async fn extend_server_with_retry(
     client: &client::Client,
-    interactive: bool,
-) -> Result<String, ExtendError> {
-    let mut attempts = 0;
+    retry: &RetryPolicy,
+    server: &Server,
+    trace: &mut Trace,
+) -> Result<String, RunError> {
+    let mut attempt = 1;
     loop {
-        match do_login_and_extend(client, interactive).await {
+        *trace = Trace::default();
+        match extend_server(client, retry, server, trace).await {
             Ok(msg) => return Ok(msg),
-            Err(ExtendError::CaptchaFailure(msg)) if attempts < 2 => {
-                println!("Captcha failed. ({})", msg);
-                attempts += 1;
-                println!("Retrying in 60 seconds... ({}/{})", attempts + 1, 3);
-                sleep(Duration::from_secs(60)).await;
-            }
-            Err(e) => return Err(e),
+            Err(e) => match retry.restart_delay(
+                attempt,
+                &e,
+                matches!(trace.phase, Phase::Extend | Phase::CaptchaSubmit),
+            ) {
+                Some(delay) => {
+                    attempt += 1;
+                    sleep(delay).await;
+                }
+                None => return Err(e),
+            },
         }
     }
 }
 
 async fn do_login_and_extend(
+    profile: &str,
     client: &client::Client,
+    retry: &RetryPolicy,
     interactive: bool,
-) -> Result<String, ExtendError> {
-    let form = client
-        .login_page()
-        .await
-        .map_err(|e| ExtendError::Other(format!("login page: {}", e)))?;
-    let account =
-        get_account().ok_or_else(|| ExtendError::Other("No account found".to_string()))?;
-    let login_res = client
-        .try_login(&form, &account)
-        .await
-        .map_err(|e| ExtendError::Other(format!("login: {}", e)))?;
+    vps: &[String],
+    trace: &mut Trace,
+) -> Result<Vec<VpsResult>, RunError> {
+    let servers = open_servers(profile, client, retry, interactive, vps, trace).await?;
+
+    let mut results = Vec::new();
+    for server in servers {
+        println!("Extending {}...", server);
+        let started = Instant::now();
+        let mut trace = Trace::default();
+        let result = extend_server_with_retry(client, retry, &server, &mut trace).await;
+        results.push(VpsResult {
+            server,
+            result,
+            trace,
+            duration: started.elapsed(),
+        });
+    }
+
+    if results.iter().any(|r| r.result.is_ok()) {
+        match retry
+            .step("refresh VPS list", || async {
+                client.list_servers().await.step("refresh VPS list")
+            })
+            .await
+        {
+            Ok(servers) if !servers.is_empty() => {
+                for r in &mut results {
+                    if let Some(s) = servers.iter().find(|s| s.id == r.server.id) {
+                        r.server = s.clone();
+                    }
+                }
+                set_servers(profile, &servers);
+            }
+            Ok(_) => println!("Failed to refresh VPS list: no VPS found"),
+            Err(e) => println!("Failed to refresh VPS list: {}", e),
+        }
+    }
+    save_client(profile, client);
+    Ok(results)
+}
+
+/// Logs in unless the saved session is still valid and returns the selected
+/// VPSs.
+async fn open_servers(
+    profile: &str,
+    client: &client::Client,
+    retry: &RetryPolicy,
+    interactive: bool,
+    vps: &[String],
+    trace: &mut Trace,
+) -> Result<Vec<Server>, RunError> {
+    trace.phase = Phase::Session;
+    let html = match retry
+        .step("session check", || async {
+            client.check_session().await.step("session check")
+        })
+        .await?
+    {
+        Some(html) => {
+            println!("Session still valid, skipping login");
+            html
+        }
+        None => login(profile, client, retry, interactive, trace).await?,
+    };
+
+    trace.phase = Phase::ServerList;
+    let mut servers = get_server_ids(&html);
+    if servers.is_empty() {
+        return Err(RunError::NoServers);
+    }
+    client.fill_expiry(&mut servers).await;
+    set_servers(profile, &servers);
+    if !vps.is_empty() {
+        servers.retain(|s| vps.contains(&s.id));
+        if servers.is_empty() {
+            return Err(RunError::NoServerMatched(vps.join(", ")));
+        }
+    }
+    Ok(servers)
+}
+
+/// Performs a full login and returns the VPS list page.
+async fn login(
+    profile: &str,
+    client: &client::Client,
+    retry: &RetryPolicy,
+    interactive: bool,
+    trace: &mut Trace,
+) -> Result<String, RunError> {
+    trace.phase = Phase::Login;
+    let account = account(profile).ok_or(RunError::NoAccount)?;
+    // Each try posts a freshly fetched login form rather than the same one
+    // again.
+    let login_res = retry
+        .step("login", || async {
+            let form = client.login_page().await.step("login page")?;
+            client.try_login(&form, &account).await.step("login")
+        })
+        .await?;
     let html;
     match login_res {
         LoginStatus::Success(text) => html = text,
-        LoginStatus::Failure(msg) => return Err(ExtendError::Other(msg)),
+        LoginStatus::Failure(msg) => return Err(RunError::BadCredentials(msg)),
         LoginStatus::TowWayAuthRequired(form, email) => {
-            if !interactive {
-                return Err(ExtendError::Other("Two-way authentication required".into()));
+            trace.phase = Phase::TwoFactor;
+            let (imap, _) = config::imap(profile);
+            if !interactive && imap.is_none() {
+                return Err(RunError::TwoFactorRequired);
             }
             if let Some(email) = email {
                 println!("Two-way authentication required. Email: {}", email);
             } else {
                 println!("Two-way authentication required.");
             }
+            let baseline = match &imap {
+                Some(imap) => Some(imap_latest_uid(imap).await?),
+                None => None,
+            };
+            // Not retried: each POST mails another code.
             let form = client
                 .two_way_select_email(&form)
                 .await
-                .map_err(|e| ExtendError::Other(format!("auth select: {}", e)))?;
-            let code = {
-                let mut buf = String::new();
-                println!("Please enter the authentication code sent to your email:");
-                std::io::stdin().read_line(&mut buf).unwrap();
-                buf.trim().to_string()
+                .step("auth select")?;
+            let code = match (&imap, baseline) {
+                (Some(imap), Some(baseline)) => {
+                    println!("Waiting for the authentication mail in {}...", imap.mailbox);
+                    imap_wait_for_code(
+                        imap,
+                        baseline,
+                        Duration::from_secs(300),
+                        Duration::from_secs(10),
+                    )
+                    .await?
+                }
+                _ => prompt::line("Please enter the authentication code sent to your email:")
+                    .ok_or(RunError::TwoFactorRequired)?,
             };
+            // Not retried: the code may already be used up.
             match client
                 .two_way_auth(&form, &code)
                 .await
-                .map_err(|e| ExtendError::Other(format!("two-way auth: {}", e)))?
+                .step("two-way auth")?
             {
                 LoginStatus::Success(text) => {
                     html = text;
                 }
-                _ => return Err(ExtendError::Other("Two-way authentication failed".into())),
+                LoginStatus::Failure(msg) => return Err(RunError::TwoFactorFailed(msg)),
+                LoginStatus::TowWayAuthRequired(..) => {
+                    return Err(RunError::TwoFactorFailed(
+                        "Asked for authentication again".into(),
+                    ));
+                }
             }
         }
     }
 
-    save_default_client();
+    save_client(profile, client);
+    Ok(html)
+}
 
-    let vps = get_server_id(&html).ok_or_else(|| ExtendError::Other("No VPS found".to_string()))?;
-    let extend_form = client
-        .extend_vps(&vps)
-        .await
-        .map_err(|e| ExtendError::Other(format!("extend vps: {}", e)))?;
+async fn extend_server(
+    client: &client::Client,
+    retry: &RetryPolicy,
+    server: &Server,
+    trace: &mut Trace,
+) -> Result<String, RunError> {
+    trace.phase = Phase::ExtendForm;
+    let extend_form = retry
+        .step("extend vps", || async {
+            client.extend_vps(&server.id).await.step("extend vps")
+        })
+        .await?;
+    // The POSTs below are sent once; a transient failure starts the
+    // extension over from the form page, see `extend_server_with_retry`.
+    trace.phase = Phase::Extend;
     match client
         .submit_extend_form(&extend_form)
         .await
-        .map_err(|e| ExtendError::Other(format!("submit extend: {}", e)))?
+        .step("submit extend")?
     {
         ExtendResponse::Success(msg) => {
             println!("Extend successful: {}", msg);
+            trace.phase = Phase::Done;
             Ok(msg)
         }
-        ExtendResponse::Failure(msg) => {
+        ExtendResponse::Unavailable(msg) => {
             println!("Extend failed: {}", msg);
-            Err(ExtendError::Other(msg))
+            Err(RunError::NotRenewable(msg))
         }
         ExtendResponse::CaptchaRequired(captcha) => {
             println!("Captcha required (Solving...)");
-            let turnstile_response =
-                if captcha.cloudflare_challenge().is_some() {
-                    Some(two_captcha_solve(&captcha).await.map_err(|e| {
-                        ExtendError::CaptchaFailure(format!("TwoCaptcha solve: {}", e))
-                    })?)
-                } else {
-                    None
-                };
-            let code =
-                if captcha.has_image() {
-                    Some(solve_captcha(&captcha).await.map_err(|e| {
-                        ExtendError::CaptchaFailure(format!("Captcha solve: {}", e))
-                    })?)
-                } else {
-                    None
-                };
+            trace.phase = Phase::Captcha;
+            trace.captcha = match (captcha.has_image(), captcha.cloudflare_challenge()) {
+                (true, Some(_)) => Some(CaptchaUsed::Both),
+                (true, None) => Some(CaptchaUsed::Image),
+                (false, Some(_)) => Some(CaptchaUsed::Turnstile),
+                (false, None) => None,
+            };
+            let chain = solver_chain();
+            let turnstile_response = if captcha.cloudflare_challenge().is_some() {
+                let (response, solver) = solve_turnstile(&chain, &captcha)
+                    .await
+                    .map_err(|e| RunError::captcha(CaptchaKind::Turnstile, e))?;
+                trace.solvers.push(solver);
+                Some(response)
+            } else {
+                None
+            };
+            let code = if captcha.has_image() {
+                let (code, solver) = solve_image(&chain, &captcha)
+                    .await
+                    .map_err(|e| RunError::captcha(CaptchaKind::Image, e))?;
+                if !trace.solvers.contains(&solver) {
+                    trace.solvers.push(solver);
+                }
+                Some(code)
+            } else {
+                None
+            };
+            trace.phase = Phase::CaptchaSubmit;
             let res = client
-                .submit_captcha(&captcha, code, turnstile_response)
+                .submit_captcha(&captcha, code.as_deref(), turnstile_response)
                 .await
-                .map_err(|e| ExtendError::CaptchaFailure(format!("Captcha submit: {}", e)))?;
+                .step("captcha submit")?;
             match res {
                 CaptchaResponse::Success(msg) => {
+                    if let Some(code) = &code {
+                        save_sample(&captcha, code);
+                    }
                     println!("Extend successful(with captcha): {}", msg);
+                    trace.phase = Phase::Done;
                     Ok(msg)
                 }
-                CaptchaResponse::Failure(msg) => {
+                CaptchaResponse::Unavailable(msg) => {
                     println!("Extend failed(with captcha): {}", msg);
-                    Err(ExtendError::CaptchaFailure(msg))
+                    Err(RunError::NotRenewable(msg))
                 }
             }
         }
@@ -1,46 +1,456 @@
 use crate::{
-    data::{self, remove_all},
-    logger,
-    task::is_auto_enabled,
+    cli::{PolicyArgs, Switch},
+    client::{EMAIL_VAR, account, account_from_env},
+    config::{self, Source},
+    data::{self, NewKey, remove_all},
+    endpoint::ENDPOINTS,
+    external::{
+        ImapConfig, NotifierConfig, SolverKind, Telemetry, default_telemetry_endpoint,
+        notification_title,
+    },
+    history, prompt,
+    retry::{RetryClass, RetryPolicy},
+    task::{installed_backend, renewal_due},
 };
 
-pub fn show_status() {
-    if let Some(account) = data::value::get_account() {
-        println!("Current account: {}", account.email);
+pub fn show_status(profile: &str) {
+    let profiles = config::profiles();
+    if !profiles.is_empty() {
+        println!("Profiles: {}", profiles.join(", "));
+    }
+    println!("Profile: {}", profile);
+    if let Some(account) = account(profile) {
+        println!(
+            "Current account: {}{}",
+            account.email,
+            if account_from_env(profile) {
+                format!(" (from {})", EMAIL_VAR)
+            } else {
+                String::new()
+            }
+        );
     } else {
         println!("No account configured");
     }
-    if let Some(webhook) = data::value::get_webhook() {
-        println!("Webhook: {}", webhook);
+    let (notifiers, _) = config::notifiers(profile);
+    if !notifiers.is_empty() {
+        let names: Vec<String> = notifiers.iter().map(ToString::to_string).collect();
+        println!("Notifications: {}", names.join(", "));
+    }
+    if let (Some(imap), _) = config::imap(profile) {
+        println!("IMAP: {}@{} {}", imap.username, imap.host, imap.mailbox);
     }
-    if let Some(ua) = data::value::get_ua() {
+    if let Some(ua) = data::value::get_ua(profile) {
         println!("User-Agent: {}", ua);
     }
-    let timer_enabled = is_auto_enabled();
-    println!(
-        "Auto update: {}",
-        if timer_enabled { "enabled" } else { "disabled" }
-    );
-    let logs = logger::read_logs();
-    if let Some((ts, msg)) = logs.last() {
-        println!("Last run: {} - {}", ts.format("%Y-%m-%d %H:%M:%S"), msg);
+    for server in data::value::get_servers(profile).unwrap_or_default() {
+        match (
+            server.expires,
+            server.expires_at(),
+            server.renewal_opens_at(),
+        ) {
+            (Some(expires), Some(expires_at), Some(opens_at)) => {
+                let now = chrono::Local::now();
+                let remaining = expires_at.signed_duration_since(now);
+                println!(
+                    "VPS {}: expires {} ({}), renewal {}",
+                    server,
+                    expires,
+                    if remaining.num_seconds() > 0 {
+                        format!(
+                            "{}d {}h remaining",
+                            remaining.num_days(),
+                            remaining.num_hours() % 24
+                        )
+                    } else {
+                        "expired".to_string()
+                    },
+                    if now >= opens_at {
+                        "open now".to_string()
+                    } else {
+                        format!(
+                            "opens {}",
+                            opens_at
+                                .with_timezone(&chrono::Local)
+                                .format("%Y-%m-%d %H:%M")
+                        )
+                    }
+                );
+            }
+            _ => println!("VPS {}: expiry unknown", server),
+        }
+    }
+    show_telemetry();
+    for (name, url) in ENDPOINTS.overrides() {
+        println!("{} URL: {} (overridden)", name, url);
+    }
+    if let Some(config) = data::value::get_key_config() {
+        println!("Encryption: {}", config.source);
+    }
+    let backend = installed_backend();
+    match backend {
+        Some(backend) => println!("Auto update: enabled ({})", backend),
+        None => println!("Auto update: disabled"),
+    }
+    if let Some(plan) = data::value::get_timer_plan().filter(|_| backend.is_some()) {
+        println!("Schedule: {}", plan);
+        let now = chrono::Local::now();
+        if let Some(next) = plan.next_after(now) {
+            println!("Next run: {}", next.format("%Y-%m-%d %H:%M"));
+            // Runs before then skip without logging in.
+            let due = renewal_due(profile)
+                .filter(|due| *due > next)
+                .and_then(|due| {
+                    plan.next_after(
+                        due.with_timezone(&chrono::Local) - chrono::Duration::seconds(1),
+                    )
+                });
+            if let Some(due) = due {
+                println!("Next renewal run: {}", due.format("%Y-%m-%d %H:%M"));
+            }
+        }
+    }
+    if let Some(last) = history::list(profile).last() {
+        println!(
+            "Last run: {} - {} {}",
+            last.local_time().format("%Y-%m-%d %H:%M:%S"),
+            last.outcome,
+            last.message
+        );
+    }
+    if let Some(last) = history::last_success(profile) {
+        println!(
+            "Last success: {}",
+            last.local_time().format("%Y-%m-%d %H:%M:%S")
+        );
     }
-    if let Some((ts, _)) = logs.iter().rev().find(|(_, m)| m.starts_with("SUCCESS")) {
-        println!("Last success: {}", ts.format("%Y-%m-%d %H:%M:%S"));
+}
+
+pub fn show_profiles() {
+    let profiles = config::profiles();
+    if profiles.is_empty() {
+        println!("No profiles configured");
+        return;
+    }
+    for profile in profiles {
+        match account(&profile) {
+            Some(account) => println!("{}: {}", profile, account.email),
+            None => println!("{}: (no account)", profile),
+        }
+    }
+}
+
+pub fn clear_data(profile: Option<&str>) {
+    match profile {
+        Some(profile) => {
+            if data::value::remove_profile(profile) {
+                if let Err(e) = data::rotate_data_key() {
+                    println!("Failed to rotate the encryption key: {}", e);
+                }
+                println!("Profile '{}' deleted", profile);
+            } else {
+                println!("Profile '{}' not found", profile);
+            }
+        }
+        None => {
+            remove_all();
+            println!("Saved data deleted");
+        }
     }
 }
 
-pub fn clear_data() {
-    remove_all();
-    println!("Saved data deleted");
+pub fn add_notifier(profile: &str, mut config: NotifierConfig) {
+    match &mut config {
+        NotifierConfig::Email(email) => {
+            if let Some(user) = &email.user {
+                let question = format!("Please enter the SMTP password for {}:", user);
+                let Some(password) = prompt::secret(&question) else {
+                    println!("No SMTP password given");
+                    return;
+                };
+                email.password = Some(password);
+            }
+        }
+        NotifierConfig::Json(json) => {
+            if let Err(e) = json.render("title", "message") {
+                println!("{}", e);
+                return;
+            }
+        }
+        _ => {}
+    }
+    let mut notifiers = data::value::get_notifiers(profile).unwrap_or_default();
+    println!("Added notifier {}: {}", notifiers.len() + 1, config);
+    notifiers.push(config);
+    data::value::set_notifiers(profile, &notifiers);
+    if config::notifiers(profile).1 == Source::File {
+        println!(
+            "Note: config.toml sets the notifiers of profile '{}', so this one is not used",
+            profile
+        );
+    }
 }
 
-pub fn set_webhook(url: &String) {
-    data::value::set_webhook(url);
-    println!("Webhook set");
+pub fn list_notifiers(profile: &str) {
+    let (notifiers, source) = config::notifiers(profile);
+    if notifiers.is_empty() {
+        println!("No notifiers configured");
+    } else if source == Source::File {
+        println!("From config.toml:");
+    }
+    for (i, config) in notifiers.iter().enumerate() {
+        println!("{}. {}", i + 1, config);
+    }
+    println!("Policy: {}", policy_summary(profile));
+}
+
+fn policy_summary(profile: &str) -> String {
+    format!(
+        "{}, attach snapshot: {}",
+        data::value::get_notify_policy(profile).unwrap_or_default(),
+        if data::value::get_notify_snapshot(profile).unwrap_or(false) {
+            "on"
+        } else {
+            "off"
+        }
+    )
+}
+
+pub fn remove_notifier(profile: &str, index: usize) {
+    if config::notifiers(profile).1 == Source::File {
+        println!(
+            "The notifiers of profile '{}' are set in config.toml, edit them with 'xrenew config edit'",
+            profile
+        );
+        return;
+    }
+    let mut notifiers = data::value::get_notifiers(profile).unwrap_or_default();
+    if index == 0 || index > notifiers.len() {
+        println!("Notifier {} not found", index);
+        return;
+    }
+    let removed = notifiers.remove(index - 1);
+    data::value::set_notifiers(profile, &notifiers);
+    println!("Removed notifier {}: {}", index, removed);
+}
+
+pub fn set_notify_policy(profile: &str, args: PolicyArgs) {
+    let mut policy = data::value::get_notify_policy(profile).unwrap_or_default();
+    let changed = args.success.is_some()
+        || args.failure.is_some()
+        || args.failures_after.is_some()
+        || args.skip.is_some()
+        || args.recovery.is_some()
+        || args.expiry_within.is_some()
+        || args.no_expiry;
+    let apply = |target: &mut bool, switch: Option<Switch>| {
+        if let Some(switch) = switch {
+            *target = switch.enabled();
+        }
+    };
+    apply(&mut policy.success, args.success);
+    apply(&mut policy.failure, args.failure);
+    apply(&mut policy.skip, args.skip);
+    apply(&mut policy.recovery, args.recovery);
+    if let Some(n) = args.failures_after {
+        policy.failures_after = n;
+    }
+    if args.no_expiry {
+        policy.expiry_within = None;
+    } else if args.expiry_within.is_some() {
+        policy.expiry_within = args.expiry_within;
+    }
+    if changed {
+        data::value::set_notify_policy(profile, &policy);
+    }
+    if let Some(switch) = args.attach_snapshot {
+        data::value::set_notify_snapshot(profile, &switch.enabled());
+    }
+    println!("Notify policy: {}", policy_summary(profile));
+}
+
+pub async fn test_notifiers(profile: &str, index: Option<usize>) {
+    let (notifiers, _) = config::notifiers(profile);
+    let title = notification_title(profile);
+    let mut found = false;
+    for (i, config) in notifiers.iter().enumerate() {
+        if index.is_some_and(|n| n != i + 1) {
+            continue;
+        }
+        found = true;
+        match config
+            .notifier()
+            .send(&title, "Test notification from xrenew")
+            .await
+        {
+            Ok(()) => println!("{}. {}: OK", i + 1, config),
+            Err(e) => println!("{}. {}: FAILED {}", i + 1, config, e),
+        }
+    }
+    if !found {
+        println!("No notifiers to test");
+    }
 }
 
 pub fn set_two_captcha_key(key: &String) {
     data::value::set_two_captcha_key(key);
     println!("TwoCaptcha API key set");
 }
+
+pub fn set_captcha_key(service: SolverKind, key: &String) {
+    match service {
+        SolverKind::TwoCaptcha => return set_two_captcha_key(key),
+        SolverKind::AntiCaptcha => data::value::set_anti_captcha_key(key),
+        SolverKind::CapSolver => data::value::set_capsolver_key(key),
+        SolverKind::Local | SolverKind::Hosted | SolverKind::Manual => {
+            println!("{} does not use an API key", service);
+            return;
+        }
+    }
+    println!("{} API key set", service);
+}
+
+pub fn set_captcha_order(order: &Vec<SolverKind>) {
+    data::value::set_captcha_solvers(order);
+    println!("Captcha solver order set");
+    config::warn_overridden("captcha.order");
+}
+
+pub fn show_captcha() {
+    let order = config::captcha_order();
+    println!(
+        "Captcha solvers: {}",
+        order
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    );
+    for (service, key) in [
+        (SolverKind::TwoCaptcha, data::value::get_two_captcha_key()),
+        (SolverKind::AntiCaptcha, data::value::get_anti_captcha_key()),
+        (SolverKind::CapSolver, data::value::get_capsolver_key()),
+    ] {
+        println!(
+            "{} API key: {}",
+            service,
+            if key.is_some() { "set" } else { "not set" }
+        );
+    }
+}
+
+pub fn set_imap(profile: &str, config: &ImapConfig) {
+    data::value::set_imap(profile, config);
+    println!("IMAP mailbox set");
+    if config::imap(profile).1 == Source::File {
+        println!(
+            "Note: config.toml sets the IMAP mailbox of profile '{}', so this one is not used",
+            profile
+        );
+    }
+}
+
+pub fn remove_imap(profile: &str) {
+    data::value::remove_imap(profile);
+    println!("IMAP mailbox removed");
+}
+
+pub fn show_imap(profile: &str) {
+    let (imap, source) = config::imap(profile);
+    if source == Source::File {
+        println!("From config.toml:");
+    }
+    match imap {
+        Some(imap) => println!(
+            "IMAP: {}@{}:{} {} (from: {}{})",
+            imap.username,
+            imap.host,
+            imap.port,
+            imap.mailbox,
+            imap.from,
+            if imap.tls { "" } else { ", no TLS" }
+        ),
+        None => println!("IMAP not configured"),
+    }
+}
+
+pub fn set_retry(
+    attempts: Option<u32>,
+    backoff: Option<u64>,
+    max_backoff: Option<u64>,
+    on: Option<Vec<RetryClass>>,
+    reset: bool,
+) {
+    let mut policy = if reset {
+        RetryPolicy::default()
+    } else {
+        data::value::get_retry_policy().unwrap_or_default()
+    };
+    let changed =
+        reset || attempts.is_some() || backoff.is_some() || max_backoff.is_some() || on.is_some();
+    if let Some(attempts) = attempts {
+        policy.max_attempts = attempts;
+    }
+    if let Some(backoff) = backoff {
+        policy.backoff_secs = backoff;
+    }
+    if let Some(max_backoff) = max_backoff {
+        policy.max_backoff_secs = max_backoff;
+    }
+    if let Some(on) = on {
+        policy.retry_on = on;
+    }
+    if changed {
+        data::value::set_retry_policy(&policy);
+    }
+    println!("Retry: {}", config::retry_policy());
+    config::warn_overridden("retry.");
+}
+
+pub fn set_telemetry(enabled: Option<bool>, endpoint: Option<Option<String>>) {
+    let mut telemetry = data::value::get_telemetry().unwrap_or_default();
+    if let Some(enabled) = enabled {
+        telemetry.enabled = enabled;
+    }
+    if let Some(endpoint) = endpoint {
+        telemetry.endpoint = endpoint;
+    }
+    data::value::set_telemetry(&telemetry);
+    show_telemetry();
+    config::warn_overridden("telemetry.");
+}
+
+pub fn show_telemetry() {
+    let (telemetry, _) = config::telemetry();
+    println!(
+        "Telemetry: {} ({})",
+        if telemetry.enabled { "on" } else { "off" },
+        telemetry.endpoint()
+    );
+}
+
+/// Asks once, on the first interactive login, whether logs may be shipped.
+pub fn prompt_telemetry() {
+    if config::telemetry().1 != Source::Default {
+        return;
+    }
+    let question = format!(
+        "Send run logs (results and error messages) to {} to help improve xrenew? (y/N)",
+        default_telemetry_endpoint()
+    );
+    data::value::set_telemetry(&Telemetry {
+        enabled: prompt::confirm(&question),
+        endpoint: None,
+    });
+    println!("You can change this later with 'xrenew telemetry on|off'");
+}
+
+pub fn rekey(new: NewKey) {
+    match data::rekey(new) {
+        Ok(source) => println!("Stored credentials re-encrypted with {}", source),
+        Err(e) => {
+            eprintln!("Rekey failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
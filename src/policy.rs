@@ -0,0 +1,306 @@
+use std::path::Path;
+
+use bincode::{Decode, Encode};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    client::Server,
+    data::value::{get_notify_policy, get_notify_snapshot, get_servers},
+    external::{Attachment, send_notification},
+    history::{self, Outcome, RunRecord},
+};
+
+/// Which runs produce a notification. Evaluated against the run history
+/// after the current run has been recorded.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct NotifyPolicy {
+    pub success: bool,
+    pub failure: bool,
+    /// Failures notify only once this many runs in a row have failed.
+    pub failures_after: u32,
+    pub skip: bool,
+    /// First success after one or more failures, even when `success` is off.
+    pub recovery: bool,
+    /// Warn on every run while a VPS expires within this many hours.
+    pub expiry_within: Option<u32>,
+}
+
+impl Default for NotifyPolicy {
+    fn default() -> Self {
+        NotifyPolicy {
+            success: true,
+            failure: true,
+            failures_after: 1,
+            skip: false,
+            recovery: true,
+            expiry_within: None,
+        }
+    }
+}
+
+impl std::fmt::Display for NotifyPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        write!(
+            f,
+            "success: {}, failure: {} (after {} in a row), skip: {}, recovery: {}, expiry: {}",
+            on_off(self.success),
+            on_off(self.failure),
+            self.failures_after,
+            on_off(self.skip),
+            on_off(self.recovery),
+            match self.expiry_within {
+                Some(hours) => format!("within {}h", hours),
+                None => "off".to_string(),
+            }
+        )
+    }
+}
+
+/// Outcome of a whole run: records written together share a timestamp.
+fn run_outcomes(records: &[RunRecord]) -> Vec<Outcome> {
+    let mut runs: Vec<(DateTime<Utc>, Outcome)> = Vec::new();
+    for record in records {
+        match runs.last_mut() {
+            Some((at, outcome)) if *at == record.at => {
+                if record.outcome == Outcome::Failure {
+                    *outcome = Outcome::Failure;
+                }
+            }
+            _ => runs.push((record.at, record.outcome)),
+        }
+    }
+    runs.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+/// Lines to prepend to the notification of the latest run, or `None` when
+/// the policy keeps it quiet.
+pub fn evaluate(policy: &NotifyPolicy, records: &[RunRecord]) -> Option<Vec<String>> {
+    let mut runs = run_outcomes(records);
+    let current = runs.pop()?;
+    // Skipped runs neither break nor extend a failure streak.
+    let previous: Vec<Outcome> = runs
+        .into_iter()
+        .filter(|o| *o != Outcome::Skipped)
+        .collect();
+    let streak = |outcomes: &[Outcome]| {
+        outcomes
+            .iter()
+            .rev()
+            .take_while(|o| **o == Outcome::Failure)
+            .count() as u32
+    };
+
+    match current {
+        Outcome::Success => {
+            let failures = streak(&previous);
+            if policy.recovery && failures > 0 {
+                Some(vec![format!("Recovered after {} failed run(s)", failures)])
+            } else if policy.success {
+                Some(Vec::new())
+            } else {
+                None
+            }
+        }
+        Outcome::Failure => {
+            let failures = streak(&previous) + 1;
+            if !policy.failure || failures < policy.failures_after {
+                None
+            } else if failures > 1 {
+                Some(vec![format!("Still failing ({} runs in a row)", failures)])
+            } else {
+                Some(Vec::new())
+            }
+        }
+        Outcome::Skipped => policy.skip.then(Vec::new),
+    }
+}
+
+/// VPSs of the profile expiring within `hours`, as notification lines.
+fn expiry_warnings(profile: &str, hours: u32) -> Vec<String> {
+    expiring(&get_servers(profile).unwrap_or_default(), hours, Utc::now())
+}
+
+fn expiring(servers: &[Server], hours: u32, now: DateTime<Utc>) -> Vec<String> {
+    servers
+        .iter()
+        .filter_map(|server| {
+            let left = server.expires_at()?.with_timezone(&Utc) - now;
+            (left.num_hours() < hours as i64).then(|| {
+                if left.num_seconds() > 0 {
+                    format!("{} expires in {}h", server, left.num_hours())
+                } else {
+                    format!("{} has expired", server)
+                }
+            })
+        })
+        .collect()
+}
+
+/// Sends `content` for the run just recorded if the profile's policy asks
+/// for it. A page saved on a layout error is attached when the profile
+/// opted in, and otherwise only its path is mentioned.
+pub async fn notify_run(profile: &str, content: &str, snapshot: Option<&Path>) {
+    let policy = get_notify_policy(profile).unwrap_or_default();
+    let decision = evaluate(&policy, &history::list(profile));
+    let warnings = policy
+        .expiry_within
+        .map(|hours| expiry_warnings(profile, hours))
+        .unwrap_or_default();
+
+    let mut lines = match decision {
+        Some(lines) => lines,
+        None if !warnings.is_empty() => Vec::new(),
+        None => return,
+    };
+    lines.push(content.to_string());
+    lines.extend(warnings);
+
+    let attachment = snapshot
+        .filter(|_| get_notify_snapshot(profile).unwrap_or(false))
+        .and_then(|path| match Attachment::read(path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                println!("Failed to read page snapshot {}: {}", path.display(), e);
+                None
+            }
+        });
+    match (&attachment, snapshot) {
+        (Some(file), _) => lines.push(format!("Unexpected page attached ({})", file.name)),
+        (None, Some(path)) => lines.push(format!("Unexpected page saved to {}", path.display())),
+        (None, None) => {}
+    }
+    send_notification(profile, &lines.join("\n"), attachment.as_ref()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, TimeZone};
+
+    use super::*;
+    use Outcome::{Failure as F, Skipped as K, Success as S};
+
+    /// One record per run, a minute apart, oldest first.
+    fn history(outcomes: &[Outcome]) -> Vec<RunRecord> {
+        let start = Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap();
+        outcomes
+            .iter()
+            .enumerate()
+            .map(|(i, outcome)| RunRecord {
+                at: start + Duration::minutes(i as i64),
+                ..RunRecord::new("default", *outcome, "")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_run_follows_success_and_failure_switches() {
+        let policy = NotifyPolicy::default();
+        assert_eq!(evaluate(&policy, &history(&[S])), Some(vec![]));
+        assert_eq!(evaluate(&policy, &history(&[F])), Some(vec![]));
+        assert_eq!(evaluate(&policy, &[]), None);
+
+        let quiet = NotifyPolicy {
+            success: false,
+            failure: false,
+            ..policy
+        };
+        assert_eq!(evaluate(&quiet, &history(&[S])), None);
+        assert_eq!(evaluate(&quiet, &history(&[F])), None);
+    }
+
+    #[test]
+    fn recovery_after_failures() {
+        let policy = NotifyPolicy {
+            success: false,
+            ..NotifyPolicy::default()
+        };
+        assert_eq!(
+            evaluate(&policy, &history(&[S, F, F, S])),
+            Some(vec!["Recovered after 2 failed run(s)".to_string()])
+        );
+        // Only the first success after the failures is a recovery.
+        assert_eq!(evaluate(&policy, &history(&[F, S, S])), None);
+
+        let off = NotifyPolicy {
+            recovery: false,
+            ..policy
+        };
+        assert_eq!(evaluate(&off, &history(&[F, S])), None);
+    }
+
+    #[test]
+    fn failure_streak() {
+        let policy = NotifyPolicy {
+            failures_after: 3,
+            ..NotifyPolicy::default()
+        };
+        assert_eq!(evaluate(&policy, &history(&[S, F])), None);
+        assert_eq!(evaluate(&policy, &history(&[S, F, F])), None);
+        assert_eq!(
+            evaluate(&policy, &history(&[S, F, F, F])),
+            Some(vec!["Still failing (3 runs in a row)".to_string()])
+        );
+        // A success resets the streak.
+        assert_eq!(evaluate(&policy, &history(&[F, F, S, F])), None);
+    }
+
+    #[test]
+    fn skipped_runs() {
+        let policy = NotifyPolicy::default();
+        assert_eq!(evaluate(&policy, &history(&[S, K])), None);
+        let skip = NotifyPolicy {
+            skip: true,
+            ..policy.clone()
+        };
+        assert_eq!(evaluate(&skip, &history(&[S, K])), Some(vec![]));
+
+        // Skips between failures neither end nor lengthen the streak.
+        assert_eq!(
+            evaluate(&policy, &history(&[F, K, K, F])),
+            Some(vec!["Still failing (2 runs in a row)".to_string()])
+        );
+        assert_eq!(
+            evaluate(&policy, &history(&[F, K, S])),
+            Some(vec!["Recovered after 1 failed run(s)".to_string()])
+        );
+    }
+
+    #[test]
+    fn records_of_one_run_fail_together() {
+        let mut records = history(&[S, S]);
+        let at = records[1].at;
+        records.push(RunRecord {
+            at,
+            ..RunRecord::new("default", F, "")
+        });
+        assert_eq!(run_outcomes(&records), vec![S, F]);
+        let policy = NotifyPolicy {
+            failures_after: 2,
+            ..NotifyPolicy::default()
+        };
+        assert_eq!(evaluate(&policy, &records), None);
+    }
+
+    #[test]
+    fn expiry_within() {
+        let server = |id: &str, expires: Option<(i32, u32, u32)>| Server {
+            id: id.to_string(),
+            name: String::new(),
+            expires: expires.and_then(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+        };
+        let servers = [
+            server("soon", Some((2025, 7, 2))),
+            server("later", Some((2025, 7, 10))),
+            server("gone", Some((2025, 6, 29))),
+            server("unknown", None),
+        ];
+        // 2025-07-02 00:00 JST; "soon" runs until the end of that day.
+        let now = Utc.with_ymd_and_hms(2025, 7, 1, 15, 0, 0).unwrap();
+        assert_eq!(
+            expiring(&servers, 48, now),
+            ["soon expires in 24h", "gone has expired"]
+        );
+        assert_eq!(expiring(&servers, 24, now), ["gone has expired"]);
+    }
+}
@@ -0,0 +1,29 @@
+//! Questions on the terminal. The end of input gives `None` instead of a
+//! panic, so commands run without a terminal fail with a message.
+
+use std::io::{BufRead, IsTerminal};
+
+/// Prints `question` and reads one line, trimmed.
+pub fn line(question: &str) -> Option<String> {
+    println!("{}", question);
+    let mut buf = String::new();
+    match std::io::stdin().lock().read_line(&mut buf) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(buf.trim().to_string()),
+    }
+}
+
+/// Like `line`, but not echoed when reading from a terminal.
+pub fn secret(question: &str) -> Option<String> {
+    if !std::io::stdin().is_terminal() {
+        return line(question);
+    }
+    rpassword::prompt_password(format!("{} ", question))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Asks a yes/no question; anything but `y` is no.
+pub fn confirm(question: &str) -> bool {
+    line(question).is_some_and(|a| a.eq_ignore_ascii_case("y"))
+}
@@ -0,0 +1,308 @@
+use std::{future::Future, time::Duration};
+
+use bincode::{Decode, Encode};
+use tokio::time::sleep;
+
+use crate::error::RunError;
+
+/// Kinds of failure that may go away on their own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, clap::ValueEnum)]
+pub enum RetryClass {
+    /// Connection errors and timeouts
+    Network,
+    /// HTTP 5xx and 429 responses
+    Server,
+    /// Pages that could not be parsed, e.g. during maintenance
+    Layout,
+    /// Rejected or unsolved captchas; the extension is started over
+    Captcha,
+}
+
+impl std::fmt::Display for RetryClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        let value = self.to_possible_value().expect("no skipped variants");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct RetryPolicy {
+    /// Tries per step, including the first one.
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled for each further retry.
+    pub backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff_secs: 30,
+            max_backoff_secs: 300,
+            retry_on: vec![RetryClass::Network, RetryClass::Server, RetryClass::Captcha],
+        }
+    }
+}
+
+impl std::fmt::Display for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let classes: Vec<String> = self.retry_on.iter().map(ToString::to_string).collect();
+        write!(
+            f,
+            "{} attempts, backoff {}s (max {}s), retry on: {}",
+            self.max_attempts,
+            self.backoff_secs,
+            self.max_backoff_secs,
+            if classes.is_empty() {
+                "nothing".to_string()
+            } else {
+                classes.join(", ")
+            }
+        )
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (starting at 1): exponential, capped,
+    /// with jitter between half and the full delay.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        let secs = self
+            .backoff_secs
+            .saturating_mul(factor)
+            .min(self.max_backoff_secs);
+        Duration::from_secs(secs).mul_f64(rand::random_range(0.5..=1.0))
+    }
+
+    /// Wait before attempt `attempt + 1`, or `None` when `error` should be
+    /// given up on.
+    fn delay(
+        &self,
+        attempt: u32,
+        error: &RunError,
+        retryable: impl Fn(RetryClass) -> bool,
+    ) -> Option<Duration> {
+        let class = error.retry_class()?;
+        (attempt < self.max_attempts && retryable(class) && self.retry_on.contains(&class))
+            .then(|| self.backoff(attempt))
+    }
+
+    /// Runs one HTTP step, retrying transient failures. Only steps that can
+    /// safely be repeated belong here: GETs, or a GET together with the
+    /// POST of the form it returned.
+    pub async fn step<T, F, Fut>(&self, step: &str, mut f: F) -> Result<T, RunError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RunError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(e) => match self.delay(attempt, &e, |c| c != RetryClass::Captcha) {
+                    Some(delay) => {
+                        attempt += 1;
+                        self.announce(step, &e, delay, attempt);
+                        sleep(delay).await;
+                    }
+                    None => return Err(e),
+                },
+                res => return res,
+            }
+        }
+    }
+
+    /// Wait before starting an extension over from its form page, or `None`
+    /// when `error` should be given up on. Captcha failures start over, and
+    /// so does any transient failure once the form was `submitted`, as the
+    /// POST itself is not sent twice.
+    pub fn restart_delay(
+        &self,
+        attempt: u32,
+        error: &RunError,
+        submitted: bool,
+    ) -> Option<Duration> {
+        let delay = self.delay(attempt, error, |c| submitted || c == RetryClass::Captcha)?;
+        let what = match error.retry_class() {
+            Some(RetryClass::Captcha) => "Captcha",
+            _ => "Extension",
+        };
+        self.announce(what, error, delay, attempt + 1);
+        Some(delay)
+    }
+
+    fn announce(&self, what: &str, error: &RunError, delay: Duration, attempt: u32) {
+        println!(
+            "{} failed ({}). Retrying in {:.0}s... ({}/{})",
+            what,
+            error,
+            delay.as_secs_f64().ceil(),
+            attempt,
+            self.max_attempts
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::{client::LoginError, error::Step};
+
+    /// Answers each request with the next status in `statuses`, repeating the
+    /// last one. Returns the URL and the request counter.
+    async fn mock_server(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[n.min(statuses.len() - 1)];
+                let mut socket = BufReader::new(socket);
+                let mut line = String::new();
+                while socket.read_line(&mut line).await.unwrap() > 2 {
+                    line.clear();
+                }
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, hits)
+    }
+
+    async fn get(url: &str) -> Result<String, RunError> {
+        let res: Result<String, LoginError> = async {
+            let res = reqwest::get(url).await?.error_for_status()?;
+            Ok(res.text().await?)
+        }
+        .await;
+        res.step("test")
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff_secs: 10,
+            max_backoff_secs: 15,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_server_errors_with_backoff() {
+        let (url, hits) = mock_server(vec![503, 502, 200]).await;
+        let started = Instant::now();
+        let res = policy(3).step("test", || get(&url)).await;
+        assert_eq!(res.unwrap(), "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        // 10s then 20s capped at 15s, each jittered down to at most half.
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(12_500), "{:?}", waited);
+        assert!(waited <= Duration::from_secs(26), "{:?}", waited);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let (url, hits) = mock_server(vec![500]).await;
+        let res = policy(2).step("test", || get(&url)).await;
+        assert!(matches!(res, Err(RunError::HttpStatus { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn does_not_retry_client_errors() {
+        let (url, hits) = mock_server(vec![404, 200]).await;
+        let started = Instant::now();
+        let res = policy(3).step("test", || get(&url)).await;
+        assert_eq!(res.unwrap_err().retry_class(), None);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn respects_disabled_classes() {
+        let (url, hits) = mock_server(vec![503, 200]).await;
+        let policy = RetryPolicy {
+            retry_on: vec![RetryClass::Network],
+            ..policy(3)
+        };
+        assert!(policy.step("test", || get(&url)).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn steps_do_not_retry_captcha_errors() {
+        let hits = AtomicUsize::new(0);
+        let res: Result<(), _> = policy(3)
+            .step("test", || async {
+                hits.fetch_add(1, Ordering::SeqCst);
+                Err(RunError::CaptchaWrong)
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let policy = policy(3);
+        assert!(
+            policy
+                .restart_delay(1, &RunError::CaptchaWrong, false)
+                .is_some()
+        );
+        assert!(
+            policy
+                .restart_delay(3, &RunError::CaptchaWrong, false)
+                .is_none()
+        );
+        assert!(
+            policy
+                .restart_delay(1, &RunError::NoAccount, true)
+                .is_none()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_after_failed_submit() {
+        let (url, hits) = mock_server(vec![503]).await;
+        let error = get(&url).await.unwrap_err();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        let policy = policy(3);
+        // A GET that failed has been retried in place already.
+        assert!(policy.restart_delay(1, &error, false).is_none());
+        assert!(policy.restart_delay(1, &error, true).is_some());
+        assert!(policy.restart_delay(3, &error, true).is_none());
+
+        let policy = RetryPolicy {
+            retry_on: vec![RetryClass::Captcha],
+            ..policy
+        };
+        assert!(policy.restart_delay(1, &error, true).is_none());
+    }
+
+    #[test]
+    fn backoff_is_capped_and_jittered() {
+        let policy = policy(10);
+        for retry in 1..10 {
+            let delay = policy.backoff(retry);
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(15));
+        }
+        assert!(policy.backoff(1) <= Duration::from_secs(10));
+    }
+}
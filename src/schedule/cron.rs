@@ -0,0 +1,337 @@
+//! Five-field cron expressions (`minute hour day month weekday`) with lists,
+//! ranges, steps and month/weekday names, as `crontab(5)` reads them.
+
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, Timelike};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead to look for a match before giving up, enough to reach the
+/// next 29 February.
+const SEARCH_YEARS: i32 = 8;
+
+/// The values of one field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Every value of the field is set.
+    all: bool,
+    /// Written starting with `*`. When neither day field is, a day matching
+    /// either one is a match.
+    star: bool,
+}
+
+impl Field {
+    fn has(self, value: u32) -> bool {
+        self.bits >> value & 1 == 1
+    }
+
+    fn values(self) -> impl Iterator<Item = u32> {
+        (0..64).filter(move |v| self.has(*v))
+    }
+
+    fn list(self) -> String {
+        if self.all {
+            return "*".to_string();
+        }
+        let values: Vec<String> = self.values().map(|v| format!("{:02}", v)).collect();
+        values.join(",")
+    }
+
+    /// `(key, value)` for each value, or a single `None` for any.
+    fn keys(self, key: &'static str) -> Vec<Option<(&'static str, u32)>> {
+        if self.all {
+            vec![None]
+        } else {
+            self.values().map(|v| Some((key, v))).collect()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cron {
+    /// The five fields, with `@daily` style shorthands expanded.
+    expr: String,
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl std::fmt::Display for Cron {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+fn value(s: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+    let value = match names.iter().position(|n| n.eq_ignore_ascii_case(s)) {
+        Some(i) => i as u32 + min,
+        None => s.parse().map_err(|_| format!("'{}' is not a number", s))?,
+    };
+    if !(min..=max).contains(&value) {
+        return Err(format!("{} is out of range {}-{}", value, min, max));
+    }
+    Ok(value)
+}
+
+fn field(s: &str, min: u32, max: u32, names: &[&str]) -> Result<Field, String> {
+    let mut bits = 0;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("'{}' is not a valid step", step)),
+            },
+            None => (part, None),
+        };
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else if let Some((from, to)) = range.split_once('-') {
+            (value(from, min, max, names)?, value(to, min, max, names)?)
+        } else {
+            let from = value(range, min, max, names)?;
+            // `5/15` counts from 5 to the end, as in Vixie cron.
+            (from, if step.is_some() { max } else { from })
+        };
+        if from > to {
+            return Err(format!("'{}' is a backwards range", range));
+        }
+        for v in (from..=to).step_by(step.unwrap_or(1)) {
+            bits |= 1 << v;
+        }
+    }
+    let every = (min..=max).fold(0, |bits, v| bits | 1 << v);
+    Ok(Field {
+        bits,
+        all: bits == every,
+        star: s.starts_with('*'),
+    })
+}
+
+impl Cron {
+    pub fn parse(s: &str) -> Result<Cron, String> {
+        let expr = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            s => s,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+        let named = |name: &str, result: Result<Field, String>| {
+            result.map_err(|e| format!("{} field: {}", name, e))
+        };
+        let mut weekdays = named("weekday", field(weekdays, 0, 7, &WEEKDAYS))?;
+        // 7 is another Sunday.
+        if weekdays.has(7) {
+            weekdays.bits = (weekdays.bits & !(1 << 7)) | 1;
+        }
+        weekdays.all = weekdays.bits == 0x7f;
+        let cron = Cron {
+            expr: fields.join(" "),
+            minutes: named("minute", field(minutes, 0, 59, &[]))?,
+            hours: named("hour", field(hours, 0, 23, &[]))?,
+            days: named("day", field(days, 1, 31, &[]))?,
+            months: named("month", field(months, 1, 12, &MONTHS))?,
+            weekdays,
+        };
+        if cron.next_after(Local::now()).is_none() {
+            return Err(format!("'{}' never matches a date", cron.expr));
+        }
+        Ok(cron)
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days.has(date.day());
+        let weekday = self.weekdays.has(date.weekday().num_days_from_sunday());
+        if self.either_day() {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    fn either_day(&self) -> bool {
+        !self.days.star && !self.weekdays.star
+    }
+
+    /// First match strictly after `after`, skipping times that do not exist
+    /// because of a DST change.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)?;
+        let mut t = start + Duration::minutes(1);
+        while t.year() <= start.year() + SEARCH_YEARS {
+            let date = t.date();
+            if !self.months.has(t.month()) {
+                let first = NaiveDate::from_ymd_opt(t.year(), t.month(), 1)?;
+                t = (first + Months::new(1)).and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(date) {
+                t = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !self.hours.has(t.hour()) {
+                t = date.and_hms_opt(t.hour(), 0, 0)? + Duration::hours(1);
+            } else if !self.minutes.has(t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                match t.and_local_timezone(Local).earliest() {
+                    Some(at) if at > after => return Some(at),
+                    _ => t += Duration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    /// `OnCalendar=` values. systemd requires both day fields to match, so
+    /// a day matching either one takes one line each.
+    pub fn on_calendar(&self) -> Vec<String> {
+        let time = format!("{}:{}:00", self.hours.list(), self.minutes.list());
+        let line = |weekdays: Field, days: Field| {
+            let date = format!("*-{}-{} {}", self.months.list(), days.list(), time);
+            if weekdays.all {
+                return date;
+            }
+            let names: Vec<String> = weekdays
+                .values()
+                .map(|d| {
+                    let name = WEEKDAYS[d as usize];
+                    name[..1].to_uppercase() + &name[1..]
+                })
+                .collect();
+            format!("{} {}", names.join(","), date)
+        };
+        let every_day = Field {
+            bits: 0,
+            all: true,
+            star: true,
+        };
+        if self.either_day() {
+            vec![line(self.weekdays, every_day), line(every_day, self.days)]
+        } else {
+            vec![line(self.weekdays, self.days)]
+        }
+    }
+
+    /// `StartCalendarInterval` entries as `(key, value)` lists; launchd
+    /// takes one entry per combination of values.
+    pub fn calendar_intervals(&self) -> Vec<Vec<(&'static str, u32)>> {
+        let days: Vec<[Option<(&str, u32)>; 2]> = if self.either_day() {
+            let days = self.days.keys("Day").into_iter().map(|d| [d, None]);
+            let weekdays = self.weekdays.keys("Weekday").into_iter();
+            days.chain(weekdays.map(|w| [None, w])).collect()
+        } else {
+            let weekdays = self.weekdays.keys("Weekday");
+            self.days
+                .keys("Day")
+                .into_iter()
+                .flat_map(|d| weekdays.iter().map(move |w| [d, *w]))
+                .collect()
+        };
+        let mut intervals = Vec::new();
+        for month in self.months.keys("Month") {
+            for [day, weekday] in &days {
+                for hour in self.hours.keys("Hour") {
+                    for minute in self.minutes.keys("Minute") {
+                        let keys = [minute, hour, *day, *weekday, month];
+                        intervals.push(keys.into_iter().flatten().collect());
+                    }
+                }
+            }
+        }
+        intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_lists_ranges_steps_and_names() {
+        let cron = Cron::parse("*/15 9-17/4 * jan,Jul mon-fri").unwrap();
+        assert_eq!(cron.minutes.values().collect::<Vec<_>>(), [0, 15, 30, 45]);
+        assert_eq!(cron.hours.values().collect::<Vec<_>>(), [9, 13, 17]);
+        assert_eq!(cron.months.values().collect::<Vec<_>>(), [1, 7]);
+        assert_eq!(cron.weekdays.values().collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert!(cron.days.all && cron.days.star);
+
+        assert_eq!(Cron::parse("0 0 * * 7").unwrap().weekdays.bits, 1);
+        assert_eq!(Cron::parse("@daily").unwrap().to_string(), "0 0 * * *");
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in [
+            "0 9 * *",
+            "60 9 * * *",
+            "0 9 * * mon-sun/0",
+            "0 17-9 * * *",
+            "0 9 0 * *",
+            "0 9 30 feb *",
+        ] {
+            assert!(Cron::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn finds_the_next_match() {
+        let cron = Cron::parse("30 9,21 * * *").unwrap();
+        assert_eq!(cron.next_after(at(7, 10, 8, 0)), Some(at(7, 10, 9, 30)));
+        assert_eq!(cron.next_after(at(7, 10, 9, 30)), Some(at(7, 10, 21, 30)));
+        assert_eq!(cron.next_after(at(7, 10, 22, 0)), Some(at(7, 11, 9, 30)));
+
+        // 2025-07-10 is a Thursday; the 15th or any Monday.
+        let cron = Cron::parse("0 6 15 * mon").unwrap();
+        assert_eq!(cron.next_after(at(7, 10, 8, 0)), Some(at(7, 14, 6, 0)));
+        assert_eq!(cron.next_after(at(7, 14, 6, 0)), Some(at(7, 15, 6, 0)));
+
+        let cron = Cron::parse("0 0 1 jan *").unwrap();
+        assert_eq!(
+            cron.next_after(at(7, 10, 8, 0)),
+            Local.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn renders_for_systemd_and_launchd() {
+        let cron = Cron::parse("5 9,21 * * *").unwrap();
+        assert_eq!(cron.on_calendar(), ["*-*-* 09,21:05:00"]);
+        assert_eq!(
+            cron.calendar_intervals(),
+            [
+                vec![("Minute", 5), ("Hour", 9)],
+                vec![("Minute", 5), ("Hour", 21)]
+            ]
+        );
+
+        let cron = Cron::parse("0 6 1,15 * sat").unwrap();
+        assert_eq!(
+            cron.on_calendar(),
+            ["Sat *-*-* 06:00:00", "*-*-01,15 06:00:00"]
+        );
+        assert_eq!(cron.calendar_intervals().len(), 3);
+        assert!(cron.calendar_intervals().contains(&vec![
+            ("Minute", 0),
+            ("Hour", 6),
+            ("Weekday", 6)
+        ]));
+    }
+}
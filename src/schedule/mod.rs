@@ -0,0 +1,359 @@
+mod cron;
+
+use chrono::{DateTime, Days, Local, NaiveTime, TimeZone, Timelike};
+use rand::Rng;
+
+use crate::client::JST;
+
+pub use cron::Cron;
+
+/// The renewal window opens at midnight JST two days before a VPS expires.
+pub const MAX_LEAD_MINUTES: i64 = 48 * 60;
+
+/// When automatic runs happen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Plan {
+    /// Every day at these local times, sorted.
+    Times(Vec<NaiveTime>),
+    Cron(Cron),
+    /// This long before a VPS expires. Every VPS expires at midnight JST, so
+    /// this is a daily run, and `extend --auto` skips until the earliest
+    /// expiry is that close.
+    BeforeExpiry(chrono::Duration),
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Plan::Times(times) => {
+                let times: Vec<String> = times
+                    .iter()
+                    .map(|t| t.format("%H:%M").to_string())
+                    .collect();
+                write!(f, "{}", times.join(","))
+            }
+            Plan::Cron(cron) => write!(f, "cron {}", cron),
+            Plan::BeforeExpiry(lead) => write!(f, "{} before expiry", format_lead(*lead)),
+        }
+    }
+}
+
+/// Reads what `Display` writes, for the stored setting.
+impl std::str::FromStr for Plan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Plan, String> {
+        if let Some(expr) = s.strip_prefix("cron ") {
+            Cron::parse(expr).map(Plan::Cron)
+        } else if let Some(lead) = s.strip_suffix(" before expiry") {
+            parse_lead(lead).map(Plan::BeforeExpiry)
+        } else {
+            parse_times(s).map(Plan::Times)
+        }
+    }
+}
+
+impl bincode::Encode for Plan {
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> Result<(), bincode::error::EncodeError> {
+        self.to_string().encode(encoder)
+    }
+}
+
+impl<Context> bincode::Decode<Context> for Plan {
+    fn decode<D: bincode::de::Decoder<Context = Context>>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        String::decode(decoder)?
+            .parse()
+            .map_err(bincode::error::DecodeError::OtherString)
+    }
+}
+
+bincode::impl_borrow_decode!(Plan);
+
+pub fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|_| format!("'{}' is not HH:MM", s))
+}
+
+/// `09:00,21:00`, sorted and without duplicates.
+pub fn parse_times(s: &str) -> Result<Vec<NaiveTime>, String> {
+    let mut times = s
+        .split(',')
+        .map(parse_time)
+        .collect::<Result<Vec<_>, _>>()?;
+    times.sort();
+    times.dedup();
+    Ok(times)
+}
+
+/// `20h`, `90m`, `1d12h`; between a minute and the two-day renewal window.
+pub fn parse_lead(s: &str) -> Result<chrono::Duration, String> {
+    let invalid = || format!("'{}' is not a duration like 20h or 1h30m", s);
+    let mut minutes: i64 = 0;
+    let mut number = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 24 * 60,
+            'h' => 60,
+            'm' => 1,
+            _ => return Err(invalid()),
+        };
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        minutes = n
+            .checked_mul(unit)
+            .and_then(|m| m.checked_add(minutes))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || s.trim().is_empty() {
+        return Err(invalid());
+    }
+    if !(1..=MAX_LEAD_MINUTES).contains(&minutes) {
+        return Err(format!(
+            "{} is outside the renewal window, which opens 48h before expiry",
+            s
+        ));
+    }
+    Ok(chrono::Duration::minutes(minutes))
+}
+
+fn format_lead(lead: chrono::Duration) -> String {
+    match (lead.num_hours(), lead.num_minutes() % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
+
+impl Plan {
+    /// Twice a day, 12 hours apart, at a random time so runs of different
+    /// installs are spread out.
+    pub fn random() -> Plan {
+        let mut rng = rand::rng();
+        let hour = rng.random_range(0..12);
+        let minute = rng.random_range(0..60);
+        Plan::Times(
+            [hour, hour + 12]
+                .into_iter()
+                .filter_map(|h| NaiveTime::from_hms_opt(h, minute, 0))
+                .collect(),
+        )
+    }
+
+    /// The plan as local times or cron fields, which the timers understand.
+    fn fixed(&self) -> Fixed<'_> {
+        match self {
+            Plan::Times(times) => Fixed::Times(times.clone()),
+            Plan::Cron(cron) => Fixed::Cron(cron),
+            Plan::BeforeExpiry(lead) => {
+                let today = Local::now().date_naive();
+                let at = today
+                    .and_hms_opt(0, 0, 0)
+                    .and_then(|midnight| JST.from_local_datetime(&midnight).single())
+                    .map(|midnight| (midnight - *lead).with_timezone(&Local).time())
+                    .unwrap_or_default();
+                Fixed::Times(vec![at.with_second(0).unwrap_or(at)])
+            }
+        }
+    }
+
+    /// First run strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self.fixed() {
+            Fixed::Times(times) => next_slot(&times, after),
+            Fixed::Cron(cron) => cron.next_after(after),
+        }
+    }
+
+    /// Longest wait between two runs over the next two months; a plan that
+    /// can wait longer than the renewal window may miss a renewal.
+    pub fn longest_gap(&self, from: DateTime<Local>) -> Option<chrono::Duration> {
+        let end = from + chrono::Duration::days(62);
+        let mut last = self.next_after(from)?;
+        let mut longest = chrono::Duration::zero();
+        // Frequent runs reach the limit long before two months have passed,
+        // but their gaps are short anyway.
+        for _ in 0..5000 {
+            let next = self.next_after(last)?;
+            longest = longest.max(next - last);
+            if next > end {
+                break;
+            }
+            last = next;
+        }
+        Some(longest)
+    }
+
+    /// `OnCalendar=` values for a systemd timer.
+    pub fn on_calendar(&self) -> Vec<String> {
+        match self.fixed() {
+            Fixed::Times(times) => times
+                .iter()
+                .map(|t| format!("*-*-* {}", t.format("%H:%M:00")))
+                .collect(),
+            Fixed::Cron(cron) => cron.on_calendar(),
+        }
+    }
+
+    /// Entries of a launchd `StartCalendarInterval`.
+    pub fn calendar_intervals(&self) -> Vec<Vec<(&'static str, u32)>> {
+        match self.fixed() {
+            Fixed::Times(times) => times
+                .iter()
+                .map(|t| vec![("Minute", t.minute()), ("Hour", t.hour())])
+                .collect(),
+            Fixed::Cron(cron) => cron.calendar_intervals(),
+        }
+    }
+
+    /// The `XRENEW_SCHEDULE_*` variable that selects this plan, for services
+    /// that run `xrenew daemon`.
+    pub fn env_var(&self) -> (&'static str, String) {
+        match self {
+            Plan::Times(_) => ("XRENEW_SCHEDULE_TIMES", self.to_string()),
+            Plan::Cron(cron) => ("XRENEW_SCHEDULE_CRON", cron.to_string()),
+            Plan::BeforeExpiry(lead) => ("XRENEW_SCHEDULE_BEFORE_EXPIRY", format_lead(*lead)),
+        }
+    }
+
+    /// The schedule fields of crontab lines.
+    pub fn crontab(&self) -> Vec<String> {
+        match self.fixed() {
+            Fixed::Times(times) => times
+                .iter()
+                .map(|t| format!("{} {} * * *", t.minute(), t.hour()))
+                .collect(),
+            Fixed::Cron(cron) => vec![cron.to_string()],
+        }
+    }
+}
+
+enum Fixed<'a> {
+    Times(Vec<NaiveTime>),
+    Cron(&'a Cron),
+}
+
+/// When automatic runs happen, from `enable` or the `[schedule]` settings.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    /// Twice a day at a random time when unset.
+    pub plan: Option<Plan>,
+    /// Upper limit of the random delay added to each run, in minutes.
+    pub random_delay: u64,
+}
+
+impl Schedule {
+    pub fn plan_or_random(&self) -> Plan {
+        self.plan.clone().unwrap_or_else(Plan::random)
+    }
+
+    /// A random delay of up to `random_delay` minutes.
+    pub fn jitter(&self) -> chrono::Duration {
+        let secs = rand::rng().random_range(0..=self.random_delay * 60);
+        chrono::Duration::seconds(secs as i64)
+    }
+}
+
+/// First of `times` (sorted) strictly after `after`, in local time.
+fn next_slot(times: &[NaiveTime], after: DateTime<Local>) -> Option<DateTime<Local>> {
+    (0..=2)
+        .filter_map(|d| after.date_naive().checked_add_days(Days::new(d)))
+        .flat_map(|day| times.iter().map(move |t| day.and_time(*t)))
+        .filter_map(|t| t.and_local_timezone(Local).earliest())
+        .find(|t| *t > after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 7, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn next_slot_moves_to_the_next_day() {
+        let times = [time(9, 0), time(21, 30)];
+        assert_eq!(next_slot(&times, at(10, 8, 0)), Some(at(10, 9, 0)));
+        assert_eq!(next_slot(&times, at(10, 9, 0)), Some(at(10, 21, 30)));
+        assert_eq!(next_slot(&times, at(10, 22, 0)), Some(at(11, 9, 0)));
+        assert_eq!(next_slot(&[], at(10, 22, 0)), None);
+    }
+
+    #[test]
+    fn random_times_are_twelve_hours_apart() {
+        let Plan::Times(times) = Plan::random() else {
+            panic!("Expected times");
+        };
+        assert_eq!(times.len(), 2);
+        assert_eq!(times[1] - times[0], chrono::Duration::hours(12));
+    }
+
+    #[test]
+    fn plans_round_trip_through_their_text() {
+        for text in [
+            "09:00,21:30",
+            "cron 0 9 * * mon-fri",
+            "20h before expiry",
+            "1h30m before expiry",
+        ] {
+            let plan: Plan = text.parse().unwrap();
+            assert_eq!(plan.to_string(), text);
+        }
+        assert_eq!(
+            parse_times("21:00, 09:00,21:00").unwrap(),
+            [time(9, 0), time(21, 0)]
+        );
+    }
+
+    #[test]
+    fn lead_stays_within_the_renewal_window() {
+        assert_eq!(parse_lead("20h").unwrap(), chrono::Duration::hours(20));
+        assert_eq!(parse_lead("1d12h").unwrap(), chrono::Duration::hours(36));
+        assert_eq!(parse_lead("90m").unwrap(), chrono::Duration::minutes(90));
+        for text in ["", "20", "h", "0m", "3d", "20x", "1.5h"] {
+            assert!(parse_lead(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn times_render_for_every_scheduler() {
+        let plan = Plan::Times(vec![time(9, 0), time(21, 30)]);
+        assert_eq!(plan.on_calendar(), ["*-*-* 09:00:00", "*-*-* 21:30:00"]);
+        assert_eq!(
+            plan.calendar_intervals(),
+            [
+                vec![("Minute", 0), ("Hour", 9)],
+                vec![("Minute", 30), ("Hour", 21)]
+            ]
+        );
+        assert_eq!(plan.crontab(), ["0 9 * * *", "30 21 * * *"]);
+    }
+
+    #[test]
+    fn sparse_plans_have_long_gaps() {
+        let daily = Plan::Times(vec![time(9, 0)]);
+        assert_eq!(
+            daily.longest_gap(at(10, 0, 0)),
+            Some(chrono::Duration::days(1))
+        );
+        let weekly: Plan = "cron 0 9 * * sun".parse().unwrap();
+        assert_eq!(
+            weekly.longest_gap(at(10, 0, 0)),
+            Some(chrono::Duration::days(7))
+        );
+    }
+}
@@ -0,0 +1,909 @@
+//! Where `enable` registers the automatic runs. The timers (systemd, launchd
+//! and cron) start `extend --auto --all` themselves; OpenRC and runit have no
+//! timers, so they supervise `xrenew daemon` instead.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use bincode::{Decode, Encode};
+
+use crate::{
+    data::SAVE_DIR,
+    schedule::{Plan, Schedule},
+};
+
+const SERVICE: &str = "xrenew.service";
+const TIMER: &str = "xrenew.timer";
+const LAUNCHD_LABEL: &str = "com.xrenew.timer";
+/// Ends every crontab line written by xrenew, so they can be found again.
+const CRON_MARKER: &str = "# xrenew";
+const SYSTEM_UNIT_DIR: &str = "/etc/systemd/system";
+const OPENRC_SCRIPT: &str = "/etc/init.d/xrenew";
+const RUNIT_DIR: &str = "/etc/sv/xrenew";
+const RUNIT_LOG_DIR: &str = "/var/log/xrenew";
+/// Directories watched by `runsvdir` on common distributions.
+const RUNIT_SERVICE_DIRS: [&str; 4] = [
+    "/var/service",
+    "/etc/service",
+    "/etc/runit/runsvdir/default",
+    "/service",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, clap::ValueEnum)]
+pub enum Backend {
+    /// systemd user timer (~/.config/systemd/user)
+    SystemdUser,
+    /// systemd system timer (/etc/systemd/system, needs root)
+    SystemdSystem,
+    /// launchd agent (macOS)
+    Launchd,
+    /// crontab of the current user
+    Crontab,
+    /// OpenRC service running `xrenew daemon` (needs root)
+    Openrc,
+    /// runit service running `xrenew daemon` (needs root)
+    Runit,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        let value = self.to_possible_value().expect("no skipped variants");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TaskError {
+    #[error("{0} is not available: {1}")]
+    Unavailable(Backend, String),
+    #[error("No scheduler found ({0}); choose one with --backend or run 'xrenew daemon'")]
+    NoBackend(String),
+    #[error("Failed to write {path}: {1}{hint}", path = .0.display(), hint = root_hint(.1))]
+    Write(PathBuf, std::io::Error),
+    #[error("Failed to remove {path}: {1}{hint}", path = .0.display(), hint = root_hint(.1))]
+    Remove(PathBuf, std::io::Error),
+    #[error("Failed to read {path}: {1}{hint}", path = .0.display(), hint = root_hint(.1))]
+    Read(PathBuf, std::io::Error),
+    #[error("Failed to run '{0}': {1}")]
+    Spawn(String, std::io::Error),
+    /// The command line, its exit status and what it printed to stderr.
+    #[error("'{0}' failed ({1}){2}")]
+    Command(String, ExitStatus, String),
+}
+
+fn root_hint(e: &std::io::Error) -> &'static str {
+    if e.kind() == std::io::ErrorKind::PermissionDenied {
+        " (run as root, e.g. with sudo)"
+    } else {
+        ""
+    }
+}
+
+/// What to register.
+pub struct Job<'a> {
+    pub exe: &'a Path,
+    pub schedule: &'a Schedule,
+    pub plan: &'a Plan,
+}
+
+/// Finds `program` in `PATH` or the sbin directories, which are often
+/// missing from the `PATH` of normal users.
+fn which(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(["/sbin", "/usr/sbin"].map(PathBuf::from))
+        .map(|dir| dir.join(program))
+        .find(|p| p.is_file())
+}
+
+fn run_with_input(program: &str, args: &[&str], input: Option<&str>) -> Result<String, TaskError> {
+    let command = [program]
+        .iter()
+        .chain(args)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let spawn_error = |e| TaskError::Spawn(command.clone(), e);
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().expect("Piped stdin");
+        stdin.write_all(input.as_bytes()).map_err(spawn_error)?;
+    }
+    let output = child.wait_with_output().map_err(spawn_error)?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let detail = if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", stderr)
+    };
+    Err(TaskError::Command(command, output.status, detail))
+}
+
+/// Runs a command and returns its standard output, or what went wrong.
+fn run(program: &str, args: &[&str]) -> Result<String, TaskError> {
+    run_with_input(program, args, None)
+}
+
+/// Reports a failure that should not stop the rest of a cleanup.
+fn warn(result: Result<String, TaskError>) {
+    if let Err(e) = result {
+        println!("Warning: {}", e);
+    }
+}
+
+fn write(path: &Path, content: &str, executable: bool) -> Result<(), TaskError> {
+    let error = |e| TaskError::Write(path.to_owned(), e);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(error)?;
+    }
+    std::fs::write(path, content).map_err(error)?;
+    #[cfg(unix)]
+    if executable {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).map_err(error)?;
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<(), TaskError> {
+    let result = if path.is_dir() && !path.is_symlink() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match result {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(TaskError::Remove(path.to_owned(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Escapes `%`, which cron turns into a newline.
+fn cron_escape(s: &str) -> String {
+    s.replace('%', r"\%")
+}
+
+/// Escapes `%`, which starts a specifier in systemd units.
+fn systemd_escape(s: &str) -> String {
+    s.replace('%', "%%")
+}
+
+/// A double-quoted `Environment=` assignment, see systemd.exec(5).
+fn systemd_env(var: &str, value: &str) -> String {
+    let value = value.replace('\\', r"\\").replace('"', r#"\""#);
+    format!("Environment=\"{}={}\"", var, systemd_escape(&value))
+}
+
+/// Whether `text` has `exe` as a whole path, not just as the start of a
+/// longer one.
+fn mentions(text: &str, exe: &str) -> bool {
+    text.match_indices(exe).any(|(i, _)| {
+        !text[i + exe.len()..].starts_with(|c: char| c.is_alphanumeric() || "-_./".contains(c))
+    })
+}
+
+/// Whether `text` assigns `var`, as in `VAR=value`, and not a variable
+/// whose name merely ends with it.
+fn assigns(text: &str, var: &str) -> bool {
+    let assignment = format!("{}=", var);
+    text.match_indices(&assignment)
+        .any(|(i, _)| !text[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+fn exe_path(exe: &Path) -> &str {
+    exe.to_str().expect("exe path to str")
+}
+
+fn uid() -> Option<String> {
+    run("id", &["-u"]).ok().map(|s| s.trim().to_string())
+}
+
+fn is_root() -> bool {
+    uid().as_deref() == Some("0")
+}
+
+pub fn user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .ok()
+        .filter(|u| !u.is_empty())
+        .or_else(|| run("id", &["-un"]).ok().map(|s| s.trim().to_string()))
+        .unwrap_or_else(|| "root".to_string())
+}
+
+/// Whether systemd keeps the user's timers running while logged out, or
+/// `None` when `loginctl` cannot tell.
+pub fn lingering() -> Option<bool> {
+    let out = run(
+        "loginctl",
+        &["show-user", &user(), "--property=Linger", "--value"],
+    )
+    .ok()?;
+    match out.trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn home_dir() -> PathBuf {
+    directories::BaseDirs::new()
+        .expect("get base dirs")
+        .home_dir()
+        .to_owned()
+}
+
+fn user_unit_dir() -> PathBuf {
+    directories::BaseDirs::new()
+        .expect("get base dirs")
+        .config_dir()
+        .join("systemd/user")
+}
+
+fn plist_path() -> PathBuf {
+    home_dir().join(format!("Library/LaunchAgents/{}.plist", LAUNCHD_LABEL))
+}
+
+fn runit_service_dir() -> Option<PathBuf> {
+    RUNIT_SERVICE_DIRS
+        .iter()
+        .map(PathBuf::from)
+        .find(|d| d.is_dir())
+}
+
+/// Variables that locate the data and the settings, so a service started by
+/// the init system finds the same ones as this process.
+fn service_env() -> Vec<(&'static str, String)> {
+    ["HOME", "XDG_DATA_HOME", "XDG_CONFIG_HOME", "XRENEW_CONFIG"]
+        .into_iter()
+        .filter_map(|var| Some((var, std::env::var(var).ok()?)))
+        .collect()
+}
+
+/// Plus the schedule, which the daemon reads from the environment.
+fn daemon_env(job: &Job) -> Vec<(&'static str, String)> {
+    let mut env = service_env();
+    env.push(job.plan.env_var());
+    if job.schedule.random_delay > 0 {
+        env.push((
+            "XRENEW_SCHEDULE_RANDOM_DELAY",
+            job.schedule.random_delay.to_string(),
+        ));
+    }
+    env
+}
+
+fn exports(env: &[(&str, String)]) -> String {
+    env.iter()
+        .map(|(var, value)| format!("export {}={}", var, sh_quote(value)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `[Timer]` lines.
+fn systemd_schedule(schedule: &Schedule, plan: &Plan) -> String {
+    let mut lines: Vec<String> = plan
+        .on_calendar()
+        .into_iter()
+        .map(|c| format!("OnCalendar={}", c))
+        .collect();
+    if schedule.random_delay > 0 {
+        lines.push(format!("RandomizedDelaySec={}min", schedule.random_delay));
+    }
+    lines.join("\n")
+}
+
+/// launchd has no random delay, so only the plan is used.
+fn launchd_schedule(plan: &Plan) -> String {
+    let entries: Vec<String> = plan
+        .calendar_intervals()
+        .iter()
+        .map(|keys| {
+            let keys: String = keys
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "            <key>{}</key>\n            <integer>{}</integer>\n",
+                        key, value
+                    )
+                })
+                .collect();
+            format!("        <dict>\n{}        </dict>", keys)
+        })
+        .collect();
+    format!(
+        "    <key>StartCalendarInterval</key>\n    <array>\n{}\n    </array>",
+        entries.join("\n")
+    )
+}
+
+fn crontab_lines() -> Result<Vec<String>, TaskError> {
+    match run("crontab", &["-l"]) {
+        Ok(out) => Ok(out.lines().map(String::from).collect()),
+        Err(TaskError::Command(_, _, detail)) if detail.contains("no crontab") => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_crontab(lines: &[String]) -> Result<(), TaskError> {
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    run_with_input("crontab", &["-"], Some(&content)).map(|_| ())
+}
+
+fn systemctl(scope: &[&str], args: &[&str]) -> Result<String, TaskError> {
+    run("systemctl", &[scope, args].concat())
+}
+
+impl Backend {
+    pub const ALL: [Backend; 6] = [
+        Backend::SystemdUser,
+        Backend::SystemdSystem,
+        Backend::Launchd,
+        Backend::Crontab,
+        Backend::Openrc,
+        Backend::Runit,
+    ];
+
+    /// The first usable backend: launchd on macOS, the system-level ones
+    /// for root and the user's own otherwise.
+    pub fn detect() -> Result<Backend, TaskError> {
+        let order: &[Backend] = if cfg!(target_os = "macos") {
+            &[Backend::Launchd, Backend::Crontab]
+        } else if is_root() {
+            &[
+                Backend::SystemdSystem,
+                Backend::Openrc,
+                Backend::Runit,
+                Backend::Crontab,
+            ]
+        } else {
+            &[Backend::SystemdUser, Backend::Crontab]
+        };
+        let mut reasons = Vec::new();
+        for backend in order {
+            match backend.check() {
+                Ok(()) => return Ok(*backend),
+                Err(e) => reasons.push(format!("{}: {}", backend, e)),
+            }
+        }
+        Err(TaskError::NoBackend(reasons.join("; ")))
+    }
+
+    /// Why the backend cannot be used here, if it cannot.
+    pub fn check(self) -> Result<(), String> {
+        let need = |program: &str| {
+            which(program)
+                .map(|_| ())
+                .ok_or_else(|| format!("{} not found", program))
+        };
+        let need_root = || {
+            if is_root() {
+                Ok(())
+            } else {
+                Err("needs root, run with sudo".to_string())
+            }
+        };
+        match self {
+            Backend::SystemdUser => {
+                need("systemctl")?;
+                systemctl(&["--user"], &["show-environment"])
+                    .map(|_| ())
+                    .map_err(|_| {
+                        "no user systemd session (e.g. after su or sudo); \
+                         try --backend systemd-system or crontab"
+                            .to_string()
+                    })
+            }
+            Backend::SystemdSystem => {
+                need("systemctl")?;
+                if !Path::new("/run/systemd/system").is_dir() {
+                    return Err("systemd is not the init system".to_string());
+                }
+                need_root()
+            }
+            Backend::Launchd => {
+                if !cfg!(target_os = "macos") {
+                    return Err("only on macOS".to_string());
+                }
+                need("launchctl")
+            }
+            Backend::Crontab => need("crontab"),
+            Backend::Openrc => {
+                need("openrc-run")?;
+                need("rc-update")?;
+                need_root()
+            }
+            Backend::Runit => {
+                need("sv")?;
+                need("chpst")?;
+                runit_service_dir().ok_or_else(|| {
+                    format!("no service directory ({})", RUNIT_SERVICE_DIRS.join(", "))
+                })?;
+                need_root()
+            }
+        }
+    }
+
+    /// Files that hold the registration; crontab entries live in the
+    /// crontab instead.
+    pub fn files(self) -> Vec<PathBuf> {
+        match self {
+            Backend::SystemdUser => {
+                vec![user_unit_dir().join(SERVICE), user_unit_dir().join(TIMER)]
+            }
+            Backend::SystemdSystem => {
+                let dir = Path::new(SYSTEM_UNIT_DIR);
+                vec![dir.join(SERVICE), dir.join(TIMER)]
+            }
+            Backend::Launchd => vec![plist_path()],
+            Backend::Crontab => Vec::new(),
+            Backend::Openrc => vec![PathBuf::from(OPENRC_SCRIPT)],
+            Backend::Runit => vec![Path::new(RUNIT_DIR).join("run")],
+        }
+    }
+
+    pub fn is_installed(self) -> bool {
+        match self {
+            Backend::Crontab => {
+                which("crontab").is_some()
+                    && crontab_lines()
+                        .is_ok_and(|lines| lines.iter().any(|l| l.ends_with(CRON_MARKER)))
+            }
+            backend => backend.files().iter().any(|f| f.exists()),
+        }
+    }
+
+    fn systemd_scope(self) -> &'static [&'static str] {
+        if self == Backend::SystemdUser {
+            &["--user"]
+        } else {
+            &[]
+        }
+    }
+
+    fn launchd_target() -> Option<String> {
+        Some(format!("gui/{}/{}", uid()?, LAUNCHD_LABEL))
+    }
+
+    /// Whether the registration starts `exe`, and not e.g. a copy that has
+    /// been moved since `enable`.
+    pub fn runs(self, exe: &Path) -> Result<bool, TaskError> {
+        let exe = exe_path(exe);
+        match self {
+            Backend::Crontab => {
+                let exe = cron_escape(exe);
+                Ok(crontab_lines()?
+                    .iter()
+                    .any(|l| l.ends_with(CRON_MARKER) && mentions(l, &exe)))
+            }
+            backend => {
+                // The service, plist or script comes first.
+                let path = &backend.files()[0];
+                let content =
+                    std::fs::read_to_string(path).map_err(|e| TaskError::Read(path.clone(), e))?;
+                let exe = match backend {
+                    Backend::SystemdUser | Backend::SystemdSystem => systemd_escape(exe),
+                    _ => exe.to_string(),
+                };
+                Ok(mentions(&content, &exe))
+            }
+        }
+    }
+
+    /// Whether the init system has picked up the registration, or its
+    /// state when it has not.
+    pub fn active(self) -> Result<(), String> {
+        match self {
+            Backend::SystemdUser | Backend::SystemdSystem => {
+                let state = systemctl(
+                    self.systemd_scope(),
+                    &["show", TIMER, "--property=ActiveState", "--value"],
+                )
+                .map_err(|e| e.to_string())?;
+                match state.trim() {
+                    "active" => Ok(()),
+                    state => Err(state.to_string()),
+                }
+            }
+            Backend::Launchd => {
+                let target = Backend::launchd_target()
+                    .ok_or_else(|| "failed to get the user id".to_string())?;
+                run("launchctl", &["print", &target])
+                    .map(|_| ())
+                    .map_err(|_| "not loaded".to_string())
+            }
+            Backend::Crontab => {
+                if which("pgrep").is_none() {
+                    return Ok(());
+                }
+                run("pgrep", &["-x", "cron|crond"])
+                    .map(|_| ())
+                    .map_err(|_| "the cron daemon is not running".to_string())
+            }
+            Backend::Openrc => run("rc-service", &["xrenew", "status"])
+                .map(|_| ())
+                .map_err(|_| "stopped".to_string()),
+            Backend::Runit => {
+                let link = runit_service_dir()
+                    .map(|d| d.join("xrenew"))
+                    .filter(|l| l.exists())
+                    .ok_or_else(|| "not in the service directory".to_string())?;
+                let out =
+                    run("sv", &["status", &link.to_string_lossy()]).map_err(|e| e.to_string())?;
+                if out.starts_with("run:") {
+                    Ok(())
+                } else {
+                    Err(out.trim().to_string())
+                }
+            }
+        }
+    }
+
+    /// When the timer fires next, as systemd has it. The other backends do
+    /// not tell.
+    pub fn next_run(self) -> Option<String> {
+        match self {
+            Backend::SystemdUser | Backend::SystemdSystem => {
+                let out = systemctl(
+                    self.systemd_scope(),
+                    &[
+                        "show",
+                        TIMER,
+                        "--property=NextElapseUSecRealtime",
+                        "--value",
+                    ],
+                )
+                .ok()?;
+                Some(out.trim().to_string()).filter(|next| !next.is_empty())
+            }
+            _ => None,
+        }
+    }
+
+    /// How the last run ended as the init system saw it, `Err` when it
+    /// failed. `None` before the first run and for backends that do not
+    /// keep track.
+    pub fn last_exit(self) -> Option<Result<String, String>> {
+        match self {
+            Backend::SystemdUser | Backend::SystemdSystem => {
+                let out = systemctl(
+                    self.systemd_scope(),
+                    &[
+                        "show",
+                        SERVICE,
+                        "--property=Result,ExecMainStatus,ExecMainExitTimestamp",
+                    ],
+                )
+                .ok()?;
+                let property = |name: &str| {
+                    out.lines()
+                        .find_map(|l| l.strip_prefix(name)?.strip_prefix('='))
+                        .unwrap_or_default()
+                };
+                let at = property("ExecMainExitTimestamp");
+                if at.is_empty() {
+                    return None;
+                }
+                let exit = format!("exit status {} at {}", property("ExecMainStatus"), at);
+                match property("Result") {
+                    "success" => Some(Ok(exit)),
+                    result => Some(Err(format!("{} ({})", exit, result))),
+                }
+            }
+            Backend::Launchd => {
+                let out = run("launchctl", &["print", &Backend::launchd_target()?]).ok()?;
+                let code = out
+                    .lines()
+                    .find_map(|l| l.trim().strip_prefix("last exit code = "))?;
+                match code {
+                    "0" => Some(Ok("exit status 0".to_string())),
+                    code if code.starts_with('(') => None,
+                    code => Some(Err(format!("exit status {}", code))),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the timer starts runs with `var` in their environment.
+    /// `None` when that cannot be told: for the backends that supervise
+    /// the daemon, whose own environment counts, and for environment files
+    /// this user cannot read.
+    pub fn passes_env(self, var: &str) -> Option<bool> {
+        match self {
+            Backend::SystemdUser | Backend::SystemdSystem => {
+                let scope = self.systemd_scope();
+                let out = systemctl(
+                    scope,
+                    &["show", SERVICE, "--property=Environment,EnvironmentFiles"],
+                )
+                .ok()?;
+                // The user manager passes its own environment on, e.g. from
+                // `systemctl --user import-environment`.
+                if assigns(&out, var)
+                    || (self == Backend::SystemdUser
+                        && assigns(&systemctl(scope, &["show-environment"]).ok()?, var))
+                {
+                    return Some(true);
+                }
+                for line in out.lines() {
+                    let Some(file) = line.strip_prefix("EnvironmentFiles=") else {
+                        continue;
+                    };
+                    let path = file.split(" (").next().unwrap_or(file);
+                    if assigns(&std::fs::read_to_string(path).ok()?, var) {
+                        return Some(true);
+                    }
+                }
+                Some(false)
+            }
+            Backend::Launchd => {
+                let plist = std::fs::read_to_string(plist_path()).ok()?;
+                Some(plist.contains(&format!("<key>{}</key>", var)))
+            }
+            Backend::Crontab => Some(crontab_lines().ok()?.iter().any(|l| assigns(l, var))),
+            Backend::Openrc | Backend::Runit => None,
+        }
+    }
+
+    /// Where the output of the runs goes.
+    pub fn log(self) -> String {
+        match self {
+            Backend::SystemdUser => format!("journalctl --user -u {}", SERVICE),
+            Backend::SystemdSystem => format!("journalctl -u {}", SERVICE),
+            Backend::Launchd => home_dir()
+                .join(".local/share/xrenew/xrenew.error.log")
+                .display()
+                .to_string(),
+            Backend::Crontab => SAVE_DIR.join("xrenew.log").display().to_string(),
+            Backend::Openrc => SAVE_DIR.join("daemon.log").display().to_string(),
+            Backend::Runit => format!("{}/current", RUNIT_LOG_DIR),
+        }
+    }
+
+    /// Registers `job`, replacing an earlier registration with this
+    /// backend.
+    pub fn install(self, job: &Job) -> Result<(), TaskError> {
+        self.check().map_err(|e| TaskError::Unavailable(self, e))?;
+        let exe = exe_path(job.exe);
+        match self {
+            Backend::SystemdUser => {
+                let service = include_str!("../../systemd/xrenew.service")
+                    .replace("{{EXEC_PATH}}", &systemd_escape(exe));
+                install_systemd(&user_unit_dir(), &["--user"], &service, job)
+            }
+            Backend::SystemdSystem => {
+                let environment: Vec<String> = service_env()
+                    .iter()
+                    .map(|(var, value)| systemd_env(var, value))
+                    .collect();
+                let service = include_str!("../../systemd/xrenew-system.service")
+                    .replace("{{EXEC_PATH}}", &systemd_escape(exe))
+                    .replace("{{USER}}", &user())
+                    .replace("{{ENVIRONMENT}}", &environment.join("\n"));
+                install_systemd(Path::new(SYSTEM_UNIT_DIR), &[], &service, job)
+            }
+            Backend::Launchd => {
+                let home = home_dir();
+                let log_dir = home.join(".local/share/xrenew");
+                let user_bin_path = home.join(".local/bin");
+                std::fs::create_dir_all(&log_dir)
+                    .map_err(|e| TaskError::Write(log_dir.clone(), e))?;
+                let plist = include_str!("../../launchd/com.xrenew.timer.plist")
+                    .replace("{{EXEC_PATH}}", exe)
+                    .replace("{{LOG_PATH}}", &log_dir.to_string_lossy())
+                    .replace("{{USER_BIN_PATH}}", &user_bin_path.to_string_lossy())
+                    .replace("{{SCHEDULE}}", &launchd_schedule(job.plan));
+                let path = plist_path();
+                write(&path, &plist, false)?;
+
+                let uid = uid().ok_or_else(|| {
+                    TaskError::Unavailable(self, "failed to get the user id".to_string())
+                })?;
+                let domain = format!("gui/{}", uid);
+                let path = path.to_string_lossy();
+                // Unloads an earlier version; fails when none is loaded.
+                run("launchctl", &["bootout", &domain, &path]).ok();
+                run("launchctl", &["bootstrap", &domain, &path])?;
+                run(
+                    "launchctl",
+                    &["enable", &format!("{}/{}", domain, LAUNCHD_LABEL)],
+                )?;
+                Ok(())
+            }
+            Backend::Crontab => {
+                let mut lines = crontab_lines()?;
+                lines.retain(|l| !l.ends_with(CRON_MARKER));
+                let exe = sh_quote(exe);
+                let delay = match job.schedule.random_delay {
+                    0 => String::new(),
+                    minutes => format!(
+                        "sleep $(awk 'BEGIN {{ srand(); print int(rand() * {}) }}'); ",
+                        minutes * 60
+                    ),
+                };
+                // `;` rather than `&&`: a failed update, e.g. of an executable
+                // owned by root, must not stop the renewal.
+                let command = format!(
+                    "{{ {}{} update --auto; {} extend --auto --all; }} >> {} 2>&1",
+                    delay,
+                    exe,
+                    exe,
+                    sh_quote(&SAVE_DIR.join("xrenew.log").to_string_lossy())
+                );
+                let command = cron_escape(&command);
+                for fields in job.plan.crontab() {
+                    lines.push(format!("{} {} {}", fields, command, CRON_MARKER));
+                }
+                write_crontab(&lines)
+            }
+            Backend::Openrc => {
+                let script = include_str!("../../openrc/xrenew")
+                    .replace("{{EXEC_PATH}}", exe)
+                    .replace("{{USER}}", &user())
+                    .replace(
+                        "{{LOG_PATH}}",
+                        &SAVE_DIR.join("daemon.log").to_string_lossy(),
+                    )
+                    .replace("{{ENVIRONMENT}}", &exports(&daemon_env(job)));
+                write(Path::new(OPENRC_SCRIPT), &script, true)?;
+                match run("rc-update", &["add", "xrenew", "default"]) {
+                    Err(TaskError::Command(_, _, detail)) if detail.contains("already") => {}
+                    result => {
+                        result?;
+                    }
+                }
+                run("rc-service", &["xrenew", "restart"]).map(|_| ())
+            }
+            Backend::Runit => {
+                let script = include_str!("../../runit/run")
+                    .replace("{{ENVIRONMENT}}", &exports(&daemon_env(job)))
+                    .replace("{{USER}}", &user())
+                    .replace("{{EXEC_PATH}}", &sh_quote(exe));
+                let dir = Path::new(RUNIT_DIR);
+                write(&dir.join("run"), &script, true)?;
+                write(
+                    &dir.join("log/run"),
+                    include_str!("../../runit/log-run"),
+                    true,
+                )?;
+                std::fs::create_dir_all(RUNIT_LOG_DIR)
+                    .map_err(|e| TaskError::Write(RUNIT_LOG_DIR.into(), e))?;
+
+                let service_dir = runit_service_dir().expect("Checked");
+                let link = service_dir.join("xrenew");
+                if link.exists() {
+                    // Already supervised; restarting picks up the new script.
+                    run("sv", &["restart", &link.to_string_lossy()]).map(|_| ())
+                } else {
+                    symlink(dir, &link).map_err(|e| TaskError::Write(link.clone(), e))
+                }
+            }
+        }
+    }
+
+    /// Removes the registration. Failures to stop are reported and the
+    /// files are removed anyway.
+    pub fn uninstall(self) -> Result<(), TaskError> {
+        match self {
+            Backend::SystemdUser => uninstall_systemd(&user_unit_dir(), &["--user"]),
+            Backend::SystemdSystem => uninstall_systemd(Path::new(SYSTEM_UNIT_DIR), &[]),
+            Backend::Launchd => {
+                let path = plist_path();
+                if let Some(uid) = uid() {
+                    warn(run(
+                        "launchctl",
+                        &["bootout", &format!("gui/{}", uid), &path.to_string_lossy()],
+                    ));
+                }
+                remove(&path)
+            }
+            Backend::Crontab => {
+                let mut lines = crontab_lines()?;
+                lines.retain(|l| !l.ends_with(CRON_MARKER));
+                write_crontab(&lines)
+            }
+            Backend::Openrc => {
+                warn(run("rc-service", &["xrenew", "stop"]));
+                warn(run("rc-update", &["del", "xrenew", "default"]));
+                remove(Path::new(OPENRC_SCRIPT))
+            }
+            Backend::Runit => {
+                if let Some(link) = runit_service_dir().map(|d| d.join("xrenew")) {
+                    if link.exists() {
+                        warn(run("sv", &["down", &link.to_string_lossy()]));
+                    }
+                    remove(&link)?;
+                }
+                remove(Path::new(RUNIT_DIR))
+            }
+        }
+    }
+}
+
+fn install_systemd(dir: &Path, scope: &[&str], service: &str, job: &Job) -> Result<(), TaskError> {
+    let timer = include_str!("../../systemd/xrenew.timer")
+        .replace("{{SCHEDULE}}", &systemd_schedule(job.schedule, job.plan));
+    write(&dir.join(SERVICE), service, false)?;
+    write(&dir.join(TIMER), &timer, false)?;
+    systemctl(scope, &["daemon-reload"])?;
+    systemctl(scope, &["enable", TIMER])?;
+    // Restarting applies a changed schedule to a timer that was running.
+    systemctl(scope, &["restart", TIMER])?;
+    Ok(())
+}
+
+fn uninstall_systemd(dir: &Path, scope: &[&str]) -> Result<(), TaskError> {
+    warn(systemctl(scope, &["disable", "--now", TIMER]));
+    remove(&dir.join(SERVICE))?;
+    remove(&dir.join(TIMER))?;
+    warn(systemctl(scope, &["daemon-reload"]));
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_percent_and_quotes() {
+        assert_eq!(
+            systemd_env("HOME", r#"/home/a "b" \c 100%"#),
+            r#"Environment="HOME=/home/a \"b\" \\c 100%%""#
+        );
+        assert_eq!(systemd_escape("/opt/%h/xrenew"), "/opt/%%h/xrenew");
+        assert_eq!(
+            cron_escape(&sh_quote("/opt/50%/xrenew")),
+            r"'/opt/50\%/xrenew'"
+        );
+    }
+
+    #[test]
+    fn finds_whole_paths_and_assignments() {
+        let line = "0 3 * * * { '/usr/bin/xrenew' extend --auto --all; } # xrenew";
+        assert!(mentions(line, "/usr/bin/xrenew"));
+        assert!(!mentions(line, "/usr/bin/xre"));
+        assert!(assigns(
+            "Environment=HOME=/root XRENEW_PASSPHRASE=x",
+            "XRENEW_PASSPHRASE"
+        ));
+        assert!(!assigns("MY_XRENEW_PASSPHRASE=x", "XRENEW_PASSPHRASE"));
+    }
+}
@@ -0,0 +1,206 @@
+mod backend;
+
+use crate::client::{Server, account_profiles};
+use crate::config::{self, schedule};
+use crate::data::value::{
+    get_servers, get_timer_backend, remove_timer_backend, remove_timer_plan, set_schedule,
+    set_timer_backend, set_timer_plan,
+};
+use crate::history::{self, Outcome};
+use crate::schedule::{MAX_LEAD_MINUTES, Plan};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+
+use backend::Job;
+pub use backend::{Backend, TaskError, lingering, user};
+
+pub fn enable_auto(plan: Option<Plan>, backend: Option<Backend>) {
+    if account_profiles().is_empty() {
+        println!("No account configured. Run 'xrenew login' first.");
+        return;
+    }
+    if let Some(plan) = plan {
+        set_schedule(&plan);
+        config::warn_overridden("schedule.plan");
+    }
+    if let Err(e) = _enable_auto(backend) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Registers the runs with `backend`, the one used before or the first
+/// usable one, and removes the registrations with any other backend.
+fn _enable_auto(backend: Option<Backend>) -> Result<(), TaskError> {
+    let backend = match backend.or_else(get_timer_backend) {
+        Some(backend) => backend,
+        None => Backend::detect()?,
+    };
+    let exe = std::env::current_exe().expect("get exe path");
+    let schedule = schedule();
+    let plan = schedule.plan_or_random();
+    let window = chrono::Duration::minutes(MAX_LEAD_MINUTES);
+    if let Some(gap) = plan.longest_gap(Local::now()).filter(|gap| *gap > window) {
+        println!(
+            "Warning: runs can be {}h apart, longer than the 48h renewal window",
+            gap.num_hours()
+        );
+    }
+
+    backend.install(&Job {
+        exe: &exe,
+        schedule: &schedule,
+        plan: &plan,
+    })?;
+    // Only after the new registration worked, so a failed switch keeps the
+    // old one.
+    for old in Backend::ALL {
+        if old != backend && old.is_installed() {
+            old.uninstall()?;
+            println!("Removed the {} registration", old);
+        }
+    }
+    set_timer_backend(&backend);
+    set_timer_plan(&plan);
+    println!("Automatic extension enabled ({})", backend);
+    println!("Schedule: {}", plan);
+    if let Some(next) = plan.next_after(Local::now()) {
+        println!("Next run: {}", next.format("%Y-%m-%d %H:%M"));
+    }
+    if backend == Backend::SystemdUser && lingering() == Some(false) {
+        println!(
+            "Note: lingering is off, so the timer only runs while {} is logged in. \
+             Turn it on with 'loginctl enable-linger'",
+            user()
+        );
+    }
+    Ok(())
+}
+
+pub fn disable_auto() {
+    let installed: Vec<Backend> = Backend::ALL
+        .into_iter()
+        .filter(|b| b.is_installed())
+        .collect();
+    if installed.is_empty() {
+        println!("Automatic extension not configured");
+    }
+    let mut failed = false;
+    for backend in installed {
+        match backend.uninstall() {
+            Ok(()) => println!("Automatic extension disabled ({})", backend),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    remove_timer_backend();
+    remove_timer_plan();
+}
+
+pub fn refresh_auto() {
+    match installed_backend() {
+        Some(backend) => {
+            if let Err(e) = _enable_auto(Some(backend)) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            println!("Automatic extension refreshed");
+        }
+        None => println!("Automatic extension not configured"),
+    }
+}
+
+/// The backend the runs are registered with: the one `enable` used, or
+/// the first one found with a registration, e.g. from an older version.
+pub fn installed_backend() -> Option<Backend> {
+    get_timer_backend()
+        .filter(|b| b.is_installed())
+        .or_else(|| Backend::ALL.into_iter().find(|b| b.is_installed()))
+}
+
+fn lead() -> Option<chrono::Duration> {
+    match schedule().plan {
+        Some(Plan::BeforeExpiry(lead)) => Some(lead),
+        _ => None,
+    }
+}
+
+/// When `server` is due by its stored expiry: once its renewal window
+/// opens, or with `enable --before-expiry` once the expiry is that close.
+fn expiry_due(server: &Server, lead: Option<chrono::Duration>) -> Option<DateTime<FixedOffset>> {
+    let opens = server.renewal_opens_at()?;
+    Some(match lead {
+        Some(lead) => opens.max(server.expires_at()? - lead),
+        None => opens,
+    })
+}
+
+/// When automatic runs for `profile` go ahead: when the earliest VPS is
+/// due by its expiry. `None` when an expiry date is unknown.
+pub fn renewal_due(profile: &str) -> Option<DateTime<FixedOffset>> {
+    let lead = lead();
+    get_servers(profile)
+        .unwrap_or_default()
+        .iter()
+        .map(|s| expiry_due(s, lead))
+        .collect::<Option<Vec<_>>>()
+        .and_then(|v| v.into_iter().min())
+}
+
+/// Returns why an automatic run should be skipped, or `None` to run.
+///
+/// Each VPS selected by `vps` (all when empty) is due by its expiry date,
+/// or when that is unknown, 23h after it was last extended. Runs are
+/// skipped until the earliest one is due. Before the VPSs are known, the
+/// last success of the profile stands in for them.
+pub fn skip_reason(profile: &str, vps: &[String]) -> Option<String> {
+    let mut servers = get_servers(profile).unwrap_or_default();
+    if !vps.is_empty() {
+        servers.retain(|s| vps.contains(&s.id));
+    }
+    if servers.is_empty() {
+        let last = history::last_success(profile)?;
+        return (Utc::now() - last.at < chrono::Duration::hours(23))
+            .then(|| "Skip: last success within 23h".to_string());
+    }
+
+    let lead = lead();
+    let history = history::list(profile);
+    let mut earliest: Option<(DateTime<Utc>, bool)> = None;
+    for server in &servers {
+        let due = match expiry_due(server, lead) {
+            Some(due) => (due.to_utc(), true),
+            None => {
+                // Never extended and expiry unknown: due now.
+                let last = history.iter().rev().find(|r| {
+                    r.outcome == Outcome::Success && r.vps.as_ref() == Some(&server.id)
+                })?;
+                (last.at + chrono::Duration::hours(23), false)
+            }
+        };
+        earliest = Some(earliest.map_or(due, |e| e.min(due)));
+    }
+    let (due, by_expiry) = earliest?;
+    if Utc::now() >= due {
+        return None;
+    }
+    Some(if by_expiry {
+        format!(
+            "Skip: renewal {} at {}",
+            match schedule().plan {
+                Some(Plan::BeforeExpiry(_)) => "scheduled",
+                _ => "opens",
+            },
+            due.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        )
+    } else {
+        format!(
+            "Skip: extended within 23h, next run after {}",
+            due.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        )
+    })
+}
@@ -1,6 +1,6 @@
 pub async fn update(auto: bool) {
     let current = semver::Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
-    let client = reqwest::Client::new();
+    let client = crate::config::http_client();
     let res = client
         .get("https://api.github.com/repos/h-sumiya/xserver-auto-renew-rs/releases/latest")
         .header(reqwest::header::USER_AGENT, "xrenew")